@@ -0,0 +1,142 @@
+//! The `#[msgpack(...)]` attributes on containers, fields and variants
+
+use syn::{Attribute, Error, ExprPath, LitStr, Result};
+
+/// How the variants of an enum are told apart
+pub enum Tagging {
+    /// `{"Variant": content}`, or just `"Variant"` for unit variants
+    External,
+    /// `{"tag": "Variant", ...fields}`
+    Internal(String),
+    /// `{"tag": "Variant", "content": content}`
+    Adjacent(String, String),
+    /// Only the content, tried against every variant in order
+    Untagged,
+}
+
+/// Attributes on the struct or enum itself
+pub struct Container {
+    /// Structs and struct variants as arrays of their fields instead of maps
+    pub array: bool,
+    pub tagging: Tagging,
+}
+
+/// What a missing or skipped field is filled with
+pub enum DefaultValue {
+    Trait,
+    Function(ExprPath),
+}
+
+/// Attributes on a field
+#[derive(Default)]
+pub struct Field {
+    pub rename: Option<String>,
+    pub skip: bool,
+    pub default: Option<DefaultValue>,
+    pub flatten: bool,
+}
+
+/// Attributes on an enum variant
+#[derive(Default)]
+pub struct Variant {
+    pub rename: Option<String>,
+}
+
+fn string (meta: &syn::meta::ParseNestedMeta) -> Result<String> {
+    Ok(meta.value()?.parse::<LitStr>()?.value())
+}
+
+fn msgpack_attributes (attrs: &[Attribute]) -> impl Iterator<Item = &Attribute> {
+    attrs.iter().filter(|attr| attr.path().is_ident("msgpack"))
+}
+
+impl Container {
+    pub fn parse (attrs: &[Attribute], is_enum: bool) -> Result<Container> {
+        let mut array = false;
+        let mut tag = None;
+        let mut content = None;
+        let mut untagged = false;
+
+        for attr in msgpack_attributes(attrs) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("map") {
+                    array = false;
+                } else if meta.path.is_ident("array") {
+                    array = true;
+                } else if meta.path.is_ident("tag") && is_enum {
+                    tag = Some(string(&meta)?);
+                } else if meta.path.is_ident("content") && is_enum {
+                    content = Some(string(&meta)?);
+                } else if meta.path.is_ident("untagged") && is_enum {
+                    untagged = true;
+                } else {
+                    return Err(meta.error("unknown msgpack container attribute"))
+                }
+
+                Ok(())
+            })?;
+        }
+
+        let tagging = match (tag, content, untagged) {
+            (None, None, false) => Tagging::External,
+            (Some(_), None, false) if array => return Err(Error::new_spanned(&attrs[0], "an internal `tag` needs the fields in a map")),
+            (Some(tag), None, false) => Tagging::Internal(tag),
+            (Some(tag), Some(content), false) if tag == content => return Err(Error::new_spanned(&attrs[0], "`tag` and `content` need different keys")),
+            (Some(tag), Some(content), false) => Tagging::Adjacent(tag, content),
+            (None, None, true) => Tagging::Untagged,
+            (None, Some(_), false) => return Err(Error::new_spanned(&attrs[0], "`content` needs a `tag` as well")),
+            _ => return Err(Error::new_spanned(&attrs[0], "`untagged` can't be combined with `tag` or `content`"))
+        };
+
+        Ok(Container { array, tagging })
+    }
+}
+
+impl Field {
+    pub fn parse (attrs: &[Attribute]) -> Result<Field> {
+        let mut field = Field::default();
+
+        for attr in msgpack_attributes(attrs) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    field.rename = Some(string(&meta)?);
+                } else if meta.path.is_ident("skip") {
+                    field.skip = true;
+                } else if meta.path.is_ident("default") {
+                    field.default = match meta.input.is_empty() || meta.input.peek(syn::Token![,]) {
+                        true => Some(DefaultValue::Trait),
+                        false => Some(DefaultValue::Function(meta.value()?.parse::<LitStr>()?.parse()?))
+                    };
+                } else if meta.path.is_ident("flatten") {
+                    field.flatten = true;
+                } else {
+                    return Err(meta.error("unknown msgpack field attribute"))
+                }
+
+                Ok(())
+            })?;
+        }
+
+        Ok(field)
+    }
+}
+
+impl Variant {
+    pub fn parse (attrs: &[Attribute]) -> Result<Variant> {
+        let mut variant = Variant::default();
+
+        for attr in msgpack_attributes(attrs) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    variant.rename = Some(string(&meta)?);
+                } else {
+                    return Err(meta.error("unknown msgpack variant attribute"))
+                }
+
+                Ok(())
+            })?;
+        }
+
+        Ok(variant)
+    }
+}
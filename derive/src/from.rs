@@ -0,0 +1,303 @@
+//! The FromMsgPack derive
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Data, DeriveInput, Error, Result};
+
+use super::attr::{Container, DefaultValue, Field, Tagging, Variant};
+use super::{bounded, string_key, unique_variants, untaken_tag, Fields, Shape};
+
+pub fn derive (input: &DeriveInput) -> Result<TokenStream> {
+    let name = &input.ident;
+    let type_name = name.to_string();
+    let container = Container::parse(&input.attrs, matches!(input.data, Data::Enum(_)))?;
+
+    let body = match &input.data {
+        Data::Struct(data) => {
+            let fields = Fields::parse(&data.fields, container.array)?;
+            decode(&fields, &quote!(#name), container.array, &type_name, quote!(value))
+        },
+        Data::Enum(data) => {
+            unique_variants(&data.variants)?;
+            let mut variants = vec![];
+
+            for variant in &data.variants {
+                let attrs = Variant::parse(&variant.attrs)?;
+                let fields = Fields::parse(&variant.fields, container.array)?;
+                let variant_name = attrs.rename.unwrap_or_else(|| variant.ident.to_string());
+
+                if let Tagging::Internal(_) = container.tagging {
+                    if fields.shape != Shape::Unit && fields.shape != Shape::Named {
+                        return Err(Error::new_spanned(variant, "only unit and struct variants can have an internal tag"))
+                    }
+                }
+
+                untaken_tag(&container.tagging, &fields, variant)?;
+
+                let ident = &variant.ident;
+                variants.push((variant_name, fields, quote!(#name::#ident)));
+            }
+
+            untag(&container, &variants, &type_name)
+        },
+        Data::Union(_) => return Err(Error::new_spanned(input, "unions can't be converted from MsgPack"))
+    };
+
+    let generics = bounded(&input.generics, quote!(::msgpack_simple::FromMsgPack));
+    let (impl_generics, type_generics, where_clause) = generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::msgpack_simple::FromMsgPack for #name #type_generics #where_clause {
+            fn from_msgpack (value: ::msgpack_simple::MsgPack) -> ::std::result::Result<Self, ::msgpack_simple::FromMsgPackError> {
+                #body
+            }
+        }
+    })
+}
+
+fn mismatch (original: TokenStream, attempted: &str) -> TokenStream {
+    quote!(::std::result::Result::Err(::msgpack_simple::FromMsgPackError::from(::msgpack_simple::ConversionError::type_error(#original, #attempted))))
+}
+
+// converts a field that's present, adding its location to errors
+fn convert (value: TokenStream, segment: &TokenStream) -> TokenStream {
+    quote! {
+        ::msgpack_simple::FromMsgPack::from_msgpack(#value).map_err(|error| error.within(#segment))?
+    }
+}
+
+// the value of a skipped field, or of a missing one
+fn missing (field: &Field, segment: &TokenStream) -> TokenStream {
+    match &field.default {
+        Some(DefaultValue::Function(path)) => quote!(#path()),
+        Some(DefaultValue::Trait) => quote!(::std::default::Default::default()),
+        None if field.skip => quote!(::std::default::Default::default()),
+        None => convert(quote!(::msgpack_simple::MsgPack::Nil), segment)
+    }
+}
+
+// an expression that converts the input into the struct or variant at the
+// path, laid out according to the shape of its fields
+fn decode (fields: &Fields, path: &TokenStream, array: bool, attempted: &str, input: TokenStream) -> TokenStream {
+    let construct = fields.construct(path);
+
+    let body = match fields.shape {
+        Shape::Unit => {
+            let mismatch = mismatch(quote!(original), attempted);
+            quote! {
+                match value {
+                    ::msgpack_simple::MsgPack::Nil => ::std::result::Result::Ok(#construct),
+                    original => #mismatch
+                }
+            }
+        },
+        Shape::Newtype => {
+            let binding = &fields.fields[0].binding;
+            quote! {
+                let #binding = ::msgpack_simple::FromMsgPack::from_msgpack(value)?;
+                ::std::result::Result::Ok(#construct)
+            }
+        },
+        Shape::Named if !array => {
+            let mismatch = mismatch(quote!(original), attempted);
+
+            // flattened fields take what's left, so they come last
+            let (flattened, named): (Vec<_>, Vec<_>) = fields.fields.iter().partition(|field| field.attrs.flatten && !field.attrs.skip);
+
+            let named = named.iter().map(|field| {
+                let binding = &field.binding;
+                let key = &field.name;
+                let segment = string_key(key);
+                let segment = quote!(::msgpack_simple::PathSegment::Key(#segment));
+                let missing = missing(&field.attrs, &segment);
+
+                if field.attrs.skip { return quote!(let #binding = #missing;) }
+
+                let found = convert(quote!(value), &segment);
+                quote! {
+                    let #binding = match elements.iter().position(|element| matches!(&element.key, ::msgpack_simple::MsgPack::String(key) if key == #key)) {
+                        ::std::option::Option::Some(index) => {
+                            let value = elements.remove(index).value;
+                            #found
+                        },
+                        ::std::option::Option::None => #missing
+                    };
+                }
+            });
+
+            let flattened = flattened.iter().map(|field| {
+                let binding = &field.binding;
+                quote! {
+                    let #binding = ::msgpack_simple::FromMsgPack::from_msgpack(::msgpack_simple::MsgPack::Map(elements))?;
+                }
+            });
+
+            quote! {
+                #[allow(unused_mut, unused_variables)]
+                let mut elements = match value {
+                    ::msgpack_simple::MsgPack::Map(elements) => elements,
+                    original => return #mismatch
+                };
+
+                #(#named)*
+                #(#flattened)*
+                ::std::result::Result::Ok(#construct)
+            }
+        },
+        Shape::Tuple | Shape::Named => {
+            let mismatch = mismatch(quote!(original), attempted);
+
+            let mut index = 0usize;
+            let items = fields.fields.iter().map(|field| {
+                let binding = &field.binding;
+                let segment = quote!(::msgpack_simple::PathSegment::Index(#index));
+                let missing = missing(&field.attrs, &segment);
+
+                if field.attrs.skip { return quote!(let #binding = #missing;) }
+                index += 1;
+
+                let found = convert(quote!(value), &segment);
+                quote! {
+                    let #binding = match items.next() {
+                        ::std::option::Option::Some(value) => #found,
+                        ::std::option::Option::None => #missing
+                    };
+                }
+            }).collect::<Vec<_>>();
+
+            quote! {
+                #[allow(unused_mut, unused_variables)]
+                let mut items = match value {
+                    ::msgpack_simple::MsgPack::Array(items) => items.into_iter(),
+                    original => return #mismatch
+                };
+
+                #(#items)*
+                ::std::result::Result::Ok(#construct)
+            }
+        }
+    };
+
+    quote! {
+        (|value: ::msgpack_simple::MsgPack| -> ::std::result::Result<Self, ::msgpack_simple::FromMsgPackError> {
+            #body
+        })(#input)
+    }
+}
+
+// an expression that finds the string under a key of the map in `value`
+fn find_string (key: &str) -> TokenStream {
+    quote! {
+        match &value {
+            ::msgpack_simple::MsgPack::Map(elements) => elements.iter().find_map(|element| match (&element.key, &element.value) {
+                (::msgpack_simple::MsgPack::String(key), ::msgpack_simple::MsgPack::String(name)) if key == #key => ::std::option::Option::Some(name.clone()),
+                _ => ::std::option::Option::None
+            }),
+            _ => ::std::option::Option::None
+        }
+    }
+}
+
+// an expression that removes a key from the map in `value` and returns its
+// value, or nil if it's not there
+fn remove (key: &str) -> TokenStream {
+    quote! {
+        match value {
+            ::msgpack_simple::MsgPack::Map(mut elements) => match elements.iter().position(|element| matches!(&element.key, ::msgpack_simple::MsgPack::String(key) if key == #key)) {
+                ::std::option::Option::Some(index) => elements.remove(index).value,
+                ::std::option::Option::None => ::msgpack_simple::MsgPack::Nil
+            },
+            _ => ::msgpack_simple::MsgPack::Nil
+        }
+    }
+}
+
+// finds the variant according to the tagging of the enum and converts its
+// contents
+fn untag (container: &Container, variants: &[(String, Fields, TokenStream)], type_name: &str) -> TokenStream {
+    let mismatch = mismatch(quote!(value), type_name);
+
+    if let Tagging::Untagged = container.tagging {
+        let attempts = variants.iter().map(|(name, fields, path)| {
+            let attempted = format!("{}::{}", type_name, name);
+            let decoded = decode(fields, path, container.array, &attempted, quote!(value.clone()));
+            quote! {
+                if let ::std::result::Result::Ok(result) = #decoded {
+                    return ::std::result::Result::Ok(result)
+                }
+            }
+        });
+
+        return quote! {
+            #(#attempts)*
+            #mismatch
+        }
+    }
+
+    let name = match &container.tagging {
+        Tagging::Internal(tag) | Tagging::Adjacent(tag, _) => find_string(tag),
+        _ => quote! {
+            match &value {
+                ::msgpack_simple::MsgPack::String(name) => ::std::option::Option::Some(name.clone()),
+                ::msgpack_simple::MsgPack::Map(elements) if elements.len() == 1 => match &elements[0].key {
+                    ::msgpack_simple::MsgPack::String(name) => ::std::option::Option::Some(name.clone()),
+                    _ => ::std::option::Option::None
+                },
+                _ => ::std::option::Option::None
+            }
+        }
+    };
+
+    let arms = variants.iter().map(|(name, fields, path)| {
+        let attempted = format!("{}::{}", type_name, name);
+
+        if fields.shape == Shape::Unit {
+            return quote!(::std::option::Option::Some(#name) => ::std::result::Result::Ok(#path))
+        }
+
+        let (content, segment) = match &container.tagging {
+            Tagging::Internal(tag) => {
+                // the fields are next to the tag, which no field should take
+                let content = quote! {
+                    match value {
+                        ::msgpack_simple::MsgPack::Map(mut elements) => {
+                            elements.retain(|element| !matches!(&element.key, ::msgpack_simple::MsgPack::String(key) if key == #tag));
+                            ::msgpack_simple::MsgPack::Map(elements)
+                        },
+                        other => other
+                    }
+                };
+                (content, None)
+            },
+            Tagging::Adjacent(_, content) => (remove(content), Some(content.as_str())),
+            _ => {
+                let content = quote! {
+                    match value {
+                        ::msgpack_simple::MsgPack::Map(mut elements) => elements.remove(0).value,
+                        _ => ::msgpack_simple::MsgPack::Nil
+                    }
+                };
+                (content, Some(name.as_str()))
+            }
+        };
+
+        let decoded = decode(fields, path, container.array, &attempted, content);
+        let decoded = match segment {
+            Some(segment) => {
+                let segment = string_key(segment);
+                quote!(#decoded.map_err(|error| error.within(::msgpack_simple::PathSegment::Key(#segment))))
+            },
+            None => decoded
+        };
+
+        quote!(::std::option::Option::Some(#name) => #decoded)
+    });
+
+    quote! {
+        let name: ::std::option::Option<::std::string::String> = #name;
+        match name.as_deref() {
+            #(#arms,)*
+            _ => #mismatch
+        }
+    }
+}
@@ -0,0 +1,297 @@
+//! Derive macros for the `ToMsgPack` and `FromMsgPack` traits of
+//! [msgpack_simple](https://docs.rs/msgpack_simple), usually used through its
+//! `derive` feature.
+//! 
+//! Structs with named fields become maps keyed by the field names, tuple
+//! structs become arrays, newtype structs become the value they wrap, and unit
+//! structs become nil. Enums become the name of the variant if it's a unit
+//! variant, and otherwise a map from the name to the contents, laid out like
+//! the matching struct.
+//! 
+//!     use msgpack_simple::{msgpack, MsgPack, FromMsgPack, ToMsgPack};
+//! 
+//!     #[derive(ToMsgPack, FromMsgPack, Debug, PartialEq)]
+//!     struct Login {
+//!         user: String,
+//!         #[msgpack(rename = "tries")]
+//!         attempts: u8,
+//!         #[msgpack(skip)]
+//!         cached: Option<u64>,
+//!     }
+//! 
+//!     #[derive(ToMsgPack, FromMsgPack, Debug, PartialEq)]
+//!     enum Event {
+//!         Logout,
+//!         Login(Login),
+//!         Move { x: i32, y: i32 },
+//!     }
+//! 
+//!     let event = Event::Login(Login { user: "ferris".to_string(), attempts: 3, cached: Some(1) });
+//!     let value = event.to_msgpack();
+//!     assert_eq!(value, msgpack!({ "Login": { "user": "ferris", "tries": (MsgPack::Uint(3)) } }));
+//! 
+//!     let decoded = Event::from_msgpack(value).unwrap();
+//!     assert_eq!(decoded, Event::Login(Login { user: "ferris".to_string(), attempts: 3, cached: None }));
+//!     assert_eq!(Event::Logout.to_msgpack(), msgpack!("Logout"));
+//! 
+//! # Attributes
+//! 
+//! On structs and enums:
+//! 
+//! - `#[msgpack(array)]` writes structs, or the struct variants of an enum, as
+//!   arrays of their fields in order instead of maps. This is more compact,
+//!   but the order of the fields becomes part of the format. Missing trailing
+//!   fields are read as nil, extra ones are ignored. `#[msgpack(map)]` is the
+//!   default.
+//! - `#[msgpack(tag = "type")]` on an enum puts the name of the variant in the
+//!   map of the fields, under the given key: `{"type": "Move", "x": 1, "y": 2}`.
+//!   Only unit and struct variants can be tagged this way, and their fields
+//!   can't use the tag as their key.
+//! - `#[msgpack(tag = "type", content = "data")]` on an enum writes a map with
+//!   the name under one key and the contents under the other:
+//!   `{"type": "Move", "data": {"x": 1, "y": 2}}`. The two keys have to
+//!   differ.
+//! - `#[msgpack(untagged)]` on an enum writes only the contents, and reading
+//!   tries every variant in order and picks the first one that fits.
+//! 
+//! On fields:
+//! 
+//! - `#[msgpack(rename = "name")]` uses a different key in the map. Two
+//!   fields can't end up with the same key.
+//! - `#[msgpack(skip)]` leaves the field out, and fills it with its `Default`
+//!   when reading.
+//! - `#[msgpack(default)]` fills the field with its `Default` if it's missing,
+//!   `#[msgpack(default = "path")]` calls the given function instead. Without
+//!   it, missing fields are read as nil, so only `Option`s can be left out.
+//! - `#[msgpack(flatten)]` merges the map of the field into the map of the
+//!   struct. When reading, the field gets every entry that isn't taken by
+//!   another field. Only one field of a struct can be flattened. When
+//!   writing, a flattened field that doesn't convert to a map, such as a
+//!   `None`, adds no entries.
+//! 
+//! On enum variants:
+//! 
+//! - `#[msgpack(rename = "name")]` uses a different name for the variant. Two
+//!   variants can't end up with the same name.
+
+extern crate proc_macro;
+
+mod attr;
+mod from;
+mod to;
+
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, parse_quote, DeriveInput, Error, Generics, Ident, Member, Result};
+
+/// Derives `ToMsgPack`, see the [crate docs](index.html) for the layout and
+/// the attributes
+#[proc_macro_derive(ToMsgPack, attributes(msgpack))]
+pub fn derive_to_msgpack (input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    to::derive(&input).unwrap_or_else(Error::into_compile_error).into()
+}
+
+/// Derives `FromMsgPack`, see the [crate docs](index.html) for the layout and
+/// the attributes
+#[proc_macro_derive(FromMsgPack, attributes(msgpack))]
+pub fn derive_from_msgpack (input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    from::derive(&input).unwrap_or_else(Error::into_compile_error).into()
+}
+
+/// The kinds of structs and variants, which decide how their fields are laid
+/// out
+#[derive(Clone, Copy, PartialEq)]
+enum Shape {
+    Unit,
+    Newtype,
+    Tuple,
+    Named,
+}
+
+struct Field {
+    member: Member,
+    // the variable the field is bound to in generated code
+    binding: Ident,
+    // the key of the field in maps
+    name: String,
+    attrs: attr::Field,
+}
+
+struct Fields {
+    shape: Shape,
+    fields: Vec<Field>,
+}
+
+impl Fields {
+    fn parse (fields: &syn::Fields, array: bool) -> Result<Fields> {
+        let mut result = vec![];
+
+        for (index, field) in fields.iter().enumerate() {
+            let attrs = attr::Field::parse(&field.attrs)?;
+
+            let (member, name) = match &field.ident {
+                Some(ident) => (Member::Named(ident.clone()), attrs.rename.clone().unwrap_or_else(|| unraw(ident))),
+                None => (Member::Unnamed(index.into()), index.to_string())
+            };
+
+            if attrs.flatten && (field.ident.is_none() || array) {
+                return Err(Error::new_spanned(field, "only fields of structs written as maps can be flattened"))
+            }
+
+            // a second flattened field would only get the same entries again
+            if attrs.flatten && !attrs.skip && result.iter().any(|other: &Field| other.attrs.flatten && !other.attrs.skip) {
+                return Err(Error::new_spanned(field, "only one field can be flattened"))
+            }
+
+            let keyed = |field: &attr::Field| !array && !field.skip && !field.flatten;
+            if field.ident.is_some() && keyed(&attrs) && result.iter().any(|other| keyed(&other.attrs) && other.name == name) {
+                return Err(Error::new_spanned(field, format!("duplicate key \"{}\"", name)))
+            }
+
+            result.push(Field { member, binding: format_ident!("__field{}", index), name, attrs });
+        }
+
+        let shape = match fields {
+            syn::Fields::Unit => Shape::Unit,
+            syn::Fields::Unnamed(_) if result.len() == 1 && !result[0].attrs.skip => Shape::Newtype,
+            syn::Fields::Unnamed(_) => Shape::Tuple,
+            syn::Fields::Named(_) => Shape::Named,
+        };
+
+        Ok(Fields { shape, fields: result })
+    }
+
+    /// A pattern that binds every field that isn't skipped, such as
+    /// `Enum::Variant { x: __field0, y: _ }`
+    fn pattern (&self, path: &TokenStream) -> TokenStream {
+        let bindings = self.fields.iter().map(|field| match field.attrs.skip {
+            true => quote!(_),
+            false => {
+                let binding = &field.binding;
+                quote!(#binding)
+            }
+        });
+
+        match self.shape {
+            Shape::Unit => quote!(#path),
+            Shape::Newtype | Shape::Tuple => quote!(#path(#(#bindings),*)),
+            Shape::Named => {
+                let members = self.fields.iter().map(|field| &field.member);
+                quote!(#path { #(#members: #bindings),* })
+            }
+        }
+    }
+
+    /// An expression that builds the value from the bindings
+    fn construct (&self, path: &TokenStream) -> TokenStream {
+        let bindings = self.fields.iter().map(|field| &field.binding);
+
+        match self.shape {
+            Shape::Unit => quote!(#path),
+            Shape::Newtype | Shape::Tuple => quote!(#path(#(#bindings),*)),
+            Shape::Named => {
+                let members = self.fields.iter().map(|field| &field.member);
+                quote!(#path { #(#members: #bindings),* })
+            }
+        }
+    }
+}
+
+// the name of an identifier without the `r#` of raw identifiers
+fn unraw (ident: &Ident) -> String {
+    let name = ident.to_string();
+    name.strip_prefix("r#").map(str::to_string).unwrap_or(name)
+}
+
+// the generics with a bound added to every type parameter
+fn bounded (generics: &Generics, bound: TokenStream) -> Generics {
+    let mut generics = generics.clone();
+    for param in generics.type_params_mut() {
+        param.bounds.push(parse_quote!(#bound));
+    }
+    generics
+}
+
+fn string_key (name: &str) -> TokenStream {
+    quote!(::msgpack_simple::MsgPack::String(::std::string::String::from(#name)))
+}
+
+// checks that no field of a struct variant is written under the key of the
+// internal tag
+fn untaken_tag (tagging: &attr::Tagging, fields: &Fields, variant: &syn::Variant) -> Result<()> {
+    let tag = match tagging {
+        attr::Tagging::Internal(tag) if fields.shape == Shape::Named => tag,
+        _ => return Ok(())
+    };
+
+    for (field, input) in fields.fields.iter().zip(&variant.fields) {
+        if !field.attrs.skip && !field.attrs.flatten && &field.name == tag {
+            return Err(Error::new_spanned(input, format!("duplicate key \"{}\", it is the tag of the enum", tag)))
+        }
+    }
+
+    Ok(())
+}
+
+// checks that no two variants of an enum end up with the same name
+fn unique_variants (variants: &syn::punctuated::Punctuated<syn::Variant, syn::token::Comma>) -> Result<()> {
+    let mut names: Vec<String> = vec![];
+
+    for variant in variants {
+        let name = attr::Variant::parse(&variant.attrs)?.rename.unwrap_or_else(|| variant.ident.to_string());
+        if names.contains(&name) {
+            return Err(Error::new_spanned(variant, format!("duplicate variant name \"{}\"", name)))
+        }
+        names.push(name);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error (input: DeriveInput) -> String {
+        let to = to::derive(&input).err().map(|error| error.to_string());
+        let from = from::derive(&input).err().map(|error| error.to_string());
+        assert_eq!(to, from);
+        to.unwrap_or_default()
+    }
+
+    #[test]
+    fn invalid_layouts () {
+        assert_eq!(error(parse_quote! {
+            struct Twice { #[msgpack(flatten)] a: A, #[msgpack(flatten)] b: B }
+        }), "only one field can be flattened");
+
+        assert_eq!(error(parse_quote! {
+            struct Renamed { id: u8, #[msgpack(rename = "id")] key: u8 }
+        }), "duplicate key \"id\"");
+
+        assert_eq!(error(parse_quote! {
+            enum Named { A, #[msgpack(rename = "A")] B(u8) }
+        }), "duplicate variant name \"A\"");
+
+        assert_eq!(error(parse_quote! {
+            #[msgpack(tag = "type")]
+            enum Tagged { A { #[msgpack(rename = "type")] kind: String } }
+        }), "duplicate key \"type\", it is the tag of the enum");
+
+        assert_eq!(error(parse_quote! {
+            #[msgpack(tag = "type", content = "type")]
+            enum Adjacent { A(u8) }
+        }), "`tag` and `content` need different keys");
+
+        // keys only have to be unique where they are written
+        assert_eq!(error(parse_quote! {
+            struct Skipped { id: u8, #[msgpack(rename = "id", skip)] key: u8, #[msgpack(flatten)] a: A, #[msgpack(flatten, skip)] b: B }
+        }), "");
+        assert_eq!(error(parse_quote! {
+            #[msgpack(array)]
+            struct Array { id: u8, #[msgpack(rename = "id")] key: u8 }
+        }), "");
+    }
+}
@@ -0,0 +1,152 @@
+//! The ToMsgPack derive
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Data, DeriveInput, Error, Result};
+
+use super::attr::{Container, Tagging, Variant};
+use super::{bounded, string_key, unique_variants, untaken_tag, Fields, Shape};
+
+pub fn derive (input: &DeriveInput) -> Result<TokenStream> {
+    let name = &input.ident;
+    let container = Container::parse(&input.attrs, matches!(input.data, Data::Enum(_)))?;
+
+    let body = match &input.data {
+        Data::Struct(data) => {
+            let fields = Fields::parse(&data.fields, container.array)?;
+            let pattern = fields.pattern(&quote!(#name));
+            let content = encode(&fields, container.array);
+
+            quote! {
+                let #pattern = self;
+                #content
+            }
+        },
+        Data::Enum(data) => {
+            unique_variants(&data.variants)?;
+            let mut arms = vec![];
+
+            for variant in &data.variants {
+                let attrs = Variant::parse(&variant.attrs)?;
+                let fields = Fields::parse(&variant.fields, container.array)?;
+                untaken_tag(&container.tagging, &fields, variant)?;
+                let variant_name = attrs.rename.unwrap_or_else(|| variant.ident.to_string());
+
+                let ident = &variant.ident;
+                let pattern = fields.pattern(&quote!(#name::#ident));
+                let value = tag(&container.tagging, &variant_name, &fields, encode(&fields, container.array), variant)?;
+
+                arms.push(quote!(#pattern => #value));
+            }
+
+            quote! {
+                match self {
+                    #(#arms,)*
+                }
+            }
+        },
+        Data::Union(_) => return Err(Error::new_spanned(input, "unions can't be converted to MsgPack"))
+    };
+
+    let generics = bounded(&input.generics, quote!(::msgpack_simple::ToMsgPack));
+    let (impl_generics, type_generics, where_clause) = generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::msgpack_simple::ToMsgPack for #name #type_generics #where_clause {
+            fn to_msgpack (&self) -> ::msgpack_simple::MsgPack {
+                #body
+            }
+        }
+    })
+}
+
+// an expression with the fields laid out according to their shape, with the
+// fields bound to their bindings as references
+fn encode (fields: &Fields, array: bool) -> TokenStream {
+    let written: Vec<_> = fields.fields.iter().filter(|field| !field.attrs.skip).collect();
+
+    match fields.shape {
+        Shape::Unit => quote!(::msgpack_simple::MsgPack::Nil),
+        Shape::Newtype => {
+            let binding = &written[0].binding;
+            quote!(::msgpack_simple::ToMsgPack::to_msgpack(#binding))
+        },
+        Shape::Named if !array => {
+            let pushes = written.iter().map(|field| {
+                let binding = &field.binding;
+
+                if field.attrs.flatten {
+                    return quote! {
+                        if let ::msgpack_simple::MsgPack::Map(inner) = ::msgpack_simple::ToMsgPack::to_msgpack(#binding) {
+                            elements.extend(inner);
+                        }
+                    }
+                }
+
+                let key = string_key(&field.name);
+                quote! {
+                    elements.push(::msgpack_simple::MapElement { key: #key, value: ::msgpack_simple::ToMsgPack::to_msgpack(#binding) });
+                }
+            });
+
+            quote! {{
+                #[allow(unused_mut)]
+                let mut elements = ::std::vec::Vec::new();
+                #(#pushes)*
+                ::msgpack_simple::MsgPack::Map(elements)
+            }}
+        },
+        Shape::Tuple | Shape::Named => {
+            let bindings = written.iter().map(|field| &field.binding);
+            quote!(::msgpack_simple::MsgPack::Array(::std::vec![#(::msgpack_simple::ToMsgPack::to_msgpack(#bindings)),*]))
+        }
+    }
+}
+
+// wraps the contents of a variant according to the tagging of the enum
+fn tag (tagging: &Tagging, name: &str, fields: &Fields, content: TokenStream, variant: &syn::Variant) -> Result<TokenStream> {
+    let name_value = string_key(name);
+    let unit = fields.shape == Shape::Unit;
+
+    let entry = |key: &str, value: &TokenStream| {
+        let key = string_key(key);
+        quote!(::msgpack_simple::MapElement { key: #key, value: #value })
+    };
+
+    let value = match tagging {
+        Tagging::External if unit => name_value,
+        Tagging::External => {
+            let entry = entry(name, &content);
+            quote!(::msgpack_simple::MsgPack::Map(::std::vec![#entry]))
+        },
+        Tagging::Internal(tag) => {
+            let tag_entry = entry(tag, &name_value);
+            match fields.shape {
+                Shape::Unit => quote!(::msgpack_simple::MsgPack::Map(::std::vec![#tag_entry])),
+                Shape::Named => quote! {
+                    match #content {
+                        ::msgpack_simple::MsgPack::Map(mut elements) => {
+                            elements.insert(0, #tag_entry);
+                            ::msgpack_simple::MsgPack::Map(elements)
+                        },
+                        other => other
+                    }
+                },
+                _ => return Err(Error::new_spanned(variant, "only unit and struct variants can have an internal tag"))
+            }
+        },
+        Tagging::Adjacent(tag, content_key) => {
+            let tag_entry = entry(tag, &name_value);
+            match unit {
+                true => quote!(::msgpack_simple::MsgPack::Map(::std::vec![#tag_entry])),
+                false => {
+                    let content_entry = entry(content_key, &content);
+                    quote!(::msgpack_simple::MsgPack::Map(::std::vec![#tag_entry, #content_entry]))
+                }
+            }
+        },
+        Tagging::Untagged => content
+    };
+
+    Ok(value)
+}
@@ -0,0 +1,177 @@
+use std::collections::BTreeMap;
+
+use msgpack_simple::{msgpack, MsgPack, FromMsgPack, ToMsgPack, Path};
+
+#[derive(ToMsgPack, FromMsgPack, Debug, PartialEq, Default)]
+struct Header {
+    id: u32,
+    #[msgpack(rename = "v")]
+    version: u8,
+}
+
+fn default_retries () -> u8 {
+    3
+}
+
+#[derive(ToMsgPack, FromMsgPack, Debug, PartialEq)]
+struct Request<T> {
+    #[msgpack(flatten)]
+    header: Header,
+    body: T,
+    #[msgpack(default = "default_retries")]
+    retries: u8,
+    #[msgpack(default)]
+    tags: Vec<String>,
+    #[msgpack(skip)]
+    cache: Option<String>,
+}
+
+#[derive(ToMsgPack, FromMsgPack, Debug, PartialEq)]
+struct Envelope {
+    kind: String,
+    #[msgpack(flatten)]
+    rest: BTreeMap<String, MsgPack>,
+}
+
+#[derive(ToMsgPack)]
+struct Annotated {
+    kind: String,
+    #[msgpack(flatten)]
+    notes: Option<BTreeMap<String, String>>,
+}
+
+#[derive(ToMsgPack, FromMsgPack, Debug, PartialEq)]
+#[msgpack(array)]
+struct Point(i32, i32);
+
+#[derive(ToMsgPack, FromMsgPack, Debug, PartialEq)]
+#[msgpack(array)]
+struct Compact {
+    name: String,
+    #[msgpack(skip)]
+    ignored: bool,
+    point: Point,
+    label: Option<String>,
+}
+
+#[derive(ToMsgPack, FromMsgPack, Debug, PartialEq)]
+struct Meters(f64);
+
+#[derive(ToMsgPack, FromMsgPack, Debug, PartialEq)]
+enum External {
+    Stop,
+    Go(Meters),
+    Turn(i8, i8),
+    #[msgpack(rename = "jump")]
+    Jump { r#height: u8 },
+}
+
+#[derive(ToMsgPack, FromMsgPack, Debug, PartialEq)]
+#[msgpack(tag = "type")]
+enum Internal {
+    Ping,
+    Move { x: i32, y: i32 },
+}
+
+#[derive(ToMsgPack, FromMsgPack, Debug, PartialEq)]
+#[msgpack(tag = "t", content = "c")]
+enum Adjacent {
+    Empty,
+    Value(u8),
+    Pair(u8, u8),
+}
+
+#[derive(ToMsgPack, FromMsgPack, Debug, PartialEq)]
+#[msgpack(untagged)]
+enum Untagged {
+    Number(u8),
+    Text(String),
+    Point { x: i32, y: i32 },
+    Nothing,
+}
+
+fn round_trip <T: ToMsgPack + FromMsgPack + PartialEq + std::fmt::Debug> (value: T, expected: MsgPack) {
+    assert_eq!(value.to_msgpack(), expected);
+    assert_eq!(T::from_msgpack(expected).unwrap(), value);
+}
+
+#[test]
+fn structs () {
+    let request = Request { header: Header { id: 7, version: 1 }, body: vec![1u8], retries: 0, tags: vec![], cache: None };
+    round_trip(request, msgpack!({
+        "id": (MsgPack::Uint(7)),
+        "v": (MsgPack::Uint(1)),
+        "body": [(MsgPack::Uint(1))],
+        "retries": (MsgPack::Uint(0)),
+        "tags": []
+    }));
+
+    let decoded = Request::<String>::from_msgpack(msgpack!({ "id": 1, "v": 2, "body": "hi", "cache": "x" })).unwrap();
+    assert_eq!((decoded.retries, decoded.tags, decoded.cache), (3, vec![], None));
+
+    let mut rest = BTreeMap::new();
+    rest.insert("trace".to_string(), msgpack!(true));
+    round_trip(Envelope { kind: "log".to_string(), rest }, msgpack!({ "kind": "log", "trace": true }));
+
+    // a flattened field that isn't a map adds nothing
+    assert_eq!(Annotated { kind: "log".to_string(), notes: None }.to_msgpack(), msgpack!({ "kind": "log" }));
+    let notes = Some(vec![("by".to_string(), "ferris".to_string())].into_iter().collect());
+    assert_eq!(Annotated { kind: "log".to_string(), notes }.to_msgpack(), msgpack!({ "kind": "log", "by": "ferris" }));
+
+    round_trip(Point(1, -2), msgpack!([1, -2]));
+    round_trip(Meters(1.5), msgpack!(1.5));
+    round_trip(
+        Compact { name: "a".to_string(), ignored: false, point: Point(0, 0), label: None },
+        msgpack!(["a", [0, 0], nil])
+    );
+
+    // missing trailing fields are nil, extra ones are ignored
+    let compact = Compact::from_msgpack(msgpack!(["b", [1, 1]])).unwrap();
+    assert_eq!(compact.label, None);
+    assert!(Compact::from_msgpack(msgpack!(["b", [1, 1], "c", 4])).is_ok());
+}
+
+#[test]
+fn enums () {
+    round_trip(External::Stop, msgpack!("Stop"));
+    round_trip(External::Go(Meters(2.0)), msgpack!({ "Go": 2.0 }));
+    round_trip(External::Turn(1, -1), msgpack!({ "Turn": [1, -1] }));
+    round_trip(External::Jump { height: 3 }, msgpack!({ "jump": { "height": (MsgPack::Uint(3)) } }));
+
+    round_trip(Internal::Ping, msgpack!({ "type": "Ping" }));
+    round_trip(Internal::Move { x: 1, y: 2 }, msgpack!({ "type": "Move", "x": 1, "y": 2 }));
+
+    round_trip(Adjacent::Empty, msgpack!({ "t": "Empty" }));
+    round_trip(Adjacent::Value(5), msgpack!({ "t": "Value", "c": (MsgPack::Uint(5)) }));
+    round_trip(Adjacent::Pair(1, 2), msgpack!({ "t": "Pair", "c": [(MsgPack::Uint(1)), (MsgPack::Uint(2))] }));
+
+    round_trip(Untagged::Number(5), MsgPack::Uint(5));
+    round_trip(Untagged::Text("five".to_string()), msgpack!("five"));
+    round_trip(Untagged::Point { x: 1, y: 2 }, msgpack!({ "x": 1, "y": 2 }));
+    round_trip(Untagged::Nothing, MsgPack::Nil);
+}
+
+#[test]
+fn errors () {
+    let error = Request::<Vec<Point>>::from_msgpack(msgpack!({ "id": 1, "v": 2, "body": [[1, 2], [3, "4"]] })).unwrap_err();
+    assert_eq!(error.path, Path::root().key("body").index(1).index(1));
+    assert_eq!(error.to_string(), "MsgPack conversion error at /body/1/1: cannot use string as i32");
+
+    let error = Request::<u8>::from_msgpack(msgpack!({ "id": 1, "body": 2 })).unwrap_err();
+    assert_eq!(error.path, Path::root().key("v"));
+    assert_eq!(error.error.original, MsgPack::Nil);
+
+    let error = External::from_msgpack(msgpack!({ "Turn": [1, 300] })).unwrap_err();
+    assert_eq!(error.path, Path::root().key("Turn").index(1));
+
+    let error = External::from_msgpack(msgpack!("Fly")).unwrap_err();
+    assert_eq!(error.error.attempted, "External");
+    assert!(error.path.is_root());
+
+    let error = Adjacent::from_msgpack(msgpack!({ "t": "Value", "c": -1 })).unwrap_err();
+    assert_eq!(error.path, Path::root().key("c"));
+
+    assert!(Internal::from_msgpack(msgpack!({ "type": "Move", "x": 1 })).is_err());
+    assert!(Untagged::from_msgpack(msgpack!(1.5)).is_err());
+    assert!(Point::from_msgpack(msgpack!({ "0": 1 })).is_err());
+}
@@ -0,0 +1,263 @@
+//! Equality, ordering and hashing for MsgPack values
+
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+
+use super::MsgPack;
+
+// type tags for hashing by meaning, where ints and uints share one so equal
+// numbers in both variants hash the same, and integral floats can join them
+pub(crate) const NIL: u8 = 0;
+pub(crate) const BOOLEAN: u8 = 1;
+pub(crate) const INTEGER: u8 = 2;
+pub(crate) const FLOAT: u8 = 3;
+pub(crate) const STRING: u8 = 4;
+pub(crate) const BINARY: u8 = 5;
+pub(crate) const ARRAY: u8 = 6;
+pub(crate) const MAP: u8 = 7;
+pub(crate) const EXTENSION: u8 = 8;
+
+// bits of a float used for hashing, with every NaN and both zeroes collapsed
+// into one value, matching how they compare
+pub(crate) fn float_bits (value: f64) -> u64 {
+    if value.is_nan() { return f64::NAN.to_bits() }
+    if value == 0.0 { return 0 }
+    value.to_bits()
+}
+
+// floats with NaN equal to itself and above everything else
+pub(crate) fn compare_floats (a: f64, b: f64) -> Ordering {
+    match (a.is_nan(), b.is_nan()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Greater,
+        (false, true) => Ordering::Less,
+        (false, false) => a.partial_cmp(&b).unwrap(),
+    }
+}
+
+// an integer against a float, exactly, without rounding the integer
+pub(crate) fn compare_int_float (int: i128, float: f64) -> Ordering {
+    if float.is_nan() { return Ordering::Less }
+    // i128 covers both i64 and u64, anything outside of it is out of reach
+    if float >= 2f64.powi(127) { return Ordering::Less }
+    if float < -(2f64.powi(127)) { return Ordering::Greater }
+
+    let whole = float.trunc();
+    match int.cmp(&(whole as i128)) {
+        Ordering::Equal => compare_floats(whole, float),
+        ordering => ordering,
+    }
+}
+
+// position of each variant in the cross-type order, numbers share one
+fn type_rank (value: &MsgPack) -> u8 {
+    match value {
+        MsgPack::Nil => 0,
+        MsgPack::Boolean(_) => 1,
+        MsgPack::Int(_) | MsgPack::Uint(_) | MsgPack::Float(_) => 2,
+        MsgPack::String(_) => 3,
+        MsgPack::Binary(_) => 4,
+        MsgPack::Array(_) => 5,
+        MsgPack::Map(_) => 6,
+        MsgPack::Extension(_) => 7,
+    }
+}
+
+// numbers that are equal in value are ordered by variant
+fn number_rank (value: &MsgPack) -> u8 {
+    match value {
+        MsgPack::Int(_) => 0,
+        MsgPack::Uint(_) => 1,
+        _ => 2,
+    }
+}
+
+fn compare_numbers (a: &MsgPack, b: &MsgPack) -> Ordering {
+    let ordering = match (a, b) {
+        (MsgPack::Int(a), MsgPack::Int(b)) => a.cmp(b),
+        (MsgPack::Uint(a), MsgPack::Uint(b)) => a.cmp(b),
+        (MsgPack::Float(a), MsgPack::Float(b)) => compare_floats(*a, *b),
+        (MsgPack::Int(a), MsgPack::Uint(b)) => (*a as i128).cmp(&(*b as i128)),
+        (MsgPack::Uint(a), MsgPack::Int(b)) => (*a as i128).cmp(&(*b as i128)),
+        (MsgPack::Int(a), MsgPack::Float(b)) => compare_int_float(*a as i128, *b),
+        (MsgPack::Uint(a), MsgPack::Float(b)) => compare_int_float(*a as i128, *b),
+        (MsgPack::Float(a), MsgPack::Int(b)) => compare_int_float(*b as i128, *a).reverse(),
+        (MsgPack::Float(a), MsgPack::Uint(b)) => compare_int_float(*b as i128, *a).reverse(),
+        _ => unreachable!(),
+    };
+
+    ordering.then_with(|| number_rank(a).cmp(&number_rank(b)))
+}
+
+/// MsgPack values have a total order, so they can be sorted, and used as keys
+/// in a `BTreeMap`.
+/// 
+/// Values of different types are ordered as nil, booleans, numbers, strings,
+/// binaries, arrays, maps and extensions. Numbers are ordered by their value,
+/// no matter if they are ints, uints or floats, and if the values are equal,
+/// ints come before uints and uints before floats. Strings and binaries are
+/// ordered byte by byte, arrays and maps element by element in their stored
+/// order, and extensions by type first, then by value.
+/// 
+/// Floats follow the usual order, except that NaN is equal to itself and
+/// greater than every other number. Negative and positive zero are equal.
+/// 
+///     use msgpack_simple::MsgPack;
+/// 
+///     let mut values = vec![
+///         MsgPack::String("a".to_string()),
+///         MsgPack::Float(f64::NAN),
+///         MsgPack::Uint(3),
+///         MsgPack::Float(2.5),
+///         MsgPack::Int(-1),
+///         MsgPack::Nil,
+///         MsgPack::Int(3),
+///     ];
+///     values.sort();
+/// 
+///     assert_eq!(values, vec![
+///         MsgPack::Nil,
+///         MsgPack::Int(-1),
+///         MsgPack::Float(2.5),
+///         MsgPack::Int(3),
+///         MsgPack::Uint(3),
+///         MsgPack::Float(f64::NAN),
+///         MsgPack::String("a".to_string()),
+///     ]);
+impl Ord for MsgPack {
+    fn cmp (&self, other: &MsgPack) -> Ordering {
+        let ordering = type_rank(self).cmp(&type_rank(other));
+        if ordering != Ordering::Equal { return ordering }
+
+        match (self, other) {
+            (MsgPack::Nil, MsgPack::Nil) => Ordering::Equal,
+            (MsgPack::Boolean(a), MsgPack::Boolean(b)) => a.cmp(b),
+            (MsgPack::String(a), MsgPack::String(b)) => a.cmp(b),
+            (MsgPack::Binary(a), MsgPack::Binary(b)) => a.cmp(b),
+            (MsgPack::Array(a), MsgPack::Array(b)) => a.cmp(b),
+            (MsgPack::Map(a), MsgPack::Map(b)) => a.cmp(b),
+            (MsgPack::Extension(a), MsgPack::Extension(b)) => a.cmp(b),
+            (a, b) => compare_numbers(a, b),
+        }
+    }
+}
+
+impl PartialOrd for MsgPack {
+    fn partial_cmp (&self, other: &MsgPack) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Two MsgPack values are equal if they are the same variant with the same
+/// value, so `Int(5)` and `Uint(5)` are not equal. Floats are compared as
+/// described for `Ord`, so NaN is equal to itself, and `-0.0` to `0.0`.
+/// 
+///     use msgpack_simple::MsgPack;
+/// 
+///     assert_eq!(MsgPack::Float(f64::NAN), MsgPack::Float(f64::NAN));
+///     assert_eq!(MsgPack::Float(-0.0), MsgPack::Float(0.0));
+///     assert_ne!(MsgPack::Int(5), MsgPack::Uint(5));
+impl PartialEq for MsgPack {
+    fn eq (&self, other: &MsgPack) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for MsgPack {}
+
+/// Hashing is consistent with equality, so MsgPack values can be used as keys
+/// in a `HashMap` or stored in a `HashSet`.
+/// 
+///     use std::collections::HashSet;
+///     use msgpack_simple::MsgPack;
+/// 
+///     let mut set = HashSet::new();
+///     set.insert(MsgPack::Float(0.0));
+///     set.insert(MsgPack::Float(-0.0));
+///     set.insert(MsgPack::Int(0));
+/// 
+///     assert_eq!(set.len(), 2);
+impl Hash for MsgPack {
+    fn hash <H: Hasher> (&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+
+        match self {
+            MsgPack::Nil => {},
+            MsgPack::Boolean(value) => value.hash(state),
+            MsgPack::Int(value) => value.hash(state),
+            MsgPack::Uint(value) => value.hash(state),
+            MsgPack::Float(value) => float_bits(*value).hash(state),
+            MsgPack::String(value) => value.hash(state),
+            MsgPack::Binary(value) => value.hash(state),
+            MsgPack::Array(value) => value.hash(state),
+            MsgPack::Map(value) => value.hash(state),
+            MsgPack::Extension(value) => value.hash(state),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::hash_map::DefaultHasher;
+
+    use super::super::{MapElement, Extension};
+
+    fn hash (value: &MsgPack) -> u64 {
+        let mut state = DefaultHasher::new();
+        value.hash(&mut state);
+        state.finish()
+    }
+
+    #[test]
+    fn numbers () {
+        assert!(MsgPack::Int(-1) < MsgPack::Uint(0));
+        assert!(MsgPack::Uint(u64::MAX) > MsgPack::Int(i64::MAX));
+        assert!(MsgPack::Int(i64::MAX) < MsgPack::Float(9223372036854775808.0));
+        assert!(MsgPack::Uint(u64::MAX) < MsgPack::Float(18446744073709551616.0));
+        assert!(MsgPack::Int(2) > MsgPack::Float(1.5));
+        assert!(MsgPack::Int(-2) < MsgPack::Float(-1.5));
+        assert!(MsgPack::Int(-1) > MsgPack::Float(-1.5));
+        assert!(MsgPack::Float(1.0) > MsgPack::Uint(1));
+        assert!(MsgPack::Float(f64::INFINITY) < MsgPack::Float(f64::NAN));
+        assert!(MsgPack::Float(f64::NEG_INFINITY) < MsgPack::Int(i64::MIN));
+        assert!(MsgPack::Float(1e300) > MsgPack::Uint(u64::MAX));
+        assert_eq!(MsgPack::Float(-f64::NAN).cmp(&MsgPack::Float(f64::NAN)), Ordering::Equal);
+    }
+
+    #[test]
+    fn types () {
+        let ordered = vec![
+            MsgPack::Nil,
+            MsgPack::Boolean(false),
+            MsgPack::Boolean(true),
+            MsgPack::Float(f64::NEG_INFINITY),
+            MsgPack::Float(f64::NAN),
+            MsgPack::String("".to_string()),
+            MsgPack::String("a".to_string()),
+            MsgPack::Binary(vec![]),
+            MsgPack::Array(vec![]),
+            MsgPack::Array(vec![MsgPack::Nil]),
+            MsgPack::Map(vec![MapElement { key: MsgPack::Nil, value: MsgPack::Nil }]),
+            MsgPack::Extension(Extension { type_id: -1, value: vec![2] }),
+            MsgPack::Extension(Extension { type_id: 1, value: vec![1] }),
+        ];
+
+        for (index, value) in ordered.iter().enumerate() {
+            for other in &ordered[index + 1..] {
+                assert!(value < other, "{} should be less than {}", value, other);
+            }
+        }
+    }
+
+    #[test]
+    fn hashing () {
+        assert_eq!(hash(&MsgPack::Float(0.0)), hash(&MsgPack::Float(-0.0)));
+        assert_eq!(hash(&MsgPack::Float(f64::NAN)), hash(&MsgPack::Float(-f64::NAN)));
+        assert_ne!(hash(&MsgPack::Int(1)), hash(&MsgPack::Uint(1)));
+
+        let nested = MsgPack::Array(vec![MsgPack::Float(-0.0)]);
+        assert_eq!(nested, MsgPack::Array(vec![MsgPack::Float(0.0)]));
+        assert_eq!(hash(&nested), hash(&MsgPack::Array(vec![MsgPack::Float(0.0)])));
+    }
+}
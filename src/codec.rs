@@ -0,0 +1,537 @@
+//! Typed extension values, decoded and encoded by registered codecs
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt::{self, Display, Formatter};
+
+use super::{MsgPack, Extension};
+use super::error::{ConversionError, ParseError};
+use super::parser;
+use super::decimal::Decimal;
+use super::timestamp::Timestamp;
+use super::uuid::Uuid;
+
+/// Converts between the bytes of an extension type and a Rust value
+/// 
+/// Codecs are collected in an [ExtensionRegistry](struct.ExtensionRegistry.html),
+/// which uses them to decode extensions into typed values, encode them back,
+/// check extensions while parsing, and display them.
+/// 
+///     use msgpack_simple::ExtensionCodec;
+/// 
+///     // a 2D point as two big-endian i16 coordinates, in extension type 2
+///     #[derive(Debug, PartialEq)]
+///     struct Point { x: i16, y: i16 }
+/// 
+///     struct PointCodec;
+/// 
+///     impl ExtensionCodec for PointCodec {
+///         type Value = Point;
+/// 
+///         fn type_id (&self) -> i8 { 2 }
+///         fn name (&self) -> &'static str { "point" }
+/// 
+///         fn encode (&self, point: &Point) -> Vec<u8> {
+///             [point.x.to_be_bytes(), point.y.to_be_bytes()].concat()
+///         }
+/// 
+///         fn decode (&self, bytes: &[u8]) -> Option<Point> {
+///             if bytes.len() != 4 { return None }
+///             Some(Point {
+///                 x: i16::from_be_bytes([bytes[0], bytes[1]]),
+///                 y: i16::from_be_bytes([bytes[2], bytes[3]]),
+///             })
+///         }
+/// 
+///         fn format (&self, point: &Point, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+///             write!(f, "point({}, {})", point.x, point.y)
+///         }
+///     }
+/// 
+///     assert_eq!(PointCodec.decode(&[0, 1, 0xff, 0xff]), Some(Point { x: 1, y: -1 }));
+pub trait ExtensionCodec {
+    /// The Rust type the extension decodes into
+    type Value: 'static;
+
+    /// The extension type id handled by the codec
+    fn type_id (&self) -> i8;
+
+    /// Name of the value type, used in conversion errors
+    fn name (&self) -> &'static str {
+        "extension value"
+    }
+
+    /// Converts a value to the bytes of the extension
+    fn encode (&self, value: &Self::Value) -> Vec<u8>;
+
+    /// Converts the bytes of the extension to a value, returning `None` if
+    /// they are not valid
+    fn decode (&self, bytes: &[u8]) -> Option<Self::Value>;
+
+    /// Writes the value in a human-readable form. Defaults to the same
+    /// `ext:type:hex` form MsgPack uses for unknown extensions.
+    fn format (&self, value: &Self::Value, f: &mut Formatter) -> fmt::Result {
+        write!(f, "ext:{}:{}", self.type_id(), hex::encode(self.encode(value)))
+    }
+}
+
+/// The codec for the timestamp extension type -1, see
+/// [Timestamp](struct.Timestamp.html)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TimestampCodec;
+
+impl ExtensionCodec for TimestampCodec {
+    type Value = Timestamp;
+
+    fn type_id (&self) -> i8 {
+        Timestamp::TYPE_ID
+    }
+
+    fn name (&self) -> &'static str {
+        "timestamp"
+    }
+
+    fn encode (&self, value: &Timestamp) -> Vec<u8> {
+        Extension::from(*value).value
+    }
+
+    fn decode (&self, bytes: &[u8]) -> Option<Timestamp> {
+        Timestamp::try_from(Extension { type_id: Timestamp::TYPE_ID, value: bytes.to_vec() }).ok()
+    }
+
+    fn format (&self, value: &Timestamp, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", value)
+    }
+}
+
+/// The codec for [UUIDs](struct.Uuid.html), in type 2 by default
+/// 
+/// The type id Tarantool uses is not reserved by the spec, so the codec is
+/// not in a new registry and has to be registered, with any other type id if
+/// needed.
+/// 
+///     use msgpack_simple::{ExtensionRegistry, Uuid, UuidCodec};
+/// 
+///     let mut registry = ExtensionRegistry::new();
+///     registry.register(UuidCodec::new(40));
+/// 
+///     let uuid: Uuid = "00112233-4455-6677-8899-aabbccddeeff".parse().unwrap();
+///     let encoded = registry.encode(&uuid).unwrap();
+/// 
+///     assert_eq!(encoded.clone().as_extension().unwrap().type_id, 40);
+///     assert_eq!(registry.display(&encoded).to_string(), "00112233-4455-6677-8899-aabbccddeeff");
+#[derive(Debug, Clone, Copy)]
+pub struct UuidCodec {
+    type_id: i8,
+}
+
+impl UuidCodec {
+    /// Creates the codec for a custom type id
+    pub fn new (type_id: i8) -> UuidCodec {
+        UuidCodec { type_id }
+    }
+}
+
+impl Default for UuidCodec {
+    fn default () -> UuidCodec {
+        UuidCodec { type_id: Uuid::TYPE_ID }
+    }
+}
+
+impl ExtensionCodec for UuidCodec {
+    type Value = Uuid;
+
+    fn type_id (&self) -> i8 {
+        self.type_id
+    }
+
+    fn name (&self) -> &'static str {
+        "uuid"
+    }
+
+    fn encode (&self, value: &Uuid) -> Vec<u8> {
+        value.as_bytes().to_vec()
+    }
+
+    fn decode (&self, bytes: &[u8]) -> Option<Uuid> {
+        Uuid::from_slice(bytes)
+    }
+
+    fn format (&self, value: &Uuid, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", value)
+    }
+}
+
+/// The codec for [decimals](struct.Decimal.html), in type 1 by default
+/// 
+/// Like [UuidCodec](struct.UuidCodec.html), it has to be registered.
+#[derive(Debug, Clone, Copy)]
+pub struct DecimalCodec {
+    type_id: i8,
+}
+
+impl DecimalCodec {
+    /// Creates the codec for a custom type id
+    pub fn new (type_id: i8) -> DecimalCodec {
+        DecimalCodec { type_id }
+    }
+}
+
+impl Default for DecimalCodec {
+    fn default () -> DecimalCodec {
+        DecimalCodec { type_id: Decimal::TYPE_ID }
+    }
+}
+
+impl ExtensionCodec for DecimalCodec {
+    type Value = Decimal;
+
+    fn type_id (&self) -> i8 {
+        self.type_id
+    }
+
+    fn name (&self) -> &'static str {
+        "decimal"
+    }
+
+    fn encode (&self, value: &Decimal) -> Vec<u8> {
+        value.to_bytes()
+    }
+
+    fn decode (&self, bytes: &[u8]) -> Option<Decimal> {
+        Decimal::from_bytes(bytes)
+    }
+
+    fn format (&self, value: &Decimal, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", value)
+    }
+}
+
+// codecs with the value type erased, so different ones fit in one registry
+trait AnyCodec {
+    fn name (&self) -> &'static str;
+    fn decode_any (&self, bytes: &[u8]) -> Option<Box<dyn Any>>;
+    fn encode_any (&self, value: &dyn Any) -> Option<Vec<u8>>;
+    fn format_bytes (&self, bytes: &[u8], f: &mut Formatter) -> Option<fmt::Result>;
+    // the codec as a `Box<dyn ExtensionCodec<Value = _>>`, to hand it back
+    fn into_any (self: Box<Self>) -> Box<dyn Any>;
+}
+
+impl <C: ExtensionCodec + 'static> AnyCodec for C {
+    fn name (&self) -> &'static str {
+        ExtensionCodec::name(self)
+    }
+
+    fn decode_any (&self, bytes: &[u8]) -> Option<Box<dyn Any>> {
+        self.decode(bytes).map(|value| Box::new(value) as Box<dyn Any>)
+    }
+
+    fn encode_any (&self, value: &dyn Any) -> Option<Vec<u8>> {
+        value.downcast_ref().map(|value| self.encode(value))
+    }
+
+    fn format_bytes (&self, bytes: &[u8], f: &mut Formatter) -> Option<fmt::Result> {
+        self.decode(bytes).map(|value| self.format(&value, f))
+    }
+
+    fn into_any (self: Box<Self>) -> Box<dyn Any> {
+        Box::new(self as Box<dyn ExtensionCodec<Value = C::Value>>)
+    }
+}
+
+/// A set of [extension codecs](trait.ExtensionCodec.html), one per type id
+/// 
+/// A new registry knows the timestamp type -1, other codecs can be added with
+/// `register`. With a registry, extensions can be decoded into their Rust
+/// types and encoded back, parsed data can be checked for extensions that
+/// don't decode, and values can be displayed with each extension in its own
+/// format.
+/// 
+///     use msgpack_simple::{msgpack, MsgPack, ExtensionRegistry, Timestamp};
+/// 
+///     let registry = ExtensionRegistry::new();
+///     let timestamp = Timestamp::new(1_500_000_000, 0).unwrap();
+/// 
+///     let message = msgpack!({ "sent": (registry.encode(&timestamp).unwrap()) });
+///     assert_eq!(message.to_string(), "{\"sent\": ext:-1:59682f00}");
+///     assert_eq!(registry.display(&message).to_string(), "{\"sent\": 2017-07-14T02:40:00Z}");
+/// 
+///     let decoded: Timestamp = registry.decode(&message["sent"]).unwrap();
+///     assert_eq!(decoded, timestamp);
+pub struct ExtensionRegistry {
+    codecs: HashMap<i8, Box<dyn AnyCodec>>,
+    types: HashMap<TypeId, i8>,
+}
+
+impl ExtensionRegistry {
+    /// Creates a registry with the codecs of the extension types defined by
+    /// the MessagePack spec, which is only the timestamp for now
+    pub fn new () -> ExtensionRegistry {
+        let mut registry = ExtensionRegistry::empty();
+        registry.register(TimestampCodec);
+        registry
+    }
+
+    /// Creates a registry without any codecs
+    pub fn empty () -> ExtensionRegistry {
+        ExtensionRegistry { codecs: HashMap::new(), types: HashMap::new() }
+    }
+
+    /// Adds a codec, replacing the one registered for the same type id or
+    /// the same value type
+    /// 
+    /// Each value type is encoded with one codec, so a codec registered for
+    /// the same value type is removed even if it has a different type id.
+    /// That codec is returned, extensions of its type id are unknown to the
+    /// registry from now on.
+    /// 
+    ///     use msgpack_simple::{ExtensionRegistry, ExtensionCodec, UuidCodec};
+    /// 
+    ///     let mut registry = ExtensionRegistry::new();
+    ///     assert!(registry.register(UuidCodec::new(40)).is_none());
+    /// 
+    ///     let evicted = registry.register(UuidCodec::new(41)).unwrap();
+    ///     assert_eq!(evicted.type_id(), 40);
+    ///     assert!(!registry.contains(40));
+    pub fn register <C: ExtensionCodec + 'static> (&mut self, codec: C) -> Option<Box<dyn ExtensionCodec<Value = C::Value>>> {
+        let type_id = codec.type_id();
+        let value_type = TypeId::of::<C::Value>();
+
+        let evicted = self.types.insert(value_type, type_id)
+            .and_then(|previous| self.codecs.remove(&previous))
+            .map(|previous| match previous.into_any().downcast::<Box<dyn ExtensionCodec<Value = C::Value>>>() {
+                Ok(previous) => *previous,
+                Err(_) => unreachable!()
+            });
+
+        self.types.retain(|&other, &mut id| id != type_id || other == value_type);
+        self.codecs.insert(type_id, Box::new(codec));
+        evicted
+    }
+
+    /// Checks if a codec is registered for the type id
+    pub fn contains (&self, type_id: i8) -> bool {
+        self.codecs.contains_key(&type_id)
+    }
+
+    /// Decodes an extension into the value type of its codec
+    /// 
+    /// Fails if the MsgPack is not an extension, no codec is registered for
+    /// its type id, the codec has a different value type, or the bytes don't
+    /// decode.
+    pub fn decode <T: 'static> (&self, value: &MsgPack) -> Result<T, ConversionError> {
+        let error = |attempted| ConversionError::type_error(value.clone(), attempted);
+
+        let extension = match value {
+            MsgPack::Extension(extension) => extension,
+            _ => return Err(error("extension"))
+        };
+
+        let codec = self.codecs.get(&extension.type_id).ok_or_else(|| error("extension value"))?;
+
+        codec.decode_any(&extension.value)
+            .and_then(|decoded| decoded.downcast().ok())
+            .map(|decoded| *decoded)
+            .ok_or_else(|| error(codec.name()))
+    }
+
+    /// Encodes a value with the codec registered for its type, returning
+    /// `None` if there is none
+    pub fn encode <T: 'static> (&self, value: &T) -> Option<MsgPack> {
+        let type_id = *self.types.get(&TypeId::of::<T>())?;
+        let value = self.codecs[&type_id].encode_any(value)?;
+
+        Some(MsgPack::Extension(Extension { type_id, value }))
+    }
+
+    /// Parses binary data like [MsgPack::parse](enum.MsgPack.html#method.parse),
+    /// but fails on extensions of a registered type that don't decode, with
+    /// the error pointing to the extension
+    /// 
+    ///     use msgpack_simple::ExtensionRegistry;
+    /// 
+    ///     let registry = ExtensionRegistry::new();
+    /// 
+    ///     // a timestamp with a billion nanoseconds in an array
+    ///     let data = [0x91, 0xd7, 0xff, 0xee, 0x6b, 0x28, 0x00, 0, 0, 0, 0];
+    /// 
+    ///     assert_eq!(registry.parse(&data).unwrap_err().byte, 1);
+    pub fn parse (&self, raw: &[u8]) -> Result<MsgPack, ParseError> {
+        let mut check = |extension: &Extension| match self.codecs.get(&extension.type_id) {
+            Some(codec) => codec.decode_any(&extension.value).is_some(),
+            None => true
+        };
+
+        let (result, _) = parser::parse_with(raw, &mut check)?;
+        Ok(result)
+    }
+
+    /// Wraps a value to display it with registered extensions in their own
+    /// format, and the rest like MsgPack's own Display
+    pub fn display <'a> (&'a self, value: &'a MsgPack) -> Formatted<'a> {
+        Formatted { value, registry: self }
+    }
+}
+
+impl Default for ExtensionRegistry {
+    fn default () -> ExtensionRegistry {
+        ExtensionRegistry::new()
+    }
+}
+
+/// A MsgPack value displayed with an
+/// [ExtensionRegistry](struct.ExtensionRegistry.html), see its `display`
+/// method
+pub struct Formatted<'a> {
+    value: &'a MsgPack,
+    registry: &'a ExtensionRegistry,
+}
+
+impl <'a> Formatted<'a> {
+    fn child (&self, value: &'a MsgPack) -> Formatted<'a> {
+        Formatted { value, registry: self.registry }
+    }
+}
+
+impl Display for Formatted<'_> {
+    fn fmt (&self, f: &mut Formatter) -> fmt::Result {
+        match self.value {
+            MsgPack::Extension(extension) => {
+                let formatted = self.registry.codecs.get(&extension.type_id)
+                    .and_then(|codec| codec.format_bytes(&extension.value, f));
+
+                match formatted {
+                    Some(result) => result,
+                    None => write!(f, "{}", self.value)
+                }
+            },
+            MsgPack::Array(value) => {
+                write!(f, "[")?;
+
+                let mut first = true;
+                for item in value {
+                    if !first { write!(f, ", ")? }
+                    first = false;
+                    write!(f, "{}", self.child(item))?;
+                }
+
+                write!(f, "]")
+            },
+            MsgPack::Map(value) => {
+                write!(f, "{{")?;
+
+                let mut first = true;
+                for item in value {
+                    if !first { write!(f, ", ")? }
+                    first = false;
+                    write!(f, "{}: ", self.child(&item.key))?;
+                    write!(f, "{}", self.child(&item.value))?;
+                }
+
+                write!(f, "}}")
+            },
+            other => write!(f, "{}", other)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Celsius;
+
+    impl ExtensionCodec for Celsius {
+        type Value = f32;
+
+        fn type_id (&self) -> i8 { 5 }
+
+        fn encode (&self, value: &f32) -> Vec<u8> {
+            value.to_be_bytes().to_vec()
+        }
+
+        fn decode (&self, bytes: &[u8]) -> Option<f32> {
+            if bytes.len() != 4 { return None }
+            Some(f32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        }
+
+        fn format (&self, value: &f32, f: &mut Formatter) -> fmt::Result {
+            write!(f, "{}°C", value)
+        }
+    }
+
+    struct Kelvin;
+
+    impl ExtensionCodec for Kelvin {
+        type Value = f32;
+
+        fn type_id (&self) -> i8 { 6 }
+
+        fn encode (&self, value: &f32) -> Vec<u8> {
+            Celsius.encode(value)
+        }
+
+        fn decode (&self, bytes: &[u8]) -> Option<f32> {
+            Celsius.decode(bytes)
+        }
+    }
+
+    #[test]
+    fn typed_values () {
+        let mut registry = ExtensionRegistry::new();
+        registry.register(Celsius);
+
+        let encoded = registry.encode(&21.5f32).unwrap();
+        assert_eq!(encoded, MsgPack::Extension(Extension { type_id: 5, value: vec![0x41, 0xac, 0, 0] }));
+        assert_eq!(registry.decode::<f32>(&encoded).unwrap(), 21.5);
+        assert!(registry.decode::<Timestamp>(&encoded).is_err());
+        assert!(registry.decode::<f32>(&MsgPack::Extension(Extension { type_id: 5, value: vec![0] })).is_err());
+        assert!(registry.decode::<f32>(&MsgPack::Extension(Extension { type_id: 9, value: vec![0] })).is_err());
+        assert_eq!(registry.encode(&1u8), None);
+
+        let error = registry.decode::<Timestamp>(&MsgPack::Extension(Extension { type_id: -1, value: vec![0] })).unwrap_err();
+        assert_eq!(error.attempted, "timestamp");
+
+        // a second codec for the same value type takes over
+        let evicted = registry.register(Kelvin).unwrap();
+        assert_eq!(ExtensionCodec::type_id(&*evicted), 5);
+        assert_eq!(evicted.decode(&[0x41, 0xac, 0, 0]), Some(21.5));
+        assert!(!registry.contains(5));
+        assert_eq!(registry.encode(&0f32).unwrap().as_extension().unwrap().type_id, 6);
+    }
+
+    #[test]
+    fn parse_and_display () {
+        let mut registry = ExtensionRegistry::new();
+        registry.register(Celsius);
+
+        let message = MsgPack::Array(vec![
+            registry.encode(&-4f32).unwrap(),
+            MsgPack::Extension(Extension { type_id: 5, value: vec![1, 2] }),
+            MsgPack::Extension(Extension { type_id: 7, value: vec![1, 2] }),
+        ]);
+        assert_eq!(registry.display(&message).to_string(), "[-4°C, ext:5:0102, ext:7:0102]");
+
+        assert_eq!(registry.parse(&message.encode()).unwrap_err().byte, 7);
+        assert_eq!(ExtensionRegistry::empty().parse(&message.encode()).unwrap(), message);
+    }
+
+    #[test]
+    fn uuid_and_decimal () {
+        let mut registry = ExtensionRegistry::new();
+        registry.register(UuidCodec::default());
+        registry.register(DecimalCodec::default());
+
+        let uuid: Uuid = "64d22e4d-ac92-4a23-899a-e59f34af5479".parse().unwrap();
+        let decimal: Decimal = "-12.34".parse().unwrap();
+        let message = MsgPack::Array(vec![MsgPack::from(uuid), MsgPack::from(decimal)]);
+
+        assert_eq!(registry.encode(&uuid).unwrap(), message[0]);
+        assert_eq!(registry.decode::<Decimal>(&message[1]).unwrap(), decimal);
+        assert_eq!(registry.display(&message).to_string(), "[64d22e4d-ac92-4a23-899a-e59f34af5479, -12.34]");
+
+        let broken = MsgPack::Extension(Extension { type_id: 1, value: vec![0x02, 0x12] });
+        assert_eq!(registry.decode::<Decimal>(&broken).unwrap_err().attempted, "decimal");
+    }
+}
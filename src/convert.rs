@@ -0,0 +1,301 @@
+//! Conversions between MsgPack and Rust types
+
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::hash::BuildHasher;
+
+use super::{MsgPack, MapElement, Extension};
+use super::error::{ConversionError, ConversionKind};
+use super::int128::{to_i128, to_u128};
+
+/// A wrapper that converts a byte vector into a MsgPack binary
+/// 
+/// A plain `Vec<u8>` converts into an array of uints, like any other vector.
+/// To get a binary instead, wrap it with this struct.
+/// 
+///     use msgpack_simple::{MsgPack, Binary};
+/// 
+///     let binary = MsgPack::from(Binary(vec![0x42, 0xff]));
+///     assert_eq!(binary, MsgPack::Binary(vec![0x42, 0xff]));
+/// 
+///     let array = MsgPack::from(vec![0x42u8, 0xff]);
+///     assert_eq!(array, MsgPack::Array(vec![MsgPack::Uint(0x42), MsgPack::Uint(0xff)]));
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct Binary(pub Vec<u8>);
+
+impl From<Vec<u8>> for Binary {
+    fn from (value: Vec<u8>) -> Binary {
+        Binary(value)
+    }
+}
+
+impl From<&[u8]> for Binary {
+    fn from (value: &[u8]) -> Binary {
+        Binary(value.to_vec())
+    }
+}
+
+macro_rules! impl_from {
+    ($($source:ty => $variant:ident as $inner:ty;)*) => {
+        $(
+            impl From<$source> for MsgPack {
+                fn from (value: $source) -> MsgPack {
+                    MsgPack::$variant(value as $inner)
+                }
+            }
+        )*
+    };
+}
+
+impl_from! {
+    i8 => Int as i64;
+    i16 => Int as i64;
+    i32 => Int as i64;
+    i64 => Int as i64;
+    isize => Int as i64;
+    u8 => Uint as u64;
+    u16 => Uint as u64;
+    u32 => Uint as u64;
+    u64 => Uint as u64;
+    usize => Uint as u64;
+    f32 => Float as f64;
+    f64 => Float as f64;
+    bool => Boolean as bool;
+}
+
+impl From<&str> for MsgPack {
+    fn from (value: &str) -> MsgPack {
+        MsgPack::String(value.to_string())
+    }
+}
+
+impl From<String> for MsgPack {
+    fn from (value: String) -> MsgPack {
+        MsgPack::String(value)
+    }
+}
+
+impl From<Binary> for MsgPack {
+    fn from (value: Binary) -> MsgPack {
+        MsgPack::Binary(value.0)
+    }
+}
+
+impl From<Extension> for MsgPack {
+    fn from (value: Extension) -> MsgPack {
+        MsgPack::Extension(value)
+    }
+}
+
+impl <T: Into<MsgPack>> From<Option<T>> for MsgPack {
+    fn from (value: Option<T>) -> MsgPack {
+        match value {
+            Some(value) => value.into(),
+            None => MsgPack::Nil
+        }
+    }
+}
+
+impl <T: Into<MsgPack>> From<Vec<T>> for MsgPack {
+    fn from (value: Vec<T>) -> MsgPack {
+        MsgPack::Array(value.into_iter().map(Into::into).collect())
+    }
+}
+
+impl <T: Clone + Into<MsgPack>> From<&[T]> for MsgPack {
+    fn from (value: &[T]) -> MsgPack {
+        MsgPack::Array(value.iter().cloned().map(Into::into).collect())
+    }
+}
+
+impl <K: Into<MsgPack>, V: Into<MsgPack>, S: BuildHasher> From<HashMap<K, V, S>> for MsgPack {
+    fn from (value: HashMap<K, V, S>) -> MsgPack {
+        MsgPack::Map(value.into_iter().map(|(key, value)| MapElement { key: key.into(), value: value.into() }).collect())
+    }
+}
+
+impl <K: Into<MsgPack>, V: Into<MsgPack>> From<BTreeMap<K, V>> for MsgPack {
+    fn from (value: BTreeMap<K, V>) -> MsgPack {
+        MsgPack::Map(value.into_iter().map(|(key, value)| MapElement { key: key.into(), value: value.into() }).collect())
+    }
+}
+
+macro_rules! impl_from_tuple {
+    ($(($($name:ident),+))*) => {
+        $(
+            impl <$($name: Into<MsgPack>),+> From<($($name,)+)> for MsgPack {
+                #[allow(non_snake_case)]
+                fn from (value: ($($name,)+)) -> MsgPack {
+                    let ($($name,)+) = value;
+                    MsgPack::Array(vec![$($name.into()),+])
+                }
+            }
+        )*
+    };
+}
+
+impl_from_tuple! {
+    (A)
+    (A, B)
+    (A, B, C)
+    (A, B, C, D)
+    (A, B, C, D, E)
+    (A, B, C, D, E, F)
+    (A, B, C, D, E, F, G)
+    (A, B, C, D, E, F, G, H)
+}
+
+fn to_int <T: TryFrom<i64> + TryFrom<u64>> (value: &MsgPack) -> Result<T, ConversionKind> {
+    match *value {
+        MsgPack::Int(inner) => T::try_from(inner).map_err(|_| ConversionKind::Range),
+        MsgPack::Uint(inner) => T::try_from(inner).map_err(|_| ConversionKind::Range),
+        _ => Err(ConversionKind::Type)
+    }
+}
+
+fn to_f64 (value: &MsgPack) -> Result<f64, ConversionKind> {
+    match *value {
+        MsgPack::Float(inner) => Ok(inner),
+        _ => Err(ConversionKind::Type)
+    }
+}
+
+fn to_f32 (value: &MsgPack) -> Result<f32, ConversionKind> {
+    match *value {
+        // infinities and NaN carry over, finite values have to fit in an f32
+        MsgPack::Float(inner) if inner.is_finite() && inner.abs() > f32::MAX as f64 => Err(ConversionKind::Range),
+        MsgPack::Float(inner) => Ok(inner as f32),
+        _ => Err(ConversionKind::Type)
+    }
+}
+
+fn to_bool (value: &MsgPack) -> Result<bool, ConversionKind> {
+    match *value {
+        MsgPack::Boolean(inner) => Ok(inner),
+        _ => Err(ConversionKind::Type)
+    }
+}
+
+macro_rules! impl_try_from {
+    ($($target:ty => $reader:expr, $name:expr;)*) => {
+        $(
+            impl TryFrom<MsgPack> for $target {
+                type Error = ConversionError;
+
+                fn try_from (value: MsgPack) -> Result<$target, ConversionError> {
+                    let reader: fn(&MsgPack) -> Result<$target, ConversionKind> = $reader;
+                    reader(&value).map_err(|kind| ConversionError { original: value, attempted: $name, kind })
+                }
+            }
+
+            impl TryFrom<&MsgPack> for $target {
+                type Error = ConversionError;
+
+                fn try_from (value: &MsgPack) -> Result<$target, ConversionError> {
+                    let reader: fn(&MsgPack) -> Result<$target, ConversionKind> = $reader;
+                    reader(value).map_err(|kind| ConversionError { original: value.clone(), attempted: $name, kind })
+                }
+            }
+        )*
+    };
+}
+
+impl_try_from! {
+    i8 => to_int, "i8";
+    i16 => to_int, "i16";
+    i32 => to_int, "i32";
+    i64 => to_int, "i64";
+    u8 => to_int, "u8";
+    u16 => to_int, "u16";
+    u32 => to_int, "u32";
+    u64 => to_int, "u64";
+    usize => to_int, "usize";
+    i128 => to_i128, "i128";
+    u128 => to_u128, "u128";
+    f32 => to_f32, "f32";
+    f64 => to_f64, "f64";
+    bool => to_bool, "bool";
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_primitives () {
+        assert_eq!(MsgPack::from(-5i8), MsgPack::Int(-5));
+        assert_eq!(MsgPack::from(42i64), MsgPack::Int(42));
+        assert_eq!(MsgPack::from(42u16), MsgPack::Uint(42));
+        assert_eq!(MsgPack::from(u64::MAX), MsgPack::Uint(u64::MAX));
+        assert_eq!(MsgPack::from(0.5f32), MsgPack::Float(0.5));
+        assert_eq!(MsgPack::from(true), MsgPack::Boolean(true));
+        assert_eq!(MsgPack::from("foo"), MsgPack::String("foo".to_string()));
+        assert_eq!(MsgPack::from("foo".to_string()), MsgPack::String("foo".to_string()));
+        assert_eq!(MsgPack::from(Binary::from(&[0x42u8][..])), MsgPack::Binary(vec![0x42]));
+
+        let extension = Extension { type_id: 2, value: vec![0x32] };
+        assert_eq!(MsgPack::from(extension.clone()), MsgPack::Extension(extension));
+    }
+
+    #[test]
+    fn from_collections () {
+        assert_eq!(MsgPack::from(Some(42u8)), MsgPack::Uint(42));
+        assert_eq!(MsgPack::from(None::<u8>), MsgPack::Nil);
+        assert_eq!(MsgPack::from(vec!["a", "b"]), MsgPack::Array(vec![MsgPack::from("a"), MsgPack::from("b")]));
+        assert_eq!(MsgPack::from(&[1i32, 2][..]), MsgPack::Array(vec![MsgPack::Int(1), MsgPack::Int(2)]));
+        assert_eq!(MsgPack::from((1i32, "a", None::<bool>)), MsgPack::Array(vec![MsgPack::Int(1), MsgPack::from("a"), MsgPack::Nil]));
+
+        let mut map = BTreeMap::new();
+        map.insert("b", vec![true]);
+        map.insert("a", vec![]);
+
+        assert_eq!(MsgPack::from(map), MsgPack::Map(vec![
+            MapElement { key: MsgPack::from("a"), value: MsgPack::Array(vec![]) },
+            MapElement { key: MsgPack::from("b"), value: MsgPack::Array(vec![MsgPack::Boolean(true)]) }
+        ]));
+
+        let mut map = HashMap::new();
+        map.insert(1u8, "one");
+
+        assert_eq!(MsgPack::from(map), MsgPack::Map(vec![
+            MapElement { key: MsgPack::Uint(1), value: MsgPack::from("one") }
+        ]));
+    }
+
+    #[test]
+    fn integers () {
+        assert_eq!(u8::try_from(MsgPack::Int(255)).unwrap(), 255);
+        assert_eq!(u8::try_from(&MsgPack::Uint(42)).unwrap(), 42);
+        assert_eq!(i8::try_from(MsgPack::Int(-128)).unwrap(), -128);
+        assert_eq!(i64::try_from(MsgPack::Uint(i64::MAX as u64)).unwrap(), i64::MAX);
+        assert_eq!(u64::try_from(MsgPack::Uint(u64::MAX)).unwrap(), u64::MAX);
+        assert_eq!(usize::try_from(MsgPack::Int(7)).unwrap(), 7);
+
+        assert!(u8::try_from(MsgPack::Int(256)).unwrap_err().is_range_error());
+        assert!(u32::try_from(MsgPack::Int(-1)).unwrap_err().is_range_error());
+        assert!(i8::try_from(MsgPack::Uint(128)).unwrap_err().is_range_error());
+        assert!(i64::try_from(MsgPack::Uint(u64::MAX)).unwrap_err().is_range_error());
+
+        let error = i32::try_from(MsgPack::Float(1.0)).unwrap_err();
+        assert_eq!(error.kind, ConversionKind::Type);
+        assert!(error.recover().is_float());
+    }
+
+    #[test]
+    fn floats () {
+        assert_eq!(f64::try_from(MsgPack::Float(4.2)).unwrap(), 4.2);
+        assert_eq!(f32::try_from(MsgPack::Float(0.5)).unwrap(), 0.5);
+        assert!(f32::try_from(MsgPack::Float(f64::INFINITY)).unwrap().is_infinite());
+        assert!(f32::try_from(MsgPack::Float(f64::NAN)).unwrap().is_nan());
+
+        assert!(f32::try_from(MsgPack::Float(1e300)).unwrap_err().is_range_error());
+        assert!(!f64::try_from(MsgPack::Int(1)).unwrap_err().is_range_error());
+    }
+
+    #[test]
+    fn booleans () {
+        assert!(bool::try_from(MsgPack::Boolean(true)).unwrap());
+        assert!(!bool::try_from(&MsgPack::Boolean(false)).unwrap());
+        assert!(bool::try_from(MsgPack::Int(1)).is_err());
+    }
+}
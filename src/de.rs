@@ -0,0 +1,446 @@
+//! A serde deserializer that reads MessagePack directly, behind the `serde`
+//! feature
+
+use std::convert::TryFrom;
+use std::io::Read;
+
+use serde::de::{self, Deserialize, DeserializeOwned, DeserializeSeed, IntoDeserializer, Unexpected, Visitor};
+use serde::de::value::{BorrowedBytesDeserializer, SeqAccessDeserializer};
+use serde::forward_to_deserialize_any;
+
+use super::MsgPack;
+use super::error::SerdeError;
+use super::path::Path;
+use super::marker::{Header, read_header};
+use super::parser;
+use super::serde_impls::EXTENSION_TOKEN;
+
+/// Deserializes a value from MessagePack
+/// 
+/// The data is read as the value asks for it, without building a
+/// [MsgPack](enum.MsgPack.html) tree first. It has to hold exactly one value,
+/// so trailing data is an error. Errors point to the byte where they were
+/// found, or for errors raised by the value itself, to where the deserializer
+/// had got to.
+/// 
+/// Strings and binaries are borrowed from the data, so `&str` and `&[u8]`
+/// fields point into it instead of being copied. Extension payloads are
+/// borrowed the same way.
+/// 
+///     use msgpack_simple::{from_slice, to_vec};
+/// 
+///     #[derive(serde::Deserialize, Debug, PartialEq)]
+///     struct Point { x: i32, y: i32 }
+/// 
+///     let point: Point = from_slice(&[0x82, 0xa1, 0x78, 0x01, 0xa1, 0x79, 0xfe]).unwrap();
+///     assert_eq!(point, Point { x: 1, y: -2 });
+/// 
+///     let error = from_slice::<Point>(&[0x82, 0xa1, 0x78, 0xc1]).unwrap_err();
+///     assert_eq!(error.byte, Some(3));
+pub fn from_slice <'de, T: Deserialize<'de>> (input: &'de [u8]) -> Result<T, SerdeError> {
+    let mut deserializer = Deserializer::from_slice(input);
+
+    let result = T::deserialize(&mut deserializer).and_then(|value| {
+        deserializer.end()?;
+        Ok(value)
+    });
+
+    result.map_err(|mut error| {
+        if error.byte.is_none() { error.byte = Some(deserializer.position) }
+        error
+    })
+}
+
+/// Deserializes a value from MessagePack read from a reader, see
+/// [from_slice](fn.from_slice.html)
+/// 
+/// The reader is read to the end first.
+pub fn from_reader <R: Read, T: DeserializeOwned> (mut reader: R) -> Result<T, SerdeError> {
+    let mut input = vec![];
+    reader.read_to_end(&mut input).map_err(|error| SerdeError { message: error.to_string(), byte: None, path: Path::root() })?;
+    from_slice(&input)
+}
+
+/// The deserializer behind [from_slice](fn.from_slice.html) and
+/// [from_reader](fn.from_reader.html)
+pub struct Deserializer<'de> {
+    input: &'de [u8],
+    position: usize,
+}
+
+impl <'de> Deserializer<'de> {
+    /// Creates a deserializer that reads from the start of the data
+    pub fn from_slice (input: &'de [u8]) -> Deserializer<'de> {
+        Deserializer { input, position: 0 }
+    }
+
+    /// The number of bytes read so far
+    pub fn position (&self) -> usize {
+        self.position
+    }
+
+    /// Checks that all of the data has been read
+    pub fn end (&self) -> Result<(), SerdeError> {
+        match self.position < self.input.len() {
+            true => Err(self.error(self.position, "trailing data")),
+            false => Ok(())
+        }
+    }
+
+    fn error (&self, byte: usize, message: &str) -> SerdeError {
+        SerdeError { message: message.to_string(), byte: Some(byte), path: Path::root() }
+    }
+
+    fn peek (&self) -> Option<u8> {
+        self.input.get(self.position).cloned()
+    }
+
+    fn header (&mut self) -> Result<Header, SerdeError> {
+        let (header, size) = read_header(&self.input[self.position..])
+            .map_err(|error| self.error(self.position + error.byte, "invalid MessagePack data"))?;
+
+        self.position += size;
+        Ok(header)
+    }
+
+    // the payload after a header, pointing at its start if it's cut short
+    fn take (&mut self, length: usize) -> Result<&'de [u8], SerdeError> {
+        let start = self.position;
+        if self.input.len() - start < length { return Err(self.error(start, "invalid MessagePack data")) }
+
+        self.position += length;
+        Ok(&self.input[start..self.position])
+    }
+
+    // reads a whole value into a tree, for the few types that need it
+    fn value (&mut self) -> Result<MsgPack, SerdeError> {
+        let start = self.position;
+        let (value, length) = parser::parse(&self.input[start..])
+            .map_err(|error| self.error(start + error.byte, "invalid MessagePack data"))?;
+
+        self.position += length;
+        Ok(value)
+    }
+}
+
+impl <'de> de::Deserializer<'de> for &mut Deserializer<'de> {
+    type Error = SerdeError;
+
+    fn is_human_readable (&self) -> bool {
+        false
+    }
+
+    fn deserialize_any <V: Visitor<'de>> (self, visitor: V) -> Result<V::Value, SerdeError> {
+        let start = self.position;
+
+        match self.header()? {
+            Header::Nil => visitor.visit_unit(),
+            Header::Boolean(value) => visitor.visit_bool(value),
+            Header::Int(value) => visitor.visit_i64(value),
+            Header::Uint(value) => visitor.visit_u64(value),
+            Header::Float(value) => visitor.visit_f64(value),
+            Header::String(length) => {
+                let payload = self.take(length)?;
+                let value = std::str::from_utf8(payload).map_err(|_| self.error(self.position - length, "invalid UTF-8 string"))?;
+                visitor.visit_borrowed_str(value)
+            },
+            Header::Binary(length) => visitor.visit_borrowed_bytes(self.take(length)?),
+            Header::Extension(type_id, length) => {
+                let data = self.take(length)?;
+                visitor.visit_map(ExtensionAccess { type_id, data, read: false })
+            },
+            Header::Array(length) => {
+                let mut items = Items { deserializer: self, remaining: length };
+                let result = visitor.visit_seq(&mut items)?;
+                items.end(start)?;
+                Ok(result)
+            },
+            Header::Map(length) => {
+                let mut items = Items { deserializer: self, remaining: length };
+                let result = visitor.visit_map(&mut items)?;
+                items.end(start)?;
+                Ok(result)
+            }
+        }
+    }
+
+    fn deserialize_i128 <V: Visitor<'de>> (self, visitor: V) -> Result<V::Value, SerdeError> {
+        let start = self.position;
+        let value = i128::try_from(self.value()?).map_err(|error| self.error(start, &error.to_string()))?;
+        visitor.visit_i128(value)
+    }
+
+    fn deserialize_u128 <V: Visitor<'de>> (self, visitor: V) -> Result<V::Value, SerdeError> {
+        let start = self.position;
+        let value = u128::try_from(self.value()?).map_err(|error| self.error(start, &error.to_string()))?;
+        visitor.visit_u128(value)
+    }
+
+    fn deserialize_option <V: Visitor<'de>> (self, visitor: V) -> Result<V::Value, SerdeError> {
+        if self.peek() == Some(0xc0) {
+            self.position += 1;
+            return visitor.visit_none()
+        }
+
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct <V: Visitor<'de>> (self, _name: &'static str, visitor: V) -> Result<V::Value, SerdeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum <V: Visitor<'de>> (self, _name: &'static str, _variants: &'static [&'static str], visitor: V) -> Result<V::Value, SerdeError> {
+        let start = self.position;
+
+        match read_header(&self.input[start..]) {
+            // a unit variant is just the name
+            Ok((Header::String(_), _)) => visitor.visit_enum(Variant { deserializer: self, map: false }),
+            // any other is a map from the name to the contents
+            Ok((Header::Map(1), size)) => {
+                self.position += size;
+                visitor.visit_enum(Variant { deserializer: self, map: true })
+            },
+            Ok(_) => Err(self.error(start, "expected an enum variant name or a map with a single entry")),
+            Err(error) => Err(self.error(start + error.byte, "invalid MessagePack data"))
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes
+        byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+// the items of an array or a map, counting down to the end
+struct Items<'a, 'de> {
+    deserializer: &'a mut Deserializer<'de>,
+    remaining: usize,
+}
+
+impl Items<'_, '_> {
+    // the visitor has to read every item
+    fn end (&self, start: usize) -> Result<(), SerdeError> {
+        match self.remaining {
+            0 => Ok(()),
+            _ => Err(self.deserializer.error(start, "more items than expected"))
+        }
+    }
+}
+
+impl <'de> de::SeqAccess<'de> for Items<'_, 'de> {
+    type Error = SerdeError;
+
+    fn next_element_seed <T: DeserializeSeed<'de>> (&mut self, seed: T) -> Result<Option<T::Value>, SerdeError> {
+        if self.remaining == 0 { return Ok(None) }
+        self.remaining -= 1;
+
+        seed.deserialize(&mut *self.deserializer).map(Some)
+    }
+
+    fn size_hint (&self) -> Option<usize> {
+        Some(self.remaining)
+    }
+}
+
+impl <'de> de::MapAccess<'de> for Items<'_, 'de> {
+    type Error = SerdeError;
+
+    fn next_key_seed <K: DeserializeSeed<'de>> (&mut self, seed: K) -> Result<Option<K::Value>, SerdeError> {
+        if self.remaining == 0 { return Ok(None) }
+        self.remaining -= 1;
+
+        seed.deserialize(&mut *self.deserializer).map(Some)
+    }
+
+    fn next_value_seed <V: DeserializeSeed<'de>> (&mut self, seed: V) -> Result<V::Value, SerdeError> {
+        seed.deserialize(&mut *self.deserializer)
+    }
+
+    fn size_hint (&self) -> Option<usize> {
+        Some(self.remaining)
+    }
+}
+
+// an extension, given to visitors as a map with a single entry from the token
+// to a tuple of the type id and the data, as it's serialized
+struct ExtensionAccess<'de> {
+    type_id: i8,
+    data: &'de [u8],
+    read: bool,
+}
+
+impl <'de> de::MapAccess<'de> for ExtensionAccess<'de> {
+    type Error = SerdeError;
+
+    fn next_key_seed <K: DeserializeSeed<'de>> (&mut self, seed: K) -> Result<Option<K::Value>, SerdeError> {
+        if self.read { return Ok(None) }
+        seed.deserialize(EXTENSION_TOKEN.into_deserializer()).map(Some)
+    }
+
+    fn next_value_seed <V: DeserializeSeed<'de>> (&mut self, seed: V) -> Result<V::Value, SerdeError> {
+        self.read = true;
+        seed.deserialize(SeqAccessDeserializer::new(ExtensionParts { type_id: self.type_id, data: self.data, index: 0 }))
+    }
+}
+
+struct ExtensionParts<'de> {
+    type_id: i8,
+    data: &'de [u8],
+    index: usize,
+}
+
+impl <'de> de::SeqAccess<'de> for ExtensionParts<'de> {
+    type Error = SerdeError;
+
+    fn next_element_seed <T: DeserializeSeed<'de>> (&mut self, seed: T) -> Result<Option<T::Value>, SerdeError> {
+        self.index += 1;
+
+        match self.index {
+            1 => seed.deserialize(self.type_id.into_deserializer()).map(Some),
+            2 => seed.deserialize(BorrowedBytesDeserializer::new(self.data)).map(Some),
+            _ => Ok(None)
+        }
+    }
+
+    fn size_hint (&self) -> Option<usize> {
+        Some(2 - self.index.min(2))
+    }
+}
+
+// an enum variant, with the deserializer at its name, which is either on its
+// own or the key of a map
+struct Variant<'a, 'de> {
+    deserializer: &'a mut Deserializer<'de>,
+    map: bool,
+}
+
+impl <'de> de::EnumAccess<'de> for Variant<'_, 'de> {
+    type Error = SerdeError;
+    type Variant = Self;
+
+    fn variant_seed <V: DeserializeSeed<'de>> (self, seed: V) -> Result<(V::Value, Self), SerdeError> {
+        let variant = seed.deserialize(&mut *self.deserializer)?;
+        Ok((variant, self))
+    }
+}
+
+impl <'de> de::VariantAccess<'de> for Variant<'_, 'de> {
+    type Error = SerdeError;
+
+    fn unit_variant (self) -> Result<(), SerdeError> {
+        // a unit variant is written as just the name, but may also come as a
+        // map from the name to nil
+        match self.map {
+            true => <()>::deserialize(self.deserializer),
+            false => Ok(())
+        }
+    }
+
+    fn newtype_variant_seed <T: DeserializeSeed<'de>> (self, seed: T) -> Result<T::Value, SerdeError> {
+        // a bare name has no contents, what follows it in the input belongs
+        // to the enclosing value
+        match self.map {
+            true => seed.deserialize(self.deserializer),
+            false => Err(de::Error::invalid_type(Unexpected::UnitVariant, &"newtype variant"))
+        }
+    }
+
+    fn tuple_variant <V: Visitor<'de>> (self, _length: usize, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.map {
+            true => de::Deserializer::deserialize_any(self.deserializer, visitor),
+            false => Err(de::Error::invalid_type(Unexpected::UnitVariant, &"tuple variant"))
+        }
+    }
+
+    fn struct_variant <V: Visitor<'de>> (self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value, SerdeError> {
+        match self.map {
+            true => de::Deserializer::deserialize_any(self.deserializer, visitor),
+            false => Err(de::Error::invalid_type(Unexpected::UnitVariant, &"struct variant"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use super::super::{Extension, to_vec};
+    use super::super::serde_impls::fixtures::{Shape, Drawing, drawing};
+
+    #[test]
+    fn round_trip () {
+        let encoded = to_vec(&drawing()).unwrap();
+        assert_eq!(from_slice::<Drawing>(&encoded).unwrap(), drawing());
+        assert_eq!(from_reader::<_, Drawing>(&encoded[..]).unwrap(), drawing());
+
+        let value = msgpack!({
+            "numbers": [-1, -300, (MsgPack::Uint(7)), 1.5, nil, false],
+            "raw": bin[1, 2, 3],
+            "ext": ext(-1, [0, 0, 0, 1]),
+            (MsgPack::Int(5)): { "nested": "text" }
+        });
+        assert_eq!(from_slice::<MsgPack>(&value.encode()).unwrap(), value);
+    }
+
+    #[derive(Deserialize)]
+    struct Borrowed<'a> {
+        name: &'a str,
+        data: &'a [u8],
+        tags: Vec<&'a str>,
+        #[serde(borrow)]
+        note: std::borrow::Cow<'a, str>,
+    }
+
+    #[test]
+    fn borrowed () {
+        let encoded = msgpack!({
+            "name": "ferris",
+            "data": bin[1, 2, 3],
+            "tags": ["a", "b"],
+            "note": "borrowed too"
+        }).encode();
+
+        let borrowed: Borrowed = from_slice(&encoded).unwrap();
+        let within = |pointer: *const u8| encoded.as_ptr_range().contains(&pointer);
+
+        assert_eq!((borrowed.name, borrowed.data), ("ferris", &[1, 2, 3][..]));
+        assert!(within(borrowed.name.as_ptr()) && within(borrowed.data.as_ptr()) && within(borrowed.tags[1].as_ptr()));
+        assert!(matches!(borrowed.note, std::borrow::Cow::Borrowed("borrowed too")));
+
+        // extension payloads are offered as borrowed bytes as well
+        let extension: Extension = from_slice(&[0xd4, 0x05, 0xff]).unwrap();
+        assert_eq!(extension, Extension { type_id: 5, value: vec![0xff] });
+    }
+
+    #[test]
+    fn errors () {
+        let encoded = to_vec(&drawing()).unwrap();
+
+        let error = from_slice::<Drawing>(&encoded[..encoded.len() - 1]).unwrap_err();
+        // the payload of the extension at the end is cut short
+        assert_eq!(error.byte, Some(encoded.len() - 3));
+
+        let mut trailing = encoded.clone();
+        trailing.push(0xc0);
+        let error = from_slice::<Drawing>(&trailing).unwrap_err();
+        assert_eq!(error.to_string(), format!("MsgPack serde error at byte {}: trailing data", encoded.len()));
+
+        // a string where a number is expected
+        let error = from_slice::<(u8, u8)>(&[0x92, 0x01, 0xa1, 0x78]).unwrap_err();
+        assert_eq!(error.byte, Some(4));
+        assert!(from_slice::<(u8, u8)>(&[0x93, 0x01, 0x02, 0x03]).is_err());
+        assert!(from_slice::<u8>(&[0xcd, 0x01, 0x00]).is_err());
+        assert!(from_slice::<Shape>(&[0x82, 0xa1, 0x78, 0xc0, 0xa1, 0x79, 0xc0]).is_err());
+
+        // a bare name is only a unit variant, even if a value follows it
+        let error = from_slice::<Shape>(&MsgPack::from("Circle").encode()).unwrap_err();
+        assert_eq!(error.message, "invalid type: unit variant, expected newtype variant");
+
+        let encoded = [MsgPack::from(vec![MsgPack::from("Circle")]).encode(), MsgPack::Float(2.5).encode()].concat();
+        assert!(from_slice::<Vec<Shape>>(&encoded).is_err());
+        let encoded = [MsgPack::from(vec![MsgPack::from("Line")]).encode(), MsgPack::from(vec![1, 2]).encode()].concat();
+        assert!(from_slice::<Vec<Shape>>(&encoded).is_err());
+        assert!(from_slice::<Vec<Shape>>(&MsgPack::from(vec![MsgPack::from("Rect")]).encode()).is_err());
+    }
+}
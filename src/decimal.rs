@@ -0,0 +1,271 @@
+//! Decimal numbers, stored as a scale and a packed decimal mantissa
+
+use std::convert::TryFrom;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use super::{MsgPack, Extension};
+use super::error::{ConversionError, TextError};
+use super::parser;
+
+// sign nibbles of packed decimals, the ones written and every one accepted
+const PLUS: u8 = 0x0c;
+const MINUS: u8 = 0x0d;
+const NEGATIVE_SIGNS: [u8; 2] = [0x0b, 0x0d];
+
+/// An exact decimal number, stored as an extension holding a scale and a
+/// mantissa
+/// 
+/// The value is the mantissa divided by ten to the power of the scale, so
+/// 12.34 has a mantissa of 1234 and a scale of 2. The extension starts with
+/// the scale as a MsgPack int, followed by the digits of the mantissa packed
+/// two to a byte, with the sign in the last half byte (`c` for positive, `d`
+/// for negative). This is the layout of Tarantool's decimal type, and the
+/// conversions use its type id, 1. [DecimalCodec](struct.DecimalCodec.html)
+/// can handle any other.
+/// 
+/// The mantissa is an `i128`, which holds any number of up to 38 digits.
+/// Equality compares the mantissa and the scale, so 1.0 and 1.00 are
+/// different values.
+/// 
+///     use std::convert::TryFrom;
+///     use msgpack_simple::{MsgPack, Decimal};
+/// 
+///     let decimal: Decimal = "-12.34".parse().unwrap();
+///     assert_eq!((decimal.mantissa(), decimal.scale()), (-1234, 2));
+/// 
+///     let encoded = MsgPack::from(decimal).encode();
+///     assert_eq!(encoded, vec![0xd6, 0x01, 0x02, 0x01, 0x23, 0x4d]);
+/// 
+///     let decoded = Decimal::try_from(MsgPack::parse(&encoded).unwrap()).unwrap();
+///     assert_eq!(decoded.to_string(), "-12.34");
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Decimal {
+    mantissa: i128,
+    scale: i32,
+}
+
+impl Decimal {
+    /// The extension type id used by the conversions
+    pub const TYPE_ID: i8 = 1;
+
+    /// Creates a decimal with the value `mantissa * 10^-scale`
+    pub fn new (mantissa: i128, scale: i32) -> Decimal {
+        Decimal { mantissa, scale }
+    }
+
+    /// The digits of the value as an integer
+    pub fn mantissa (&self) -> i128 {
+        self.mantissa
+    }
+
+    /// The number of digits after the decimal point, negative if the
+    /// mantissa has to be multiplied instead
+    pub fn scale (&self) -> i32 {
+        self.scale
+    }
+
+    /// Converts the decimal to the bytes of an extension
+    pub fn to_bytes (&self) -> Vec<u8> {
+        // small scales as fixints, large positive ones as uints, as Tarantool
+        // writes them
+        let mut result = match self.scale {
+            scale if scale >= 128 => MsgPack::Uint(scale as u64).encode(),
+            scale => MsgPack::Int(scale as i64).encode()
+        };
+
+        let mut nibbles: Vec<u8> = self.mantissa.unsigned_abs().to_string().bytes().map(|digit| digit - b'0').collect();
+        nibbles.push(if self.mantissa < 0 { MINUS } else { PLUS });
+        if nibbles.len() % 2 == 1 { nibbles.insert(0, 0) }
+
+        result.extend(nibbles.chunks(2).map(|pair| pair[0] << 4 | pair[1]));
+        result
+    }
+
+    /// Reads a decimal from the bytes of an extension, returning `None` if
+    /// they are not valid or the mantissa doesn't fit in an `i128`
+    pub fn from_bytes (bytes: &[u8]) -> Option<Decimal> {
+        let (scale, length) = parser::parse(bytes).ok()?;
+        let scale = match scale {
+            MsgPack::Int(scale) => i32::try_from(scale).ok()?,
+            MsgPack::Uint(scale) => i32::try_from(scale).ok()?,
+            _ => return None
+        };
+
+        let packed = &bytes[length..];
+        let (&last, digits) = packed.split_last()?;
+
+        let negative = match last & 0x0f {
+            sign if NEGATIVE_SIGNS.contains(&sign) => true,
+            sign if sign >= 0x0a => false,
+            _ => return None
+        };
+
+        // negative mantissas are summed up as negative, so i128::MIN fits
+        let mut mantissa: i128 = 0;
+        let nibbles = digits.iter().flat_map(|byte| vec![byte >> 4, byte & 0x0f]).chain(Some(last >> 4));
+
+        for digit in nibbles {
+            if digit > 9 { return None }
+            mantissa = mantissa.checked_mul(10)?;
+            mantissa = if negative { mantissa.checked_sub(digit as i128)? } else { mantissa.checked_add(digit as i128)? };
+        }
+
+        Some(Decimal { mantissa, scale })
+    }
+}
+
+impl From<Decimal> for Extension {
+    fn from (decimal: Decimal) -> Extension {
+        Extension { type_id: Decimal::TYPE_ID, value: decimal.to_bytes() }
+    }
+}
+
+impl From<Decimal> for MsgPack {
+    fn from (decimal: Decimal) -> MsgPack {
+        MsgPack::Extension(decimal.into())
+    }
+}
+
+impl TryFrom<Extension> for Decimal {
+    type Error = ConversionError;
+
+    fn try_from (extension: Extension) -> Result<Decimal, ConversionError> {
+        let decimal = match extension.type_id {
+            Decimal::TYPE_ID => Decimal::from_bytes(&extension.value),
+            _ => None
+        };
+
+        decimal.ok_or(ConversionError::type_error(MsgPack::Extension(extension), "decimal"))
+    }
+}
+
+impl TryFrom<MsgPack> for Decimal {
+    type Error = ConversionError;
+
+    fn try_from (value: MsgPack) -> Result<Decimal, ConversionError> {
+        match value {
+            MsgPack::Extension(extension) => Decimal::try_from(extension),
+            original => Err(ConversionError::type_error(original, "decimal"))
+        }
+    }
+}
+
+/// Writes the decimal with as many digits after the point as the scale, or
+/// with an exponent if the scale is negative, so `12e3` for a mantissa of 12
+/// and a scale of -3
+impl Display for Decimal {
+    fn fmt (&self, f: &mut Formatter) -> fmt::Result {
+        if self.mantissa < 0 { write!(f, "-")? }
+        let digits = self.mantissa.unsigned_abs().to_string();
+
+        if self.scale <= 0 {
+            write!(f, "{}", digits)?;
+            if self.scale < 0 { write!(f, "e{}", -(self.scale as i64))? }
+            return Ok(())
+        }
+
+        let scale = self.scale as usize;
+        let digits = format!("{:0>width$}", digits, width = scale + 1);
+        let point = digits.len() - scale;
+
+        write!(f, "{}.{}", &digits[..point], &digits[point..])
+    }
+}
+
+/// Reads a decimal with an optional sign, decimal point and exponent, such as
+/// `-12.34`, `+5` or `1.5e-3`
+impl FromStr for Decimal {
+    type Err = TextError;
+
+    fn from_str (text: &str) -> Result<Decimal, TextError> {
+        let error = || TextError { text: text.to_string(), attempted: "decimal" };
+
+        let (number, exponent) = match text.find(['e', 'E']) {
+            Some(index) => {
+                let exponent = &text[index + 1..];
+                let digits = exponent.trim_start_matches(['+', '-']);
+                if digits.is_empty() || !digits.bytes().all(|c| c.is_ascii_digit()) { return Err(error()) }
+
+                (&text[..index], exponent.parse::<i64>().map_err(|_| error())?)
+            },
+            None => (text, 0)
+        };
+
+        let (negative, number) = match number.as_bytes().first() {
+            Some(b'-') => (true, &number[1..]),
+            Some(b'+') => (false, &number[1..]),
+            _ => (false, number)
+        };
+
+        let (whole, fraction) = match number.find('.') {
+            Some(index) => (&number[..index], &number[index + 1..]),
+            None => (number, "")
+        };
+
+        let digits = format!("{}{}", whole, fraction);
+        if digits.is_empty() || !digits.bytes().all(|c| c.is_ascii_digit()) { return Err(error()) }
+
+        // parsed with the sign, so i128::MIN fits
+        let digits = if negative { format!("-{}", digits) } else { digits };
+        let mantissa = digits.parse::<i128>().map_err(|_| error())?;
+        let scale = (fraction.len() as i64).checked_sub(exponent).and_then(|scale| i32::try_from(scale).ok()).ok_or_else(error)?;
+
+        Ok(Decimal { mantissa, scale })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encoding () {
+        // from the MP_DECIMAL description in the Tarantool docs
+        let decimal = Decimal::new(-1234, 2);
+        assert_eq!(MsgPack::from(decimal).encode(), vec![0xd6, 0x01, 0x02, 0x01, 0x23, 0x4d]);
+
+        assert_eq!(Decimal::new(0, 0).to_bytes(), vec![0x00, 0x0c]);
+        assert_eq!(Decimal::new(5, -3).to_bytes(), vec![0xfd, 0x5c]);
+
+        let values = [Decimal::new(-1234, 2), Decimal::new(i128::MAX, 7), Decimal::new(-i128::MAX, -7), Decimal::new(i128::MIN, 0), Decimal::new(10, 1), Decimal::new(1, 200)];
+        for value in values.iter() {
+            assert_eq!(Decimal::try_from(Extension::from(*value)).unwrap(), *value);
+        }
+
+        // other sign nibbles, scale as an int8, and a missing pad nibble
+        assert_eq!(Decimal::from_bytes(&[0x02, 0x01, 0x23, 0x4b]), Some(Decimal::new(-1234, 2)));
+        assert_eq!(Decimal::from_bytes(&[0xd0, 0x02, 0x12, 0x3f]), Some(Decimal::new(123, 2)));
+        assert_eq!(Decimal::from_bytes(&[0x00, 0x00, 0x12, 0x3c]), Some(Decimal::new(123, 0)));
+
+        assert_eq!(Decimal::from_bytes(&[0x02]), None);
+        assert_eq!(Decimal::from_bytes(&[0x02, 0x12, 0x34]), None);
+        assert_eq!(Decimal::from_bytes(&[0x02, 0x1a, 0x3c]), None);
+        assert_eq!(Decimal::from_bytes(&[0xc0, 0x1c]), None);
+        assert!(Decimal::try_from(Extension { type_id: 2, value: vec![0x00, 0x0c] }).is_err());
+    }
+
+    #[test]
+    fn text () {
+        let cases = [("-12.34", -1234, 2), ("0.005", 5, 3), ("-0.5", -5, 1), ("12e3", 12, -3), ("1.0", 10, 1), ("7", 7, 0)];
+        for (text, mantissa, scale) in cases.iter() {
+            let decimal: Decimal = text.parse().unwrap();
+            assert_eq!(decimal, Decimal::new(*mantissa, *scale));
+            assert_eq!(decimal.to_string(), *text);
+        }
+
+        assert_eq!("+1.5e-3".parse::<Decimal>().unwrap(), Decimal::new(15, 4));
+        assert_eq!(".5".parse::<Decimal>().unwrap(), Decimal::new(5, 1));
+        assert_eq!(Decimal::new(i128::MIN, 0).to_string().parse::<Decimal>().unwrap(), Decimal::new(i128::MIN, 0));
+        assert!("170141183460469231731687303715884105728".parse::<Decimal>().is_err());
+        assert!("1e-9223372036854775808".parse::<Decimal>().is_err());
+        assert!("1.5e-9223372036854775807".parse::<Decimal>().is_err());
+
+        for text in ["", "-", ".", "1.2.3", "1e", "1e+", "abc", "--1", "1 "].iter() {
+            assert!(text.parse::<Decimal>().is_err(), "{:?} should not parse", text);
+        }
+
+        let error = "1x".parse::<Decimal>().unwrap_err();
+        assert_eq!(error.to_string(), "MsgPack text error: cannot read \"1x\" as decimal");
+    }
+}
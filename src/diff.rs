@@ -0,0 +1,520 @@
+//! Structural diffs between MsgPack values, in the spirit of JSON Patch
+
+use std::convert::TryFrom;
+use std::fmt::{self, Display, Formatter};
+
+use super::{MsgPack, MapElement};
+use super::index::MapKey;
+use super::error::{ConversionError, PathError};
+use super::path::{Path, PathSegment, array_index, child_mut, map_position, segment_key};
+
+/// A single step of a [Patch](struct.Patch.html)
+#[derive(Debug, Clone, PartialEq)]
+pub enum PatchOperation {
+    /// Inserts a value into an array, shifting later elements up, or sets a
+    /// key in a map
+    Add { path: Path, value: MsgPack },
+    /// Removes an existing value
+    Remove { path: Path },
+    /// Replaces an existing value
+    Replace { path: Path, value: MsgPack },
+    /// Removes a value and adds it somewhere else
+    Move { from: Path, path: Path },
+}
+
+impl Display for PatchOperation {
+    fn fmt (&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            PatchOperation::Add { path, value } => write!(f, "add {}: {}", path, value),
+            PatchOperation::Remove { path } => write!(f, "remove {}", path),
+            PatchOperation::Replace { path, value } => write!(f, "replace {}: {}", path, value),
+            PatchOperation::Move { from, path } => write!(f, "move {} to {}", from, path),
+        }
+    }
+}
+
+/// A list of operations that turns one MsgPack value into another, see
+/// [MsgPack::diff](enum.MsgPack.html#method.diff)
+/// 
+/// Operations follow JSON Patch (RFC 6902) and are applied in order, so each
+/// path refers to the value as it is after the previous operations. Patches
+/// display as one operation per line, and can be converted to and from
+/// MsgPack for storage. In that form, a patch is an array of maps like
+/// `{ "op": "add", "path": ["users", 0], "value": ... }`, where paths are
+/// arrays of keys and indexes. Pointer strings are accepted as paths too.
+/// 
+///     use std::convert::TryFrom;
+///     use msgpack_simple::{msgpack, MsgPack, Patch};
+/// 
+///     let stored = MsgPack::from(MsgPack::diff(&msgpack!({ "a": 1 }), &msgpack!({ "a": 2 })));
+///     assert_eq!(stored, msgpack!([{ "op": "replace", "path": ["a"], "value": 2 }]));
+/// 
+///     let patch = Patch::try_from(msgpack!([{ "op": "remove", "path": "/a" }])).unwrap();
+///     assert_eq!(patch.to_string(), "remove /a");
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Patch {
+    operations: Vec<PatchOperation>,
+}
+
+impl Patch {
+    /// Creates an empty patch
+    pub fn new () -> Patch {
+        Patch { operations: vec![] }
+    }
+
+    /// Adds an operation to the end of the patch
+    pub fn push (&mut self, operation: PatchOperation) {
+        self.operations.push(operation);
+    }
+
+    /// The operations of the patch, in order
+    pub fn operations (&self) -> &[PatchOperation] {
+        &self.operations
+    }
+
+    /// Checks if the patch has no operations
+    pub fn is_empty (&self) -> bool {
+        self.operations.is_empty()
+    }
+}
+
+impl Display for Patch {
+    fn fmt (&self, f: &mut Formatter) -> fmt::Result {
+        for (index, operation) in self.operations.iter().enumerate() {
+            if index > 0 { writeln!(f)? }
+            write!(f, "{}", operation)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl From<Vec<PatchOperation>> for Patch {
+    fn from (operations: Vec<PatchOperation>) -> Patch {
+        Patch { operations }
+    }
+}
+
+impl IntoIterator for Patch {
+    type Item = PatchOperation;
+    type IntoIter = std::vec::IntoIter<PatchOperation>;
+
+    fn into_iter (self) -> Self::IntoIter {
+        self.operations.into_iter()
+    }
+}
+
+fn path_to_msgpack (path: &Path) -> MsgPack {
+    MsgPack::Array(path.segments().iter().map(segment_key).collect())
+}
+
+fn path_from_msgpack (value: &MsgPack) -> Option<Path> {
+    match value {
+        MsgPack::String(pointer) => Some(Path::parse(pointer)),
+        MsgPack::Array(segments) => Some(segments.iter().map(|segment| match segment {
+            // an index too large for usize still selects a map entry by key
+            MsgPack::Uint(index) => usize::try_from(*index).map(PathSegment::Index).unwrap_or_else(|_| PathSegment::Key(segment.clone())),
+            key => PathSegment::Key(key.clone()),
+        }).collect::<Vec<_>>().into()),
+        _ => None
+    }
+}
+
+fn entry (key: &str, value: MsgPack) -> MapElement {
+    MapElement { key: MsgPack::String(key.to_string()), value }
+}
+
+impl From<PatchOperation> for MsgPack {
+    fn from (operation: PatchOperation) -> MsgPack {
+        MsgPack::Map(match operation {
+            PatchOperation::Add { path, value } => vec![entry("op", "add".into()), entry("path", path_to_msgpack(&path)), entry("value", value)],
+            PatchOperation::Remove { path } => vec![entry("op", "remove".into()), entry("path", path_to_msgpack(&path))],
+            PatchOperation::Replace { path, value } => vec![entry("op", "replace".into()), entry("path", path_to_msgpack(&path)), entry("value", value)],
+            PatchOperation::Move { from, path } => vec![entry("op", "move".into()), entry("from", path_to_msgpack(&from)), entry("path", path_to_msgpack(&path))],
+        })
+    }
+}
+
+impl From<Patch> for MsgPack {
+    fn from (patch: Patch) -> MsgPack {
+        MsgPack::Array(patch.operations.into_iter().map(MsgPack::from).collect())
+    }
+}
+
+impl TryFrom<MsgPack> for PatchOperation {
+    type Error = ConversionError;
+
+    fn try_from (value: MsgPack) -> Result<PatchOperation, ConversionError> {
+        let path = value.get("path").and_then(path_from_msgpack);
+        let from = value.get("from").and_then(path_from_msgpack);
+        let operand = value.get("value").cloned();
+
+        let operation = match (value.get("op").and_then(|op| op.clone().as_string().ok()).as_deref(), path) {
+            (Some("add"), Some(path)) => operand.map(|value| PatchOperation::Add { path, value }),
+            (Some("remove"), Some(path)) => Some(PatchOperation::Remove { path }),
+            (Some("replace"), Some(path)) => operand.map(|value| PatchOperation::Replace { path, value }),
+            (Some("move"), Some(path)) => from.map(|from| PatchOperation::Move { from, path }),
+            _ => None
+        };
+
+        operation.ok_or(ConversionError::type_error(value, "patch operation"))
+    }
+}
+
+impl TryFrom<MsgPack> for Patch {
+    type Error = ConversionError;
+
+    fn try_from (value: MsgPack) -> Result<Patch, ConversionError> {
+        let operations = value.as_array()?;
+        let operations = operations.into_iter().map(PatchOperation::try_from).collect::<Result<_, _>>()?;
+        Ok(Patch { operations })
+    }
+}
+
+fn diff_node (a: &MsgPack, b: &MsgPack, path: &mut Path, patch: &mut Patch) {
+    if a == b { return }
+
+    match (a, b) {
+        (MsgPack::Map(a), MsgPack::Map(b)) => diff_maps(a, b, path, patch),
+        (MsgPack::Array(a), MsgPack::Array(b)) => {
+            let common = a.len().min(b.len());
+
+            for index in 0..common {
+                path.push(PathSegment::Index(index));
+                diff_node(&a[index], &b[index], path, patch);
+                path.pop();
+            }
+
+            for (index, value) in b.iter().enumerate().skip(common) {
+                patch.push(PatchOperation::Add { path: path.clone().index(index), value: value.clone() });
+            }
+
+            // removed from the end, so the indexes stay valid
+            for index in (common..a.len()).rev() {
+                patch.push(PatchOperation::Remove { path: path.clone().index(index) });
+            }
+        },
+        _ => patch.push(PatchOperation::Replace { path: path.clone(), value: b.clone() })
+    }
+}
+
+// how many entries of the map have the key
+fn count (map: &[MapElement], key: &MsgPack) -> usize {
+    map.iter().filter(|element| key.matches_key(&element.key)).count()
+}
+
+// checks if an earlier entry has the same key, so lookups never reach it
+fn shadowed (map: &[MapElement], index: usize) -> bool {
+    map[..index].iter().any(|element| element.key.matches_key(&map[index].key))
+}
+
+fn diff_maps (a: &[MapElement], b: &[MapElement], path: &mut Path, patch: &mut Patch) {
+    // a removed key takes all its entries with it
+    let mut removed = vec![];
+
+    for (index, element) in a.iter().enumerate() {
+        match b.iter().find(|other| other.key.matches_key(&element.key)) {
+            Some(_) if shadowed(a, index) => {},
+            Some(other) => {
+                path.push(PathSegment::Key(element.key.clone()));
+                diff_node(&element.value, &other.value, path, patch);
+                path.pop();
+            },
+            None => removed.push(element)
+        }
+    }
+
+    let added: Vec<_> = b.iter().enumerate()
+        .filter(|&(index, element)| !shadowed(b, index) && count(a, &element.key) == 0)
+        .map(|(_, element)| element)
+        .collect();
+
+    // a removed key with the same value as an added one was renamed
+    let mut moved = vec![false; removed.len()];
+    let mut additions = vec![];
+
+    for element in added {
+        let movable = |(index, old): (usize, &&MapElement)| !moved[index] && old.value == element.value && count(a, &old.key) == 1;
+
+        match removed.iter().enumerate().position(movable) {
+            Some(index) => {
+                moved[index] = true;
+                patch.push(PatchOperation::Move {
+                    from: path.clone().key(removed[index].key.clone()),
+                    path: path.clone().key(element.key.clone())
+                });
+            },
+            None => additions.push(element)
+        }
+    }
+
+    for (index, element) in removed.iter().enumerate() {
+        if !moved[index] { patch.push(PatchOperation::Remove { path: path.clone().key(element.key.clone()) }) }
+    }
+
+    for element in additions {
+        patch.push(PatchOperation::Add { path: path.clone().key(element.key.clone()), value: element.value.clone() });
+    }
+}
+
+fn error (path: &Path, depth: usize, reason: &str) -> PathError {
+    PathError { path: path.clone(), depth, reason: reason.to_string() }
+}
+
+// the parent of the value the path points to, and the last segment
+fn parent_mut <'a> (root: &'a mut MsgPack, path: &'a Path) -> Result<(&'a mut MsgPack, &'a PathSegment), PathError> {
+    let (last, segments) = match path.segments().split_last() {
+        Some(split) => split,
+        None => return Err(error(path, 0, "the root has no parent"))
+    };
+
+    let mut node = root;
+    for (depth, segment) in segments.iter().enumerate() {
+        node = child_mut(node, segment).ok_or_else(|| error(path, depth, "value not found"))?;
+    }
+
+    Ok((node, last))
+}
+
+fn add (root: &mut MsgPack, path: &Path, value: MsgPack) -> Result<(), PathError> {
+    if path.is_root() {
+        *root = value;
+        return Ok(())
+    }
+
+    let depth = path.segments().len() - 1;
+    let (parent, last) = parent_mut(root, path)?;
+
+    match parent {
+        MsgPack::Map(map) => match map_position(map, last) {
+            Some(position) => map[position].value = value,
+            None => map.push(MapElement { key: segment_key(last), value })
+        },
+        MsgPack::Array(array) => {
+            let index = match last {
+                PathSegment::Key(MsgPack::String(token)) if token == "-" => Some(array.len()),
+                _ => array_index(last)
+            };
+
+            match index {
+                Some(index) if index <= array.len() => array.insert(index, value),
+                Some(index) => {
+                    let reason = format!("index {} is out of bounds for an array of length {}", index, array.len());
+                    return Err(error(path, depth, &reason))
+                },
+                None => return Err(error(path, depth, "array index expected"))
+            }
+        },
+        other => return Err(error(path, depth, &format!("cannot go into {}", other.type_name())))
+    }
+
+    Ok(())
+}
+
+fn remove (root: &mut MsgPack, path: &Path) -> Result<MsgPack, PathError> {
+    let depth = path.segments().len().saturating_sub(1);
+    let (parent, last) = parent_mut(root, path)?;
+
+    let removed = match parent {
+        MsgPack::Map(map) => map_position(map, last).map(|position| map.remove(position).value),
+        MsgPack::Array(array) => match array_index(last) {
+            Some(index) if index < array.len() => Some(array.remove(index)),
+            _ => None
+        },
+        _ => None
+    };
+
+    removed.ok_or_else(|| error(path, depth, "value not found"))
+}
+
+fn apply (root: &mut MsgPack, operation: PatchOperation) -> Result<(), PathError> {
+    match operation {
+        PatchOperation::Add { path, value } => add(root, &path, value),
+        PatchOperation::Remove { path } => remove(root, &path).map(|_| ()),
+        PatchOperation::Replace { path, value } => {
+            let mut node = root;
+            for (depth, segment) in path.segments().iter().enumerate() {
+                node = child_mut(node, segment).ok_or_else(|| error(&path, depth, "value not found"))?;
+            }
+
+            *node = value;
+            Ok(())
+        },
+        PatchOperation::Move { from, path } => {
+            if path.segments().len() > from.segments().len() && path.segments().starts_with(from.segments()) {
+                return Err(error(&path, from.segments().len(), "cannot move a value into itself"))
+            }
+
+            let value = remove(root, &from)?;
+            add(root, &path, value)
+        }
+    }
+}
+
+impl MsgPack {
+    /// Computes the operations that turn one value into another
+    /// 
+    /// Maps are compared key by key and arrays index by index. Keys that are
+    /// removed and added with the same value in the same map are reported as
+    /// moves. Everything else that differs is replaced.
+    /// 
+    /// Keys are matched the way [get](#method.get) and
+    /// [apply_patch](#method.apply_patch) match them, so an int key and a uint
+    /// key with the same value are the same entry. The order of keys in a map
+    /// is not part of the diff either, added keys end up at the end of the
+    /// map. Applying the patch to this value therefore gives a value that is
+    /// [semantic_eq](#method.semantic_eq) to the other one, but not always
+    /// `==` to it, and the patch is empty if the values only differ in key
+    /// order or int and uint keys.
+    /// 
+    /// Duplicate keys are diffed the way lookups see them: only the first
+    /// entry of a key is compared, and a removed key is removed with all its
+    /// entries. With duplicates, the patched value gives the same lookups as
+    /// the other one, but is not always semantic_eq to it.
+    /// 
+    ///     use msgpack_simple::{msgpack, MsgPack};
+    /// 
+    ///     let old = msgpack!({ "name": "Ferris", "nick": "crab", "tags": ["a", "b"] });
+    ///     let new = msgpack!({ "name": "Corro", "tags": ["a"], "alias": "crab", "age": 7 });
+    ///     let patch = MsgPack::diff(&old, &new);
+    /// 
+    ///     assert_eq!(patch.to_string(), [
+    ///         "replace /name: \"Corro\"",
+    ///         "remove /tags/1",
+    ///         "move /nick to /alias",
+    ///         "add /age: 7",
+    ///     ].join("\n"));
+    /// 
+    ///     let mut patched = old.clone();
+    ///     patched.apply_patch(&patch).unwrap();
+    ///     assert_eq!(patched, new);
+    pub fn diff (&self, other: &MsgPack) -> Patch {
+        let mut patch = Patch::new();
+        diff_node(self, other, &mut Path::root(), &mut patch);
+        patch
+    }
+
+    /// Applies the operations of a patch in order
+    /// 
+    /// If an operation fails, for example because the value it refers to is
+    /// missing, the error is returned and this value is left unchanged.
+    /// 
+    ///     use msgpack_simple::{msgpack, Patch, PatchOperation, Path};
+    /// 
+    ///     let mut message = msgpack!({ "list": [1, 3] });
+    ///     let patch = Patch::from(vec![
+    ///         PatchOperation::Add { path: Path::parse("/list/1"), value: msgpack!(2) },
+    ///         PatchOperation::Remove { path: Path::parse("/missing") },
+    ///     ]);
+    /// 
+    ///     assert!(message.apply_patch(&patch).is_err());
+    ///     assert_eq!(message, msgpack!({ "list": [1, 3] }));
+    pub fn apply_patch (&mut self, patch: &Patch) -> Result<(), PathError> {
+        let mut result = self.clone();
+
+        for operation in &patch.operations {
+            apply(&mut result, operation.clone())?;
+        }
+
+        *self = result;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map (entries: Vec<(&str, MsgPack)>) -> MsgPack {
+        MsgPack::Map(entries.into_iter().map(|(key, value)| entry(key, value)).collect())
+    }
+
+    #[test]
+    fn diff_and_apply () {
+        let pairs = vec![
+            (MsgPack::Int(1), MsgPack::Int(1)),
+            (MsgPack::Int(1), MsgPack::Uint(1)),
+            (MsgPack::from(vec![1, 2, 3]), MsgPack::from(vec![1])),
+            (MsgPack::from(vec![1]), MsgPack::from(vec![0, 1, 2])),
+            (map(vec![("a", MsgPack::Int(1)), ("b", MsgPack::Int(1))]), map(vec![("c", MsgPack::Int(1)), ("d", MsgPack::Int(1))])),
+            (map(vec![("a", MsgPack::from(vec![map(vec![("x", MsgPack::Nil)])]))]), map(vec![("a", MsgPack::from(vec![map(vec![("y", MsgPack::Nil)]), MsgPack::Nil]))])),
+            (MsgPack::Map(vec![MapElement { key: MsgPack::Uint(3), value: MsgPack::Nil }]), MsgPack::Map(vec![MapElement { key: MsgPack::Uint(4), value: MsgPack::Nil }])),
+        ];
+
+        for (a, b) in pairs {
+            let patch = MsgPack::diff(&a, &b);
+            assert_eq!(patch.is_empty(), a == b);
+
+            let mut patched = a.clone();
+            patched.apply_patch(&patch).unwrap();
+            assert_eq!(patched, b);
+
+            // uint keys come back as indexes, which select the same entries
+            let stored = Patch::try_from(MsgPack::from(patch)).unwrap();
+            let mut patched = a.clone();
+            patched.apply_patch(&stored).unwrap();
+            assert_eq!(patched, b);
+        }
+
+        let patch = MsgPack::diff(&map(vec![("a", MsgPack::Int(1)), ("b", MsgPack::Int(1))]), &map(vec![("c", MsgPack::Int(1)), ("b", MsgPack::Int(1))]));
+        assert_eq!(patch.operations(), &[PatchOperation::Move { from: Path::root().key("a"), path: Path::root().key("c") }]);
+    }
+
+    #[test]
+    fn loose_keys () {
+        let a = map(vec![("a", MsgPack::Int(1)), ("b", MsgPack::Int(2))]);
+        let b = map(vec![("b", MsgPack::Int(2)), ("a", MsgPack::Int(1))]);
+        assert!(a != b && a.semantic_eq(&b));
+        assert!(MsgPack::diff(&a, &b).is_empty());
+
+        let a = MsgPack::Map(vec![MapElement { key: MsgPack::Int(1), value: MsgPack::from(vec![1]) }, entry("x", MsgPack::Nil)]);
+        let b = MsgPack::Map(vec![entry("y", MsgPack::Nil), MapElement { key: MsgPack::Uint(1), value: MsgPack::from(vec![2]) }]);
+        let patch = MsgPack::diff(&a, &b);
+        assert_eq!(patch.operations(), &[
+            PatchOperation::Replace { path: Path::root().key(MsgPack::Int(1)).index(0), value: MsgPack::Int(2) },
+            PatchOperation::Move { from: Path::root().key("x"), path: Path::root().key("y") },
+        ]);
+
+        let mut patched = a.clone();
+        patched.apply_patch(&patch).unwrap();
+        assert!(patched != b && patched.semantic_eq(&b));
+    }
+
+    #[test]
+    fn duplicate_keys () {
+        let cases = vec![
+            (map(vec![("a", MsgPack::Int(1))]), map(vec![("a", MsgPack::Int(1)), ("a", MsgPack::Int(2))]), vec![]),
+            (map(vec![("a", MsgPack::Int(1)), ("a", MsgPack::Int(2))]), map(vec![("a", MsgPack::Int(1))]), vec![]),
+            (map(vec![]), map(vec![("n", MsgPack::Int(1)), ("n", MsgPack::Int(2))]), vec!["add /n: 1"]),
+            (map(vec![("x", MsgPack::Int(2)), ("x", MsgPack::Int(1))]), map(vec![("y", MsgPack::Int(1))]), vec!["remove /x", "remove /x", "add /y: 1"]),
+        ];
+
+        for (a, b, operations) in cases {
+            let patch = MsgPack::diff(&a, &b);
+            assert_eq!(patch.to_string(), operations.join("\n"));
+
+            let mut patched = a.clone();
+            patched.apply_patch(&patch).unwrap();
+            for element in b.clone().as_map().unwrap().into_iter().chain(patched.clone().as_map().unwrap()) {
+                assert_eq!(patched.get(&element.key), b.get(&element.key));
+            }
+        }
+    }
+
+    #[test]
+    fn apply_errors () {
+        let mut message = map(vec![("a", MsgPack::from(vec![1]))]);
+
+        let error = message.apply_patch(&Patch::from(vec![PatchOperation::Add { path: Path::parse("/a/5"), value: MsgPack::Nil }])).unwrap_err();
+        assert_eq!(error.to_string(), "MsgPack path error at /a/5: index 5 is out of bounds for an array of length 1");
+
+        let error = message.apply_patch(&Patch::from(vec![PatchOperation::Replace { path: Path::parse("/b/c"), value: MsgPack::Nil }])).unwrap_err();
+        assert_eq!(error.depth, 0);
+
+        let error = message.apply_patch(&Patch::from(vec![PatchOperation::Move { from: Path::parse("/a"), path: Path::parse("/a/0") }])).unwrap_err();
+        assert_eq!(error.reason, "cannot move a value into itself");
+
+        assert!(Patch::try_from(MsgPack::from(vec![map(vec![("op", MsgPack::from("copy"))])])).is_err());
+        assert!(Patch::try_from(MsgPack::from(vec![map(vec![("op", MsgPack::from("add")), ("path", MsgPack::from("/a"))])])).is_err());
+    }
+}
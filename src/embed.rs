@@ -0,0 +1,191 @@
+//! MsgPack documents embedded in extension and binary payloads
+
+use std::cell::OnceCell;
+use std::fmt::{self, Debug, Formatter};
+
+use super::{MsgPack, Extension};
+use super::convert::Binary;
+use super::error::ParseError;
+use super::parser;
+
+// parses a payload that has to be exactly one MsgPack value
+fn parse_payload (raw: &[u8]) -> Result<MsgPack, ParseError> {
+    let (result, length) = parser::parse(raw)?;
+    if length != raw.len() { return Err(ParseError { byte: length }) }
+    Ok(result)
+}
+
+impl Extension {
+    /// Creates an extension holding an encoded MsgPack document
+    /// 
+    ///     use msgpack_simple::{msgpack, Extension};
+    /// 
+    ///     let inner = msgpack!({ "to": "billing", "amount": 300 });
+    ///     let envelope = Extension::wrap(20, &inner);
+    /// 
+    ///     assert_eq!(envelope.type_id, 20);
+    ///     assert_eq!(envelope.value, inner.encode());
+    ///     assert_eq!(envelope.unwrap_msgpack().unwrap(), inner);
+    pub fn wrap (type_id: i8, value: &MsgPack) -> Extension {
+        Extension { type_id, value: value.encode() }
+    }
+
+    /// Parses the payload as a MsgPack document
+    /// 
+    /// The payload has to be exactly one value, so trailing data is an error,
+    /// pointing to the first byte after the value.
+    pub fn unwrap_msgpack (&self) -> Result<MsgPack, ParseError> {
+        parse_payload(&self.value)
+    }
+
+    /// Wraps the payload to be parsed as a MsgPack document on first access,
+    /// see [LazyMsgPack](struct.LazyMsgPack.html)
+    pub fn lazy_msgpack (&self) -> LazyMsgPack {
+        LazyMsgPack::new(self.value.clone())
+    }
+}
+
+impl Binary {
+    /// Creates a binary holding an encoded MsgPack document
+    /// 
+    ///     use msgpack_simple::{MsgPack, Binary};
+    /// 
+    ///     let wrapped = Binary::wrap(&MsgPack::String("hello".to_string()));
+    ///     assert_eq!(wrapped.0, vec![0xa5, 0x68, 0x65, 0x6c, 0x6c, 0x6f]);
+    ///     assert_eq!(wrapped.unwrap_msgpack().unwrap().as_string().unwrap(), "hello");
+    pub fn wrap (value: &MsgPack) -> Binary {
+        Binary(value.encode())
+    }
+
+    /// Parses the binary as a MsgPack document, with the same rules as
+    /// [Extension.unwrap_msgpack()](struct.Extension.html#method.unwrap_msgpack)
+    pub fn unwrap_msgpack (&self) -> Result<MsgPack, ParseError> {
+        parse_payload(&self.0)
+    }
+
+    /// Wraps the binary to be parsed as a MsgPack document on first access,
+    /// see [LazyMsgPack](struct.LazyMsgPack.html)
+    pub fn lazy_msgpack (&self) -> LazyMsgPack {
+        LazyMsgPack::new(self.0.clone())
+    }
+}
+
+/// An embedded MsgPack document that is only parsed when first accessed
+/// 
+/// The raw bytes are always available, so a document can be forwarded as it
+/// is without decoding it. The first call to `get()` parses it and keeps the
+/// result, so later calls return the same value or error without parsing
+/// again.
+/// 
+///     use msgpack_simple::{msgpack, MsgPack, Extension};
+/// 
+///     let envelope = Extension::wrap(20, &msgpack!([1, 2, 3]));
+///     let lazy = envelope.lazy_msgpack();
+/// 
+///     assert_eq!(lazy.raw(), &[0x93, 0x01, 0x02, 0x03][..]);
+///     assert!(!lazy.is_parsed());
+/// 
+///     assert_eq!(lazy.get().unwrap()[1], MsgPack::Int(2));
+///     assert!(lazy.is_parsed());
+#[derive(Clone)]
+pub struct LazyMsgPack {
+    raw: Vec<u8>,
+    parsed: OnceCell<Result<MsgPack, ParseError>>,
+}
+
+impl LazyMsgPack {
+    /// Creates a lazy document from its encoded bytes
+    pub fn new (raw: Vec<u8>) -> LazyMsgPack {
+        LazyMsgPack { raw, parsed: OnceCell::new() }
+    }
+
+    /// The encoded bytes of the document
+    pub fn raw (&self) -> &[u8] {
+        &self.raw
+    }
+
+    /// Checks if the document has been parsed already
+    pub fn is_parsed (&self) -> bool {
+        self.parsed.get().is_some()
+    }
+
+    /// Parses the document if it hasn't been parsed yet, and returns the
+    /// result
+    pub fn get (&self) -> Result<&MsgPack, ParseError> {
+        self.parsed.get_or_init(|| parse_payload(&self.raw)).as_ref().map_err(Clone::clone)
+    }
+
+    /// Consumes the lazy document and returns the parsed value
+    pub fn into_inner (self) -> Result<MsgPack, ParseError> {
+        match self.parsed.into_inner() {
+            Some(result) => result,
+            None => parse_payload(&self.raw)
+        }
+    }
+
+    /// Consumes the lazy document and returns the encoded bytes
+    pub fn into_raw (self) -> Vec<u8> {
+        self.raw
+    }
+}
+
+impl Debug for LazyMsgPack {
+    fn fmt (&self, f: &mut Formatter) -> fmt::Result {
+        match self.parsed.get() {
+            Some(Ok(value)) => write!(f, "LazyMsgPack({:?})", value),
+            Some(Err(error)) => write!(f, "LazyMsgPack({:?})", error),
+            None => write!(f, "LazyMsgPack(unparsed: {})", hex::encode(&self.raw))
+        }
+    }
+}
+
+impl From<LazyMsgPack> for Binary {
+    fn from (lazy: LazyMsgPack) -> Binary {
+        Binary(lazy.raw)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::MapElement;
+
+    fn message () -> MsgPack {
+        MsgPack::Map(vec![
+            MapElement { key: MsgPack::String("id".to_string()), value: MsgPack::Int(7) },
+            MapElement { key: MsgPack::String("body".to_string()), value: MsgPack::Binary(vec![1, 2]) },
+        ])
+    }
+
+    #[test]
+    fn wrapping () {
+        let inner = message();
+        let envelope = MsgPack::Array(vec![MsgPack::Extension(Extension::wrap(20, &inner)), MsgPack::from(Binary::wrap(&inner))]);
+        let decoded = MsgPack::parse(&envelope.encode()).unwrap();
+
+        assert_eq!(decoded[0].clone().as_extension().unwrap().unwrap_msgpack().unwrap(), inner);
+        assert_eq!(Binary(decoded[1].clone().as_binary().unwrap()).unwrap_msgpack().unwrap(), inner);
+
+        // truncated and trailing data
+        assert_eq!(Binary(vec![0x92, 0x01]).unwrap_msgpack().unwrap_err().byte, 2);
+        assert_eq!(Binary(vec![0x01, 0x02]).unwrap_msgpack().unwrap_err().byte, 1);
+        assert_eq!(Extension { type_id: 1, value: vec![] }.unwrap_msgpack().unwrap_err().byte, 0);
+    }
+
+    #[test]
+    fn lazy () {
+        let lazy = Extension::wrap(20, &message()).lazy_msgpack();
+        assert!(!lazy.is_parsed());
+        assert_eq!(format!("{:?}", lazy), format!("LazyMsgPack(unparsed: {})", hex::encode(message().encode())));
+
+        assert_eq!(lazy.get().unwrap(), &message());
+        assert!(lazy.is_parsed());
+        assert!(lazy.clone().is_parsed());
+        assert_eq!(lazy.into_inner().unwrap(), message());
+
+        let broken = Binary(vec![0xc1]).lazy_msgpack();
+        assert_eq!(broken.get().unwrap_err().byte, 0);
+        assert!(broken.is_parsed());
+        assert_eq!(Binary::from(broken).0, vec![0xc1]);
+    }
+}
@@ -1,59 +1,76 @@
 use std::fmt::{self, Display, Debug, Formatter};
 use std::error::Error;
 
-use super::{MsgPack};
+use super::{MsgPack, parser};
+use super::path::{Path, PathSegment};
 
 /// An error that occurred when trying to access a field as a different type
 /// 
 /// The "as_type" functions of [MsgPack](enum.MsgPack.html) can throw this
 /// error. It contains the original object and a string representation of the
 /// attempted conversion.
+/// 
+/// Checked numeric conversions (`TryFrom<MsgPack>` for the primitive types and
+/// `as_some_int()`/`as_some_uint()`) use the name of the target type, such as
+/// `"u8"`, and `ConversionKind::Range` when the value is a number that does not
+/// fit, instead of wrapping it.
+/// 
+/// More fields may be added later, so outside of this crate the error is
+/// created with `type_error` or `range_error`.
+#[non_exhaustive]
 pub struct ConversionError {
     /// The original object, owned.
     pub original: MsgPack,
     /// A string that contains which type conversion was attempted.
     pub attempted: &'static str,
+    /// Whether the type of the value or its range was the problem.
+    pub kind: ConversionKind,
+}
+
+/// Why a [ConversionError](struct.ConversionError.html) happened
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConversionKind {
+    /// The value has a type that can't be converted to the attempted one
+    Type,
+    /// The value is a number, but it does not fit into the attempted type
+    Range,
 }
 
 impl Display for ConversionError {
     fn fmt (&self, f: &mut Formatter) -> fmt::Result {
-        let original_type = match self.original {
-            MsgPack::Nil => "nil",
-            MsgPack::Int(_) => "int",
-            MsgPack::Uint(_) => "uint",
-            MsgPack::Float(_) => "float",
-            MsgPack::Boolean(_) => "boolean",
-            MsgPack::String(_) => "string",
-            MsgPack::Binary(_) => "binary",
-            MsgPack::Array(_) => "array",
-            MsgPack::Map(_) => "map",
-            MsgPack::Extension(_) => "extension",
-        };
-        write!(f, "MsgPack conversion error: cannot use {} as {}", original_type, self.attempted)
+        write!(f, "MsgPack conversion error: ")?;
+        self.describe(f)
     }
 }
 
 impl Debug for ConversionError {
     fn fmt (&self, f: &mut Formatter) -> fmt::Result {
-        let original_type = match self.original {
-            MsgPack::Nil => "nil",
-            MsgPack::Int(_) => "int",
-            MsgPack::Uint(_) => "uint",
-            MsgPack::Float(_) => "float",
-            MsgPack::Boolean(_) => "boolean",
-            MsgPack::String(_) => "string",
-            MsgPack::Binary(_) => "binary",
-            MsgPack::Array(_) => "array",
-            MsgPack::Map(_) => "map",
-            MsgPack::Extension(_) => "extension",
-        };
-        write!(f, "MsgPack conversion error: cannot use {} as {} (original value: {:?})", original_type, self.attempted, self.original)
+        write!(f, "MsgPack conversion error: ")?;
+        self.describe(f)?;
+        write!(f, " (original value: {:?})", self.original)
     }
 }
 
 impl Error for ConversionError {}
 
 impl ConversionError {
+    /// Creates an error for a value that has a type that can't be converted
+    /// to the attempted one
+    /// 
+    ///     use msgpack_simple::{MsgPack, ConversionError};
+    /// 
+    ///     let error = ConversionError::type_error(MsgPack::Nil, "string");
+    ///     assert_eq!(error.to_string(), "MsgPack conversion error: cannot use nil as string");
+    pub fn type_error (original: MsgPack, attempted: &'static str) -> ConversionError {
+        ConversionError { original, attempted, kind: ConversionKind::Type }
+    }
+
+    /// Creates an error for a number that does not fit into the attempted
+    /// type
+    pub fn range_error (original: MsgPack, attempted: &'static str) -> ConversionError {
+        ConversionError { original, attempted, kind: ConversionKind::Range }
+    }
+
     /// Recovers the MsgPack object from the error
     /// 
     ///     use msgpack_simple::MsgPack;
@@ -67,9 +84,90 @@ impl ConversionError {
     pub fn recover (self) -> MsgPack {
         self.original
     }
+
+    /// Checks if the conversion failed because the number did not fit into
+    /// the target type, as opposed to it not being a number at all
+    /// 
+    ///     use std::convert::TryFrom;
+    ///     use msgpack_simple::MsgPack;
+    /// 
+    ///     let error = u8::try_from(MsgPack::Int(-1)).unwrap_err();
+    ///     assert!(error.is_range_error());
+    /// 
+    ///     let error = u8::try_from(MsgPack::Nil).unwrap_err();
+    ///     assert!(!error.is_range_error());
+    pub fn is_range_error (&self) -> bool {
+        self.kind == ConversionKind::Range
+    }
+
+    fn describe (&self, f: &mut Formatter) -> fmt::Result {
+        if !self.is_range_error() {
+            return write!(f, "cannot use {} as {}", self.original.type_name(), self.attempted)
+        }
+
+        match self.original {
+            MsgPack::Int(value) if value < 0 && self.attempted.starts_with('u') => {
+                write!(f, "cannot use negative int {} as {}", value, self.attempted)
+            },
+            MsgPack::Int(value) => write!(f, "int {} is out of range for {}", value, self.attempted),
+            MsgPack::Uint(value) => write!(f, "uint {} is out of range for {}", value, self.attempted),
+            MsgPack::Float(value) => write!(f, "float {} is out of range for {}", value, self.attempted),
+            _ => write!(f, "{} is out of range for {}", self.original.type_name(), self.attempted)
+        }
+    }
+}
+
+/// An error that occurred while converting a MsgPack value with
+/// [FromMsgPack](trait.FromMsgPack.html)
+/// 
+/// Holds the conversion that failed, along with the path of the value it
+/// failed on, relative to the value that was being converted.
+/// 
+///     use msgpack_simple::{msgpack, FromMsgPack, Path};
+/// 
+///     let error = Vec::<(String, u8)>::from_msgpack(msgpack!([["a", 1], ["b", -1]])).unwrap_err();
+///     assert_eq!(error.path, Path::root().index(1).index(1));
+///     assert_eq!(error.to_string(), "MsgPack conversion error at /1/1: cannot use negative int -1 as u8");
+#[derive(Debug)]
+pub struct FromMsgPackError {
+    /// The conversion that failed
+    pub error: ConversionError,
+    /// Location of the value that couldn't be converted
+    pub path: Path,
+}
+
+impl FromMsgPackError {
+    /// Moves the error down into a child of the value, for implementations
+    /// that convert their children
+    pub fn within (mut self, segment: PathSegment) -> FromMsgPackError {
+        self.path.prepend(segment);
+        self
+    }
+}
+
+impl From<ConversionError> for FromMsgPackError {
+    fn from (error: ConversionError) -> FromMsgPackError {
+        FromMsgPackError { error, path: Path::root() }
+    }
+}
+
+impl Display for FromMsgPackError {
+    fn fmt (&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "MsgPack conversion error")?;
+        if !self.path.is_root() { write!(f, " at {}", self.path)? }
+        write!(f, ": ")?;
+        self.error.describe(f)
+    }
+}
+
+impl Error for FromMsgPackError {
+    fn source (&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
 }
 
 /// An error that occurred while parsing a binary as MsgPack
+#[derive(Clone, PartialEq, Eq)]
 pub struct ParseError {
     /// The byte where the error was found
     pub byte: usize
@@ -115,6 +213,224 @@ impl ParseError {
     pub fn offset_result <T> (result: Result<T, ParseError>, value: usize) -> Result<T, ParseError> {
         result.map_err(|err| err.offset(value))
     }
+
+    /// Renders the error as a hexdump of the input around the offending byte,
+    /// marked with a caret, along with a description of the marker that was
+    /// being decoded. `input` should be the same data that failed to parse.
+    /// 
+    ///     use msgpack_simple::MsgPack;
+    /// 
+    ///     let data = vec![0x92, 0xc3, 0xda, 0x03, 0x2c, 0x61, 0x62];
+    ///     let error = MsgPack::parse(&data).unwrap_err();
+    ///     let rendered = error.render(&data);
+    /// 
+    ///     assert!(rendered.contains("str16 declared 812 bytes, 2 available"));
+    ///     println!("{}", rendered);
+    pub fn render (&self, input: &[u8]) -> String {
+        let mut result = format!("{}", self);
+
+        match parser::explain(input) {
+            Some(explanation) => {
+                let location = match input.get(explanation.marker) {
+                    Some(_) => format!(" (marker at byte {})", explanation.marker),
+                    None => String::new(),
+                };
+                result.push_str(&format!(": {}{}", explanation.message, location));
+            },
+            None => result.push_str(": input parses without errors, it may not be the data that caused this error"),
+        }
+        result.push('\n');
+
+        // show two rows before and one row after the offending byte
+        let error_row = self.byte / 16;
+        let first_row = error_row.saturating_sub(2);
+        let last_row = error_row + 1;
+
+        for row in first_row..=last_row {
+            let start = row * 16;
+            if start > input.len() || (start == input.len() && row != error_row) { break }
+
+            let end = std::cmp::min(start + 16, input.len());
+            let bytes = &input[start..end];
+
+            let mut hex = String::new();
+            for (index, byte) in bytes.iter().enumerate() {
+                if index == 8 { hex.push(' ') }
+                hex.push_str(&format!("{:02x} ", byte));
+            }
+
+            let ascii: String = bytes.iter()
+                .map(|&byte| if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' })
+                .collect();
+
+            result.push_str(&format!("\n{:08x}  {:<49} |{}|", start, hex, ascii));
+
+            if row == error_row {
+                let column = self.byte - start;
+                let padding = 10 + column * 3 + if column >= 8 { 1 } else { 0 };
+                result.push_str(&format!("\n{}^^", " ".repeat(padding)));
+                if self.byte >= input.len() { result.push_str(" end of data") }
+            }
+        }
+
+        result
+    }
+}
+
+/// A problem found while parsing data with
+/// [parser::parse_partial](parser/fn.parse_partial.html)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartialError {
+    /// The error itself, with the byte where it was found
+    pub error: ParseError,
+    /// Location of the affected node in the recovered value, as indexes from
+    /// the root. Arrays are indexed by element and maps by entry.
+    pub path: Vec<usize>,
+    /// True if the node was replaced with a Nil placeholder, false if it is an
+    /// array or map that was cut short
+    pub placeholder: bool,
+    /// True if the problem is in the key of the map entry the path ends at,
+    /// rather than in its value. The map is cut short before that entry.
+    pub key: bool,
+}
+
+impl Display for PartialError {
+    fn fmt (&self, f: &mut Formatter) -> fmt::Result {
+        let path: Vec<String> = self.path.iter().map(|index| index.to_string()).collect();
+
+        if self.key {
+            return write!(f, "{} (key of entry /{} unreadable, map truncated)", self.error, path.join("/"))
+        }
+
+        let action = if self.placeholder { "replaced with nil" } else { "truncated" };
+        write!(f, "{} (node /{} {})", self.error, path.join("/"), action)
+    }
+}
+
+impl Error for PartialError {}
+
+/// An error that occurred while setting a value by its path
+/// 
+/// [MsgPack.set_path()](enum.MsgPack.html#method.set_path) returns this if
+/// the path leads through a value that is not a map or an array, or points
+/// beyond the end of an array.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathError {
+    /// The path that was being set
+    pub path: Path,
+    /// The number of segments that could be followed before the error
+    pub depth: usize,
+    /// What went wrong at that point
+    pub reason: String,
+}
+
+impl Display for PathError {
+    fn fmt (&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "MsgPack path error at {}: {}", self.path, self.reason)
+    }
+}
+
+impl Error for PathError {}
+
+/// An extension with a reserved type id that the MessagePack spec doesn't
+/// define
+/// 
+/// Type ids from -128 to -1 are reserved for the spec, and only -1, the
+/// timestamp, is defined so far. Depending on the
+/// [ReservedPolicy](enum.ReservedPolicy.html), encoding fails with this, or
+/// it's returned as a warning along with the result.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReservedExtension {
+    /// The reserved type id
+    pub type_id: i8,
+    /// Location of the extension in the value, or of the map entry if it is
+    /// part of a key
+    pub path: Path,
+}
+
+impl Display for ReservedExtension {
+    fn fmt (&self, f: &mut Formatter) -> fmt::Result {
+        if self.path.is_root() {
+            write!(f, "MsgPack reserved extension type {} at the root", self.type_id)
+        } else {
+            write!(f, "MsgPack reserved extension type {} at {}", self.type_id, self.path)
+        }
+    }
+}
+
+impl Error for ReservedExtension {}
+
+/// An error that occurred while reading a value from its text form
+/// 
+/// Returned by the `FromStr` implementations of the extension value types,
+/// such as [Uuid](struct.Uuid.html) and [Decimal](struct.Decimal.html).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextError {
+    /// The text that could not be read
+    pub text: String,
+    /// The type that was attempted
+    pub attempted: &'static str,
+}
+
+impl Display for TextError {
+    fn fmt (&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "MsgPack text error: cannot read {:?} as {}", self.text, self.attempted)
+    }
+}
+
+impl Error for TextError {}
+
+/// An error that occurred while serializing or deserializing with serde
+/// 
+/// Errors found in the input of a deserializer carry the byte where they were
+/// found, the others, such as an I/O error or a type that can't be
+/// serialized, don't. Errors from converting to and from a `MsgPack` tree
+/// carry the path of the value that failed instead.
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SerdeError {
+    /// What went wrong
+    pub message: String,
+    /// The byte of the input where the error was found
+    pub byte: Option<usize>,
+    /// Location of the failing value in the tree, the root if there is none
+    pub path: Path,
+}
+
+#[cfg(feature = "serde")]
+impl SerdeError {
+    // moves the error down into a child of the value
+    pub(crate) fn within (mut self, segment: PathSegment) -> SerdeError {
+        self.path.prepend(segment);
+        self
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Display for SerdeError {
+    fn fmt (&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "MsgPack serde error")?;
+        if !self.path.is_root() { write!(f, " at {}", self.path)? }
+        if let Some(byte) = self.byte { write!(f, " at byte {}", byte)? }
+        write!(f, ": {}", self.message)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Error for SerdeError {}
+
+#[cfg(feature = "serde")]
+impl serde::ser::Error for SerdeError {
+    fn custom <T: Display> (message: T) -> SerdeError {
+        SerdeError { message: message.to_string(), byte: None, path: Path::root() }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::de::Error for SerdeError {
+    fn custom <T: Display> (message: T) -> SerdeError {
+        SerdeError { message: message.to_string(), byte: None, path: Path::root() }
+    }
 }
 
 #[cfg(test)]
@@ -123,7 +439,7 @@ mod tests {
 
     #[test]
     fn conversion_error () {
-        let error = ConversionError { original: MsgPack::Float(4.2), attempted: "int" };
+        let error = ConversionError { original: MsgPack::Float(4.2), attempted: "int", kind: ConversionKind::Type };
         let error_message = format!("{}", error);
         assert_eq!(error_message, "MsgPack conversion error: cannot use float as int");
 
@@ -132,10 +448,51 @@ mod tests {
         assert_eq!(recovered.as_float().unwrap(), 4.2);
     }
 
+    #[test]
+    fn range_error () {
+        let error = ConversionError::range_error(MsgPack::Uint(300), "u8");
+        assert_eq!(format!("{}", error), "MsgPack conversion error: uint 300 is out of range for u8");
+
+        let error = ConversionError::range_error(MsgPack::Int(-1), "u64");
+        assert_eq!(format!("{}", error), "MsgPack conversion error: cannot use negative int -1 as u64");
+    }
+
     #[test]
     fn parse_error () {
         let error = ParseError { byte: 42 };
         let error_message = format!("{}", error);
         assert_eq!(error_message, "MsgPack parse error at byte 42");
     }
+
+    #[test]
+    fn partial_error () {
+        let error = PartialError { error: ParseError { byte: 42 }, path: vec![3, 1], placeholder: true, key: false };
+        assert_eq!(format!("{}", error), "MsgPack parse error at byte 42 (node /3/1 replaced with nil)");
+
+        let error = PartialError { error: ParseError { byte: 7 }, path: vec![2], placeholder: false, key: true };
+        assert_eq!(format!("{}", error), "MsgPack parse error at byte 7 (key of entry /2 unreadable, map truncated)");
+    }
+
+    #[test]
+    fn render_parse_error () {
+        let mut data = vec![0x93, 0xa5, 0x68, 0x65, 0x6c, 0x6c, 0x6f, 0xb7];
+        data.extend_from_slice(&[0x2d; 23]);
+        data.extend_from_slice(&[0xda, 0x03, 0x2c, 0x61, 0x62]);
+
+        let error = MsgPack::parse(&data).unwrap_err();
+        let rendered = error.render(&data);
+        let lines: Vec<&str> = rendered.lines().collect();
+
+        assert_eq!(lines[0], "MsgPack parse error at byte 34: str16 declared 812 bytes, 2 available (marker at byte 31)");
+        assert_eq!(lines[2], "00000000  93 a5 68 65 6c 6c 6f b7  2d 2d 2d 2d 2d 2d 2d 2d  |..hello.--------|");
+        assert_eq!(lines[4], "00000020  03 2c 61 62                                       |.,ab|");
+        assert_eq!(lines[5], "                ^^");
+
+        let data = vec![0x93, 0x01, 0x02];
+        let error = MsgPack::parse(&data).unwrap_err();
+        let rendered = error.render(&data);
+
+        assert!(rendered.starts_with("MsgPack parse error at byte 3: fixarray declared 3 elements, data ends after 2 (marker at byte 0)"));
+        assert!(rendered.ends_with("^^ end of data"));
+    }
 }
\ No newline at end of file
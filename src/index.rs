@@ -0,0 +1,293 @@
+//! Key and index lookup on maps and arrays
+
+use std::ops::{Index, IndexMut};
+
+use super::{MsgPack, MapElement};
+use super::map::{hash_int, hash_key, hash_str};
+
+// returned by Index when the key or index is missing
+static NIL: MsgPack = MsgPack::Nil;
+
+/// A type that can be used to look up values in a MessagePack map
+/// 
+/// Implemented for strings, all integer types and MsgPack itself. Strings only
+/// match string keys, integers match both Int and Uint keys with the same
+/// value, and MsgPack keys have to be equal, except that Int and Uint keys
+/// match each other like integers do.
+pub trait MapKey {
+    /// Checks if a key in the map matches this one
+    fn matches_key (&self, key: &MsgPack) -> bool;
+
+    /// Hash of the key, used by [MsgPackMap](map/struct.MsgPackMap.html) for
+    /// fast lookups. It has to be equal to the hash of any MsgPack key this
+    /// one matches, which is why this crate computes it. `None` falls back to
+    /// checking every key with `matches_key`.
+    fn key_hash (&self) -> Option<u64> {
+        None
+    }
+}
+
+impl MapKey for str {
+    fn matches_key (&self, key: &MsgPack) -> bool {
+        match key {
+            MsgPack::String(value) => value == self,
+            _ => false
+        }
+    }
+
+    fn key_hash (&self) -> Option<u64> {
+        Some(hash_str(self))
+    }
+}
+
+impl MapKey for String {
+    fn matches_key (&self, key: &MsgPack) -> bool {
+        self.as_str().matches_key(key)
+    }
+
+    fn key_hash (&self) -> Option<u64> {
+        Some(hash_str(self))
+    }
+}
+
+impl MapKey for MsgPack {
+    fn matches_key (&self, key: &MsgPack) -> bool {
+        match *self {
+            MsgPack::Int(value) => value.matches_key(key),
+            MsgPack::Uint(value) => value.matches_key(key),
+            _ => self == key
+        }
+    }
+
+    fn key_hash (&self) -> Option<u64> {
+        Some(hash_key(self))
+    }
+}
+
+impl <T: MapKey + ?Sized> MapKey for &T {
+    fn matches_key (&self, key: &MsgPack) -> bool {
+        (**self).matches_key(key)
+    }
+
+    fn key_hash (&self) -> Option<u64> {
+        (**self).key_hash()
+    }
+}
+
+macro_rules! impl_map_key {
+    ($($type:ty)*) => {
+        $(
+            impl MapKey for $type {
+                fn matches_key (&self, key: &MsgPack) -> bool {
+                    match *key {
+                        MsgPack::Int(value) => value as i128 == *self as i128,
+                        MsgPack::Uint(value) => value as i128 == *self as i128,
+                        _ => false
+                    }
+                }
+
+                fn key_hash (&self) -> Option<u64> {
+                    Some(hash_int(*self as i128))
+                }
+            }
+        )*
+    };
+}
+
+impl_map_key! { i8 i16 i32 i64 isize u8 u16 u32 u64 usize }
+
+impl MsgPack {
+    /// Looks up a value in a map by its key
+    /// 
+    /// Returns `None` if the MsgPack is not a map or the key is not found. If
+    /// the key occurs multiple times, the first one is used.
+    /// 
+    ///     use msgpack_simple::msgpack;
+    /// 
+    ///     let message = msgpack!({ "foo": 42, 7: "seven" });
+    /// 
+    ///     assert_eq!(message.get("foo"), Some(&msgpack!(42)));
+    ///     assert_eq!(message.get(7u8), Some(&msgpack!("seven")));
+    ///     assert_eq!(message.get(&msgpack!("foo")), Some(&msgpack!(42)));
+    ///     assert_eq!(message.get("bar"), None);
+    pub fn get <K: MapKey> (&self, key: K) -> Option<&MsgPack> {
+        match self {
+            MsgPack::Map(map) => map.iter().find(|element| key.matches_key(&element.key)).map(|element| &element.value),
+            _ => None
+        }
+    }
+
+    /// Looks up a value in a map by its key, as a mutable reference
+    /// 
+    ///     use msgpack_simple::msgpack;
+    /// 
+    ///     let mut message = msgpack!({ "foo": 42 });
+    ///     *message.get_mut("foo").unwrap() = msgpack!("bar");
+    /// 
+    ///     assert_eq!(message, msgpack!({ "foo": "bar" }));
+    pub fn get_mut <K: MapKey> (&mut self, key: K) -> Option<&mut MsgPack> {
+        match self {
+            MsgPack::Map(map) => map.iter_mut().find(|element| key.matches_key(&element.key)).map(|element| &mut element.value),
+            _ => None
+        }
+    }
+
+    /// Gets an element of an array by its index
+    /// 
+    /// Returns `None` if the MsgPack is not an array or the index is out of
+    /// bounds.
+    /// 
+    ///     use msgpack_simple::msgpack;
+    /// 
+    ///     let message = msgpack!([true, nil]);
+    /// 
+    ///     assert_eq!(message.get_index(0), Some(&msgpack!(true)));
+    ///     assert_eq!(message.get_index(2), None);
+    pub fn get_index (&self, index: usize) -> Option<&MsgPack> {
+        match self {
+            MsgPack::Array(array) => array.get(index),
+            _ => None
+        }
+    }
+
+    /// Gets an element of an array by its index, as a mutable reference
+    /// 
+    ///     use msgpack_simple::msgpack;
+    /// 
+    ///     let mut message = msgpack!([true, nil]);
+    ///     *message.get_index_mut(1).unwrap() = msgpack!(false);
+    /// 
+    ///     assert_eq!(message, msgpack!([true, false]));
+    pub fn get_index_mut (&mut self, index: usize) -> Option<&mut MsgPack> {
+        match self {
+            MsgPack::Array(array) => array.get_mut(index),
+            _ => None
+        }
+    }
+}
+
+/// Looks up a string key in a map, returning Nil if it's not found or the
+/// MsgPack is not a map
+/// 
+///     use msgpack_simple::msgpack;
+/// 
+///     let message = msgpack!({ "foo": { "bar": [1, 2] } });
+/// 
+///     assert_eq!(message["foo"]["bar"][1], msgpack!(2));
+///     assert!(message["baz"]["bar"].is_nil());
+impl Index<&str> for MsgPack {
+    type Output = MsgPack;
+
+    fn index (&self, key: &str) -> &MsgPack {
+        self.get(key).unwrap_or(&NIL)
+    }
+}
+
+/// Gets an element of an array, returning Nil if the index is out of bounds or
+/// the MsgPack is not an array
+impl Index<usize> for MsgPack {
+    type Output = MsgPack;
+
+    fn index (&self, index: usize) -> &MsgPack {
+        self.get_index(index).unwrap_or(&NIL)
+    }
+}
+
+/// Looks up a string key in a map for writing
+/// 
+/// If the key is missing, it's added with a Nil value. Nil itself is turned
+/// into an empty map first. Panics for any other type.
+/// 
+///     use msgpack_simple::{msgpack, MsgPack};
+/// 
+///     let mut message = MsgPack::Nil;
+///     message["foo"]["bar"] = msgpack!(42);
+/// 
+///     assert_eq!(message, msgpack!({ "foo": { "bar": 42 } }));
+impl IndexMut<&str> for MsgPack {
+    fn index_mut (&mut self, key: &str) -> &mut MsgPack {
+        if self.is_nil() { *self = MsgPack::Map(vec![]) }
+
+        match self {
+            MsgPack::Map(map) => {
+                let position = match map.iter().position(|element| key.matches_key(&element.key)) {
+                    Some(position) => position,
+                    None => {
+                        map.push(MapElement { key: MsgPack::String(key.to_string()), value: MsgPack::Nil });
+                        map.len() - 1
+                    }
+                };
+
+                &mut map[position].value
+            },
+            _ => panic!("cannot index into MsgPack {} with a string key", self)
+        }
+    }
+}
+
+/// Gets an element of an array for writing
+/// 
+/// Panics if the MsgPack is not an array or the index is out of bounds.
+impl IndexMut<usize> for MsgPack {
+    fn index_mut (&mut self, index: usize) -> &mut MsgPack {
+        match self {
+            MsgPack::Array(array) => {
+                let length = array.len();
+                array.get_mut(index).unwrap_or_else(|| panic!("index {} out of bounds for MsgPack array of length {}", index, length))
+            },
+            _ => panic!("cannot index into MsgPack {} with an integer index", self)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookup () {
+        let message = MsgPack::Map(vec![
+            MapElement { key: MsgPack::String("a".to_string()), value: MsgPack::Int(1) },
+            MapElement { key: MsgPack::Uint(5), value: MsgPack::Int(2) },
+            MapElement { key: MsgPack::Int(-5), value: MsgPack::Int(3) },
+            MapElement { key: MsgPack::String("a".to_string()), value: MsgPack::Int(4) },
+        ]);
+
+        assert_eq!(message.get("a"), Some(&MsgPack::Int(1)));
+        assert_eq!(message.get("a".to_string()), Some(&MsgPack::Int(1)));
+        assert_eq!(message.get(5i64), Some(&MsgPack::Int(2)));
+        assert_eq!(message.get(-5i8), Some(&MsgPack::Int(3)));
+        assert_eq!(message.get(MsgPack::Int(5)), Some(&MsgPack::Int(2)));
+        assert_eq!(message.get(MsgPack::Float(5.0)), None);
+        assert_eq!(message.get(6), None);
+        assert_eq!(MsgPack::Nil.get("a"), None);
+
+        assert_eq!(message["a"], MsgPack::Int(1));
+        assert_eq!(message["b"], MsgPack::Nil);
+        assert_eq!(message[0], MsgPack::Nil);
+
+        let array = MsgPack::Array(vec![MsgPack::Nil, MsgPack::Boolean(true)]);
+        assert_eq!(array[1], MsgPack::Boolean(true));
+        assert_eq!(array[2], MsgPack::Nil);
+        assert_eq!(array["a"], MsgPack::Nil);
+    }
+
+    #[test]
+    fn mutation () {
+        let mut message = MsgPack::Map(vec![]);
+        message["a"] = MsgPack::Array(vec![MsgPack::Nil]);
+        message["a"][0] = MsgPack::Int(1);
+        *message.get_mut("a").unwrap().get_index_mut(0).unwrap() = MsgPack::Int(2);
+
+        assert_eq!(message, MsgPack::Map(vec![
+            MapElement { key: MsgPack::String("a".to_string()), value: MsgPack::Array(vec![MsgPack::Int(2)]) }
+        ]));
+    }
+
+    #[test]
+    #[should_panic]
+    fn mutation_out_of_bounds () {
+        let mut array = MsgPack::Array(vec![]);
+        array[0] = MsgPack::Nil;
+    }
+}
@@ -0,0 +1,210 @@
+//! 128-bit integers, stored in an extension type when they don't fit in 64
+//! bits
+
+use std::convert::TryFrom;
+
+use super::{MsgPack, Extension};
+use super::error::{ConversionError, ConversionKind};
+
+/// The convention for storing 128-bit integers
+/// 
+/// MessagePack integers stop at 64 bits, so `i128` and `u128` values that fit
+/// in 64 bits are stored as ordinary ints and uints, and larger ones as an
+/// extension holding the value in big-endian two's complement, with as few
+/// bytes as it takes to keep the sign. Values above `i128::MAX` need 17 bytes,
+/// the first of them zero.
+/// 
+/// The `From` and `TryFrom` conversions of `i128` and `u128` use the extension
+/// type `Int128::DEFAULT_TYPE_ID`. An `Int128` with a different type id does
+/// the same conversions with that one.
+/// 
+///     use std::convert::TryFrom;
+///     use msgpack_simple::{MsgPack, Extension, Int128};
+/// 
+///     assert_eq!(MsgPack::from(-5i128), MsgPack::Int(-5));
+///     assert_eq!(MsgPack::from(1u128 << 64), MsgPack::Extension(Extension {
+///         type_id: 127,
+///         value: vec![0x01, 0, 0, 0, 0, 0, 0, 0, 0]
+///     }));
+///     assert_eq!(i128::try_from(MsgPack::from(i128::MIN)).unwrap(), i128::MIN);
+/// 
+///     let custom = Int128::new(12);
+///     let value = custom.encode_u128(u128::MAX);
+///     assert_eq!(value.clone().as_extension().unwrap().value.len(), 17);
+///     assert_eq!(custom.decode_u128(&value).unwrap(), u128::MAX);
+///     assert!(u128::try_from(value).is_err());
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Int128 {
+    type_id: i8,
+}
+
+impl Default for Int128 {
+    fn default () -> Int128 {
+        Int128 { type_id: Int128::DEFAULT_TYPE_ID }
+    }
+}
+
+// the shortest big-endian two's complement form of the value
+fn shorten (bytes: &[u8]) -> Vec<u8> {
+    let mut start = 0;
+
+    while start + 1 < bytes.len() {
+        let redundant = (bytes[start] == 0x00 && bytes[start + 1] & 0x80 == 0)
+            || (bytes[start] == 0xff && bytes[start + 1] & 0x80 != 0);
+
+        if !redundant { break }
+        start += 1;
+    }
+
+    bytes[start..].to_vec()
+}
+
+// the value as 16 bytes, and whether it's negative, if it fits
+fn widen (bytes: &[u8]) -> Option<([u8; 16], bool)> {
+    let negative = bytes.first()? & 0x80 != 0;
+
+    let bytes = match bytes.len() {
+        17 if bytes[0] == 0x00 => &bytes[1..],
+        17 => return None,
+        length if length > 17 => return None,
+        _ => bytes
+    };
+
+    let mut result = if negative { [0xff; 16] } else { [0x00; 16] };
+    result[16 - bytes.len()..].copy_from_slice(bytes);
+    Some((result, negative))
+}
+
+impl Int128 {
+    /// The extension type used by the `From` and `TryFrom` conversions
+    pub const DEFAULT_TYPE_ID: i8 = 127;
+
+    /// Creates the convention with a custom extension type
+    pub fn new (type_id: i8) -> Int128 {
+        Int128 { type_id }
+    }
+
+    /// The extension type of the convention
+    pub fn type_id (&self) -> i8 {
+        self.type_id
+    }
+
+    /// Converts an `i128` to MsgPack
+    pub fn encode_i128 (&self, value: i128) -> MsgPack {
+        if let Ok(value) = i64::try_from(value) { return MsgPack::Int(value) }
+        if let Ok(value) = u64::try_from(value) { return MsgPack::Uint(value) }
+
+        MsgPack::Extension(Extension { type_id: self.type_id, value: shorten(&value.to_be_bytes()) })
+    }
+
+    /// Converts a `u128` to MsgPack
+    pub fn encode_u128 (&self, value: u128) -> MsgPack {
+        if let Ok(value) = u64::try_from(value) { return MsgPack::Uint(value) }
+
+        let mut bytes = vec![0x00];
+        bytes.extend_from_slice(&value.to_be_bytes());
+        MsgPack::Extension(Extension { type_id: self.type_id, value: shorten(&bytes) })
+    }
+
+    /// Converts MsgPack to an `i128`, accepting ints, uints, and extensions of
+    /// the convention's type
+    pub fn decode_i128 (&self, value: &MsgPack) -> Result<i128, ConversionError> {
+        let result = match value {
+            MsgPack::Int(value) => Ok(*value as i128),
+            MsgPack::Uint(value) => Ok(*value as i128),
+            MsgPack::Extension(extension) if extension.type_id == self.type_id => match widen(&extension.value) {
+                Some((bytes, negative)) if negative || bytes[0] & 0x80 == 0 => Ok(i128::from_be_bytes(bytes)),
+                Some(_) => Err(ConversionKind::Range),
+                None => Err(ConversionKind::Type)
+            },
+            _ => Err(ConversionKind::Type)
+        };
+
+        result.map_err(|kind| ConversionError { original: value.clone(), attempted: "i128", kind })
+    }
+
+    /// Converts MsgPack to a `u128`, accepting non-negative ints, uints, and
+    /// extensions of the convention's type
+    pub fn decode_u128 (&self, value: &MsgPack) -> Result<u128, ConversionError> {
+        let result = match value {
+            MsgPack::Int(value) => u128::try_from(*value).map_err(|_| ConversionKind::Range),
+            MsgPack::Uint(value) => Ok(*value as u128),
+            MsgPack::Extension(extension) if extension.type_id == self.type_id => match widen(&extension.value) {
+                Some((bytes, false)) => Ok(u128::from_be_bytes(bytes)),
+                Some(_) => Err(ConversionKind::Range),
+                None => Err(ConversionKind::Type)
+            },
+            _ => Err(ConversionKind::Type)
+        };
+
+        result.map_err(|kind| ConversionError { original: value.clone(), attempted: "u128", kind })
+    }
+}
+
+impl From<i128> for MsgPack {
+    fn from (value: i128) -> MsgPack {
+        Int128::default().encode_i128(value)
+    }
+}
+
+impl From<u128> for MsgPack {
+    fn from (value: u128) -> MsgPack {
+        Int128::default().encode_u128(value)
+    }
+}
+
+pub(crate) fn to_i128 (value: &MsgPack) -> Result<i128, ConversionKind> {
+    Int128::default().decode_i128(value).map_err(|error| error.kind)
+}
+
+pub(crate) fn to_u128 (value: &MsgPack) -> Result<u128, ConversionKind> {
+    Int128::default().decode_u128(value).map_err(|error| error.kind)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extension (value: Vec<u8>) -> MsgPack {
+        MsgPack::Extension(Extension { type_id: Int128::DEFAULT_TYPE_ID, value })
+    }
+
+    #[test]
+    fn encoding () {
+        assert_eq!(MsgPack::from(i64::MIN as i128), MsgPack::Int(i64::MIN));
+        assert_eq!(MsgPack::from(u64::MAX as i128), MsgPack::Uint(u64::MAX));
+        assert_eq!(MsgPack::from(7u128), MsgPack::Uint(7));
+        assert_eq!(MsgPack::from(i64::MIN as i128 - 1), extension(vec![0xff, 0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]));
+        assert_eq!(MsgPack::from(u64::MAX as i128 + 1), extension(vec![0x01, 0, 0, 0, 0, 0, 0, 0, 0]));
+        assert_eq!(MsgPack::from(1i128 << 71), extension(vec![0x00, 0x80, 0, 0, 0, 0, 0, 0, 0, 0]));
+        assert_eq!(MsgPack::from(i128::MAX).as_extension().unwrap().value.len(), 16);
+        assert_eq!(MsgPack::from(u128::MAX), extension([vec![0x00], vec![0xff; 16]].concat()));
+    }
+
+    #[test]
+    fn decoding () {
+        let values = [0, -1, i64::MIN as i128 - 1, u64::MAX as i128 + 1, 1 << 71, i128::MIN, i128::MAX];
+        for value in values.iter() {
+            assert_eq!(i128::try_from(MsgPack::from(*value)).unwrap(), *value);
+        }
+
+        let values = [0, u64::MAX as u128 + 1, i128::MAX as u128 + 1, u128::MAX];
+        for value in values.iter() {
+            assert_eq!(u128::try_from(&MsgPack::from(*value)).unwrap(), *value);
+        }
+
+        assert!(i128::try_from(MsgPack::from(u128::MAX)).unwrap_err().is_range_error());
+        assert!(u128::try_from(MsgPack::from(i128::MIN)).is_err());
+        assert!(u128::try_from(MsgPack::Int(-1)).is_err());
+        assert!(!i128::try_from(extension(vec![])).unwrap_err().is_range_error());
+        assert!(i128::try_from(extension(vec![0xff; 17])).is_err());
+        assert!(i128::try_from(MsgPack::Extension(Extension { type_id: 3, value: vec![1] })).is_err());
+
+        // non-minimal forms still decode
+        assert_eq!(i128::try_from(extension(vec![0xff, 0xff])).unwrap(), -1);
+        assert_eq!(u128::try_from(extension(vec![0x00, 0x00, 0x05])).unwrap(), 5);
+
+        let error = u128::try_from(MsgPack::Int(-1)).unwrap_err();
+        assert_eq!(error.to_string(), "MsgPack conversion error: cannot use negative int -1 as u128");
+    }
+}
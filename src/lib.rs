@@ -68,7 +68,8 @@
 //! `MsgPack::parse()` takes a byte array slice (`&[u8]`) and returns an
 //! [MsgPack enum](enum.MsgPack.html) wrapped in a result. The error type is
 //! [ParseError](struct.ParseError.html), which can show the byte where the
-//! parser encountered an error if needed.
+//! parser encountered an error if needed, or render a hexdump of the input
+//! around that byte with `ParseError::render()`.
 //! 
 //! If you need more control, you can use the `parser` module directly:
 //! 
@@ -84,6 +85,10 @@
 //! `parser::parse()` behaves identically, but it also returns the length of the
 //! MessagePack data parsed.
 //! 
+//! For damaged or truncated data, `MsgPack::parse_partial()` returns whatever
+//! could be recovered, along with a list of
+//! [PartialErrors](struct.PartialError.html) describing what was lost.
+//! 
 //! # Encoding MsgPack
 //! 
 //! msgpack_simple provides the `MsgPack.encode()` function for encoding data:
@@ -96,6 +101,37 @@
 //!     let data = vec![0xaa, 0x48, 0x65, 0x6c, 0x6c, 0x6f, 0x20, 0x52, 0x75, 0x73, 0x74];
 //!     assert_eq!(encoded, data);
 //! 
+//! Values can also be built from Rust primitives and standard collections
+//! with `From`/`Into`. Byte vectors need the [Binary](struct.Binary.html)
+//! wrapper to become a MessagePack binary, otherwise they are arrays.
+//! 
+//!     use std::collections::BTreeMap;
+//!     use msgpack_simple::{MsgPack, MapElement, Binary};
+//! 
+//!     let mut fields = BTreeMap::new();
+//!     fields.insert("id", MsgPack::from(42));
+//!     fields.insert("tags", MsgPack::from(vec!["foo", "bar"]));
+//!     fields.insert("data", MsgPack::from(Binary(vec![0x42, 0xff])));
+//!     fields.insert("parent", MsgPack::from(None::<i64>));
+//! 
+//!     let message = MsgPack::from(fields);
+//!     assert_eq!(message.as_map().unwrap()[0], MapElement {
+//!         key: MsgPack::String("data".to_string()),
+//!         value: MsgPack::Binary(vec![0x42, 0xff])
+//!     });
+//! 
+//! For handwritten values, the [msgpack!](macro.msgpack.html) macro accepts a
+//! JSON-like syntax and expands to the same constructors:
+//! 
+//!     use msgpack_simple::msgpack;
+//! 
+//!     let message = msgpack!({
+//!         "hello": 42,
+//!         "world": [true, nil, bin[0x42, 0xff], ext(2, [0x32, 0x4a, 0x67, 0x11])]
+//!     });
+//! 
+//!     assert!(message.is_map());
+//! 
 //! # Accessing MsgPack
 //! 
 //! For every variant of the enum (String, Float, Array, etc.) MsgPack provides
@@ -119,7 +155,82 @@
 //! 
 //! There are two special cases: `as_nil()` does not exist because the Nil
 //! variant holds no data, and there is an `is_some_int()` and `as_some_int()`
-//! pair, which matches both Int and Uint and returns `i64`.
+//! pair, which matches both Int and Uint and returns `i64`. `as_some_uint()`
+//! does the same, but returns `u64`. Both of them fail instead of wrapping if
+//! the value does not fit.
+//! 
+//! For any other primitive, MsgPack implements `TryFrom`, which checks that the
+//! value fits into the requested type:
+//! 
+//!     use std::convert::TryFrom;
+//!     use msgpack_simple::MsgPack;
+//! 
+//!     let small = MsgPack::Uint(200);
+//!     let large = MsgPack::Int(-42);
+//! 
+//!     assert_eq!(u8::try_from(&small).unwrap(), 200);
+//!     assert!(i8::try_from(&small).is_err());
+//!     assert!(u32::try_from(large).unwrap_err().is_range_error());
+//! 
+//! Whole Rust values convert with the `ToMsgPack` and `FromMsgPack` traits,
+//! which are implemented for primitives, strings, collections, tuples, smart
+//! pointers, `Duration` and `SystemTime`. Errors from `FromMsgPack` point to
+//! the part of the value that didn't fit:
+//! 
+//!     use std::collections::HashMap;
+//!     use msgpack_simple::{msgpack, FromMsgPack, ToMsgPack};
+//! 
+//!     let scores = HashMap::<String, Vec<u8>>::from_msgpack(msgpack!({ "ferris": [3, 5] })).unwrap();
+//!     assert_eq!(scores.to_msgpack(), msgpack!({ "ferris": [(3u8.to_msgpack()), (5u8.to_msgpack())] }));
+//! 
+//!     let error = HashMap::<String, Vec<u8>>::from_msgpack(msgpack!({ "ferris": [3, -5] })).unwrap_err();
+//!     assert_eq!(error.path.to_string(), "/ferris/1");
+//! 
+//! With the `derive` feature, both traits can be derived for structs and
+//! enums, see [msgpack_simple_derive](https://docs.rs/msgpack_simple_derive)
+//! for the layout and the attributes.
+//! 
+//! # Looking up values
+//! 
+//! Without consuming the MsgPack, values in maps can be looked up by key with
+//! `get()`, and elements of arrays with `get_index()`. Both return an
+//! `Option` of a reference. Indexing with `[]` works too, returning Nil if
+//! nothing is found, so lookups can be chained.
+//! 
+//!     use msgpack_simple::msgpack;
+//! 
+//!     let message = msgpack!({ "users": [{ "name": "Ferris" }], 42: true });
+//! 
+//!     assert_eq!(message.get(42), Some(&msgpack!(true)));
+//!     assert_eq!(message["users"][0]["name"], msgpack!("Ferris"));
+//!     assert!(message["users"][1]["name"].is_nil());
+//! 
+//! For values deeper in the tree, `pointer()`, `pointer_mut()`, `set_path()`
+//! and `remove_path()` take a [Path](struct.Path.html), or a pointer string
+//! in the style of JSON Pointer:
+//! 
+//!     use msgpack_simple::{msgpack, MsgPack};
+//! 
+//!     let mut config = MsgPack::Nil;
+//!     config.set_path("/server/host", "localhost").unwrap();
+//! 
+//!     assert_eq!(config.pointer("/server/host"), Some(&msgpack!("localhost")));
+//!     assert_eq!(config.remove_path("/server/host"), Some(msgpack!("localhost")));
+//!     assert_eq!(config, msgpack!({ "server": {} }));
+//! 
+//! To go through every node of the tree, use `walk()` with a
+//! [Visitor](trait.Visitor.html), or `walk_mut()` with a
+//! [VisitorMut](trait.VisitorMut.html). Both get the path and depth of each
+//! node. `transform()` rewrites the tree bottom-up, and can drop nodes too.
+//! 
+//! Layered values, like defaults and overrides, can be combined with
+//! `merge()`, which follows JSON Merge Patch (RFC 7386): maps are merged
+//! recursively and Nil deletes a key. [Merge](struct.Merge.html) has the
+//! settings for merging arrays.
+//! 
+//! To see what changed between two versions of a value, `MsgPack::diff()`
+//! returns a [Patch](struct.Patch.html) of JSON Patch style operations, which
+//! can be printed, stored as MsgPack, or replayed with `apply_patch()`.
 //! 
 //! # Arrays, Maps, and Extensions
 //! 
@@ -162,15 +273,134 @@
 //! 
 //!     assert_eq!(first.value.as_some_int().unwrap(), 42);
 //!     assert_eq!(second.key.as_extension().unwrap().type_id, 27);
+//! 
+//! For maps with many keys, [MsgPackMap](map/struct.MsgPackMap.html) keeps the
+//! same elements in the same order, but with a hash index for fast lookups.
+//! 
+//! The timestamp extension type that MessagePack itself defines (type -1) is
+//! available as [Timestamp](struct.Timestamp.html), which converts to and
+//! from extensions and `SystemTime`. Application extension types can get
+//! their own [ExtensionCodec](trait.ExtensionCodec.html), and an
+//! [ExtensionRegistry](struct.ExtensionRegistry.html) of codecs decodes,
+//! encodes, checks and displays them.
+//! 
+//! [Uuid](struct.Uuid.html) and [Decimal](struct.Decimal.html) cover two
+//! common application types, in the layouts Tarantool uses: UUIDs as a
+//! fixext 16, decimals as a scale and packed decimal digits. Both convert to
+//! and from their text form, and have a codec for any type id.
+//! 
+//! Negative type ids are reserved for the spec. Use
+//! `Extension::application()` to create extensions that stay clear of them,
+//! and [ParseOptions](struct.ParseOptions.html) and
+//! [EncodeOptions](struct.EncodeOptions.html) to reject or warn about
+//! reserved ids the spec doesn't define.
+//! 
+//! `i128` and `u128` convert to and from MsgPack too. Values that fit in 64
+//! bits become ints and uints, larger ones an extension of type 127, see
+//! [Int128](struct.Int128.html) for the layout and for using another type id.
+//! 
+//! A whole MsgPack document can travel inside an extension or a binary, for
+//! example in an envelope that routers forward without decoding it.
+//! `Extension::wrap()` and `Binary::wrap()` encode the document,
+//! `unwrap_msgpack()` parses it back, and `lazy_msgpack()` returns a
+//! [LazyMsgPack](struct.LazyMsgPack.html) that only parses it on first access.
+//! 
+//! # Comparing values
+//! 
+//! MsgPack implements `Eq`, `Ord` and `Hash`, so values can be sorted, put in a
+//! `HashSet`, or used as keys in a `HashMap` or `BTreeMap`. Values are ordered
+//! by type first, and numbers by value across ints, uints and floats. NaN is
+//! equal to itself and sorts after every other number, and `-0.0` equals `0.0`.
+//! See the `Ord` implementation on [MsgPack](enum.MsgPack.html) for the details.
+//! 
+//!     use msgpack_simple::MsgPack;
+//! 
+//!     let mut values = vec![MsgPack::Float(0.5), MsgPack::Nil, MsgPack::Int(-3)];
+//!     values.sort();
+//! 
+//!     assert_eq!(values, vec![MsgPack::Nil, MsgPack::Int(-3), MsgPack::Float(0.5)]);
+//! 
+//! These comparisons still tell `Int(5)` and `Uint(5)` apart, and compare maps
+//! in their stored order. To check if two values mean the same, use
+//! [semantic_eq](enum.MsgPack.html#method.semantic_eq), or wrap them in
+//! [SemanticKey](struct.SemanticKey.html) to use them as cache keys.
+//! 
+//! # Serde
+//! 
+//! With the `serde` feature, `MsgPack`, `MapElement` and `Extension` implement
+//! `Serialize` and `Deserialize`, so dynamic values can sit in serde-derived
+//! structs and convert to any serde format. Binaries are serialized as bytes,
+//! and extensions as a struct with a single `$msgpack_simple::Extension`
+//! field, which deserializing a `MsgPack` turns back into an extension.
+//! 
+//! Typed values don't need to go through `MsgPack` at all: `to_vec()` and
+//! `to_writer()` serialize them straight to MessagePack, and `from_slice()`
+//! and `from_reader()` deserialize them straight from it, in the same formats
+//! as `encode()` and `parse()`. `to_value()` and `from_value()` convert
+//! between typed values and `MsgPack` trees, for messages that are only partly
+//! known until one of their fields is read.
 
 extern crate byteorder;
 extern crate hex;
-use byteorder::{BigEndian, WriteBytesExt};
 
+#[macro_use]
+mod macros;
+mod cmp;
+mod codec;
+mod convert;
+#[cfg(feature = "serde")]
+mod de;
+mod decimal;
+mod diff;
+mod embed;
 mod error;
+mod index;
+mod int128;
+pub mod map;
+mod marker;
+mod merge;
+mod path;
 pub mod parser;
+mod reserved;
+mod semantic;
+#[cfg(feature = "serde")]
+mod ser;
+#[cfg(feature = "serde")]
+mod serde_impls;
+mod timestamp;
+mod traits;
+mod uuid;
+#[cfg(feature = "serde")]
+mod value;
+mod visit;
 
-pub use self::error::{ConversionError, ParseError};
+pub use self::codec::{DecimalCodec, ExtensionCodec, ExtensionRegistry, Formatted, TimestampCodec, UuidCodec};
+pub use self::convert::Binary;
+#[cfg(feature = "serde")]
+pub use self::de::{from_reader, from_slice, Deserializer};
+pub use self::decimal::Decimal;
+pub use self::diff::{Patch, PatchOperation};
+pub use self::embed::LazyMsgPack;
+pub use self::error::{ConversionError, ConversionKind, FromMsgPackError, ParseError, PartialError, PathError, ReservedExtension, TextError};
+#[cfg(feature = "serde")]
+pub use self::error::SerdeError;
+pub use self::index::MapKey;
+pub use self::int128::Int128;
+pub use self::map::MsgPackMap;
+pub use self::merge::{ArrayMerge, Merge};
+pub use self::path::{Path, PathSegment};
+pub use self::reserved::{EncodeOptions, ParseOptions, ReservedPolicy};
+pub use self::semantic::{Semantic, SemanticKey};
+#[cfg(feature = "serde")]
+pub use self::ser::{to_vec, to_writer, Compound, Serializer};
+pub use self::timestamp::Timestamp;
+pub use self::traits::{FromMsgPack, ToMsgPack};
+#[cfg(feature = "derive")]
+pub use msgpack_simple_derive::{FromMsgPack, ToMsgPack};
+pub use self::uuid::Uuid;
+#[cfg(feature = "serde")]
+pub use self::value::{from_value, to_value};
+pub use self::visit::{Visitor, VisitorMut};
 
 /// A piece of MessagePack-compatible data
 /// 
@@ -194,7 +424,7 @@ pub use self::error::{ConversionError, ParseError};
 ///             ])
 ///         }
 ///     ]);
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, Clone)]
 pub enum MsgPack {
     /// Empty value
     /// 
@@ -304,7 +534,8 @@ pub enum MsgPack {
 ///             value: MsgPack::String("bar".to_string())
 ///         }
 ///     ]);
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MapElement {
     pub key: MsgPack,
     pub value: MsgPack
@@ -318,7 +549,7 @@ pub struct MapElement {
 ///         type_id: 42,
 ///         value: vec![0x42]
 ///     });
-#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
 pub struct Extension {
     /// Type of the extension field. 0-127 are free to set by the application,
     /// but MessagePack reserves the negative type IDs for predefined types.
@@ -344,6 +575,36 @@ impl MsgPack {
         Ok(result)
     }
 
+    /// Parses as much of the data as possible, returning the recovered value
+    /// along with the problems found
+    /// 
+    /// Arrays and maps that run out of data are cut short, and values that
+    /// can't be read are replaced with a Nil placeholder. See
+    /// [parser::parse_partial](parser/fn.parse_partial.html) for details.
+    /// 
+    ///     use msgpack_simple::{MsgPack, MapElement};
+    /// 
+    ///     let message = MsgPack::Map(vec![
+    ///         MapElement { key: MsgPack::String("id".to_string()), value: MsgPack::Int(42) },
+    ///         MapElement { key: MsgPack::String("name".to_string()), value: MsgPack::String("Rust".to_string()) }
+    ///     ]);
+    /// 
+    ///     let mut encoded = message.encode();
+    ///     encoded.truncate(encoded.len() - 2);
+    /// 
+    ///     assert!(MsgPack::parse(&encoded).is_err());
+    /// 
+    ///     let (decoded, errors) = MsgPack::parse_partial(&encoded);
+    ///     assert_eq!(decoded, MsgPack::Map(vec![
+    ///         MapElement { key: MsgPack::String("id".to_string()), value: MsgPack::Int(42) },
+    ///         MapElement { key: MsgPack::String("name".to_string()), value: MsgPack::Nil }
+    ///     ]));
+    ///     assert_eq!(errors[0].path, vec![1]);
+    pub fn parse_partial (raw: &[u8]) -> (MsgPack, Vec<PartialError>) {
+        let (result, _, errors) = parser::parse_partial(raw);
+        (result, errors)
+    }
+
     /// Encodes a MsgPack enum into binary format
     /// 
     ///     use msgpack_simple::MsgPack;
@@ -354,191 +615,63 @@ impl MsgPack {
     ///     let data = vec![0xaa, 0x48, 0x65, 0x6c, 0x6c, 0x6f, 0x20, 0x52, 0x75, 0x73, 0x74];
     ///     assert_eq!(encoded, data);
     pub fn encode (&self) -> Vec<u8> {
-        match self {
-            MsgPack::Nil => vec![0xc0],
-            MsgPack::Boolean(value) => vec![if *value { 0xc3 } else { 0xc2 }],
-            MsgPack::Int(value) => {
-                let value = *value;
-                if value >= 0 && value < 128 { return vec![value as u8] }
-                if value < 0 && value > -32 {
-                    let raw = unsafe { std::mem::transmute::<i8, u8>(value as i8) };
-                    return vec![raw];
-                };
-
-                let mut result = vec![];
-
-                if value >= -0x80 && value < 0x80 {
-                    result.push(0xd0);
-                    result.write_i8(value as i8).unwrap();
-                } else if value >= -0x8000 && value < 0x8000 {
-                    result.push(0xd1);
-                    result.write_i16::<BigEndian>(value as i16).unwrap();
-                } else if value >= -0x8000_0000 && value < 0x8000_0000 {
-                    result.push(0xd2);
-                    result.write_i32::<BigEndian>(value as i32).unwrap();
-                } else {
-                    result.push(0xd3);
-                    result.write_i64::<BigEndian>(value).unwrap();
-                }
-
-                result
-            },
-            MsgPack::Uint(value) => {
-                let value = *value;
-                // not writing Uint as fixint retains integer types in the decoded value
-                // if value < 128 { return vec![value as u8] }
-
-                let mut result = vec![];
-
-                if value <= 0x88 {
-                    result.push(0xcc);
-                    result.write_u8(value as u8).unwrap();
-                } else if value <= 0x8888 {
-                    result.push(0xcd);
-                    result.write_u16::<BigEndian>(value as u16).unwrap();
-                } else if value <= 0x8888_8888 {
-                    result.push(0xce);
-                    result.write_u32::<BigEndian>(value as u32).unwrap();
-                } else {
-                    result.push(0xcf);
-                    result.write_u64::<BigEndian>(value).unwrap();
-                }
-
-                result
-            },
-            MsgPack::Float(value) => {
-                // since it's nontrivial when float32 is enough and when it's not, we're just going to always use float64
-                let mut result = vec![0xcb];
-                let int_value = unsafe { std::mem::transmute::<f64, u64>(*value) };
+        let mut result = vec![];
+        self.encode_into(&mut result);
+        result
+    }
 
-                result.write_u64::<BigEndian>(int_value).unwrap();
-                result
-            },
+    // writing to a vector can't fail, so the unwraps are safe
+    fn encode_into (&self, result: &mut Vec<u8>) {
+        match self {
+            MsgPack::Nil => result.push(0xc0),
+            MsgPack::Boolean(value) => result.push(if *value { 0xc3 } else { 0xc2 }),
+            MsgPack::Int(value) => marker::write_int(result, *value).unwrap(),
+            MsgPack::Uint(value) => marker::write_uint(result, *value).unwrap(),
+            MsgPack::Float(value) => marker::write_float(result, *value).unwrap(),
             MsgPack::String(value) => {
-                let bytes = value.as_bytes();
-                let length = bytes.len();
-                let mut result = Vec::with_capacity(length + 5);
-
-                // encode length
-                if length < 32 {
-                    result.push(0xa0 | length as u8);
-                } else if length <= 0x88 {
-                    result.push(0xd9);
-                    result.write_u8(length as u8).unwrap();
-                } else if length <= 0x8888 {
-                    result.push(0xda);
-                    result.write_u16::<BigEndian>(length as u16).unwrap();
-                } else {
-                    result.push(0xdb);
-                    result.write_u32::<BigEndian>(length as u32).unwrap();
-                }
-
-                // now that length is encoded, time to add the actual string
-                result.extend_from_slice(bytes);
-                result
-            }
+                marker::write_string_header(result, value.len()).unwrap();
+                result.extend_from_slice(value.as_bytes());
+            },
             MsgPack::Binary(value) => {
-                let length = value.len();
-                let mut result = Vec::with_capacity(length + 5);
-
-                // encode length
-                if length <= 0x88 {
-                    result.push(0xc4);
-                    result.write_u8(length as u8).unwrap();
-                } else if length <= 0x8888 {
-                    result.push(0xc5);
-                    result.write_u16::<BigEndian>(length as u16).unwrap();
-                } else {
-                    result.push(0xc6);
-                    result.write_u32::<BigEndian>(length as u32).unwrap();
-                }
-
-                // after length is encoded, add the actual value
+                marker::write_binary_header(result, value.len()).unwrap();
                 result.extend_from_slice(value);
-                result
             },
             MsgPack::Extension(extension) => {
-                let value = &extension.value;
-                let type_id = unsafe { std::mem::transmute::<i8, u8>(extension.type_id) };
-
-                let length = value.len();
-                let mut result = Vec::with_capacity(length + 6);
-
-                // encode length (wow there are a lot of options here)
-                if length == 1 {
-                    result.push(0xd4);
-                } else if length == 2 {
-                    result.push(0xd5);
-                } else if length == 4 {
-                    result.push(0xd6);
-                } else if length == 8 {
-                    result.push(0xd7);
-                } else if length == 16 {
-                    result.push(0xd8);
-                } else if length <= 0x88 {
-                    result.push(0xc7);
-                    result.write_u8(length as u8).unwrap();
-                } else if length <= 0x8888 {
-                    result.push(0xc8);
-                    result.write_u16::<BigEndian>(length as u16).unwrap();
-                } else {
-                    result.push(0xc9);
-                    result.write_u32::<BigEndian>(length as u32).unwrap();
-                }
-
-                // with length encoded now we can add the tuple
-                result.push(type_id);
-                result.extend_from_slice(value);
-                result
+                marker::write_extension_header(result, extension.value.len(), extension.type_id).unwrap();
+                result.extend_from_slice(&extension.value);
             },
             MsgPack::Array(value) => {
-                let length = value.len();
-                let mut result = vec![];
-
-                // encode length
-                if length < 16 {
-                    result.push(0x90 | length as u8);
-                } else if length <= 0x8888 {
-                    result.push(0xdc);
-                    result.write_u16::<BigEndian>(length as u16).unwrap();
-                } else {
-                    result.push(0xdd);
-                    result.write_u32::<BigEndian>(length as u32).unwrap();
-                }
-
-                // now just add all the values
+                marker::write_array_header(result, value.len()).unwrap();
                 for item in value {
-                    result.append(&mut item.encode());
+                    item.encode_into(result);
                 }
-
-                result
             },
             MsgPack::Map(value) => {
-                let length = value.len();
-                let mut result = vec![];
-
-                // encode length
-                if length < 16 {
-                    result.push(0x80 | length as u8);
-                } else if length <= 0x8888 {
-                    result.push(0xde);
-                    result.write_u16::<BigEndian>(length as u16).unwrap();
-                } else {
-                    result.push(0xdf);
-                    result.write_u32::<BigEndian>(length as u32).unwrap();
-                }
-
-                // and add the values
+                marker::write_map_header(result, value.len()).unwrap();
                 for item in value {
-                    result.append(&mut item.key.encode());
-                    result.append(&mut item.value.encode());
+                    item.key.encode_into(result);
+                    item.value.encode_into(result);
                 }
-
-                result
             }
         }
     }
 
+    // name of the variant, for error messages
+    pub(crate) fn type_name (&self) -> &'static str {
+        match self {
+            MsgPack::Nil => "nil",
+            MsgPack::Int(_) => "int",
+            MsgPack::Uint(_) => "uint",
+            MsgPack::Float(_) => "float",
+            MsgPack::Boolean(_) => "boolean",
+            MsgPack::String(_) => "string",
+            MsgPack::Binary(_) => "binary",
+            MsgPack::Array(_) => "array",
+            MsgPack::Map(_) => "map",
+            MsgPack::Extension(_) => "extension",
+        }
+    }
+
     // convenience functions
 
     /// Checks if the MsgPack is an int variant
@@ -548,10 +681,7 @@ impl MsgPack {
     ///     assert_eq!(MsgPack::Int(42).is_int(), true);
     ///     assert_eq!(MsgPack::Float(42.0).is_int(), false);
     pub fn is_int (&self) -> bool {
-        match self {
-            MsgPack::Int(_) => true,
-            _ => false
-        }
+        matches!(self, MsgPack::Int(_))
     }
     /// Consumes the MsgPack as int
     /// 
@@ -561,7 +691,7 @@ impl MsgPack {
     pub fn as_int (self) -> Result<i64, ConversionError> {
         match self {
             MsgPack::Int(value) => Ok(value),
-            _ => Err(ConversionError { original: self, attempted: "int" })
+            _ => Err(ConversionError::type_error(self, "int"))
         }
     }
     /// Checks if the MsgPack is a uint variant
@@ -571,10 +701,7 @@ impl MsgPack {
     ///     assert_eq!(MsgPack::Uint(42).is_uint(), true);
     ///     assert_eq!(MsgPack::Float(42.0).is_uint(), false);
     pub fn is_uint (&self) -> bool {
-        match self {
-            MsgPack::Uint(_) => true,
-            _ => false
-        }
+        matches!(self, MsgPack::Uint(_))
     }
     /// Consumes the MsgPack as uint
     /// 
@@ -584,7 +711,7 @@ impl MsgPack {
     pub fn as_uint (self) -> Result<u64, ConversionError> {
         match self {
             MsgPack::Uint(value) => Ok(value),
-            _ => Err(ConversionError { original: self, attempted: "uint" })
+            _ => Err(ConversionError::type_error(self, "uint"))
         }
     }
     /// Checks if the MsgPack is one of the integer variants
@@ -595,23 +722,40 @@ impl MsgPack {
     ///     assert_eq!(MsgPack::Uint(42).is_some_int(), true);
     ///     assert_eq!(MsgPack::Float(42.0).is_some_int(), false);
     pub fn is_some_int (&self) -> bool {
-        match self {
-            MsgPack::Uint(_) => true,
-            MsgPack::Int(_) => true,
-            _ => false
-        }
+        matches!(self, MsgPack::Uint(_) | MsgPack::Int(_))
     }
     /// Consumes the MsgPack as an int, even if it's a uint
     /// 
+    /// Uints above `i64::MAX` are rejected instead of wrapping around.
+    /// 
     ///     use msgpack_simple::MsgPack;
     /// 
     ///     assert_eq!(MsgPack::Int(42).as_some_int().unwrap(), 42);
     ///     assert_eq!(MsgPack::Uint(42).as_some_int().unwrap(), 42);
+    ///     assert!(MsgPack::Uint(u64::MAX).as_some_int().is_err());
     pub fn as_some_int (self) -> Result<i64, ConversionError> {
         match self {
             MsgPack::Int(value) => Ok(value),
-            MsgPack::Uint(value) => Ok(value as i64),
-            _ => Err(ConversionError { original: self, attempted: "int" })
+            MsgPack::Uint(value) if value <= i64::MAX as u64 => Ok(value as i64),
+            MsgPack::Uint(_) => Err(ConversionError::range_error(self, "i64")),
+            _ => Err(ConversionError::type_error(self, "int"))
+        }
+    }
+    /// Consumes the MsgPack as a uint, even if it's an int
+    /// 
+    /// Negative ints are rejected instead of wrapping around.
+    /// 
+    ///     use msgpack_simple::MsgPack;
+    /// 
+    ///     assert_eq!(MsgPack::Int(42).as_some_uint().unwrap(), 42);
+    ///     assert_eq!(MsgPack::Uint(42).as_some_uint().unwrap(), 42);
+    ///     assert!(MsgPack::Int(-1).as_some_uint().is_err());
+    pub fn as_some_uint (self) -> Result<u64, ConversionError> {
+        match self {
+            MsgPack::Uint(value) => Ok(value),
+            MsgPack::Int(value) if value >= 0 => Ok(value as u64),
+            MsgPack::Int(_) => Err(ConversionError::range_error(self, "u64")),
+            _ => Err(ConversionError::type_error(self, "uint"))
         }
     }
     /// Checks if the MsgPack is a float
@@ -621,10 +765,7 @@ impl MsgPack {
     ///     assert_eq!(MsgPack::Float(42.0).is_float(), true);
     ///     assert_eq!(MsgPack::Int(42).is_float(), false);
     pub fn is_float (&self) -> bool {
-        match self {
-            MsgPack::Float(_) => true,
-            _ => false
-        }
+        matches!(self, MsgPack::Float(_))
     }
     /// Consumes the MsgPack as a float
     /// 
@@ -634,7 +775,7 @@ impl MsgPack {
     pub fn as_float (self) -> Result<f64, ConversionError> {
         match self {
             MsgPack::Float(value) => Ok(value),
-            _ => Err(ConversionError { original: self, attempted: "float" })
+            _ => Err(ConversionError::type_error(self, "float"))
         }
     }
     /// Checks if the MsgPack is a boolean
@@ -644,10 +785,7 @@ impl MsgPack {
     ///     assert_eq!(MsgPack::Boolean(true).is_boolean(), true);
     ///     assert_eq!(MsgPack::Int(1).is_boolean(), false);
     pub fn is_boolean (&self) -> bool {
-        match self {
-            MsgPack::Boolean(_) => true,
-            _ => false
-        }
+        matches!(self, MsgPack::Boolean(_))
     }
     /// Consumes the MsgPack as a boolean
     /// 
@@ -657,7 +795,7 @@ impl MsgPack {
     pub fn as_boolean (self) -> Result<bool, ConversionError> {
         match self {
             MsgPack::Boolean(value) => Ok(value),
-            _ => Err(ConversionError { original: self, attempted: "boolean" })
+            _ => Err(ConversionError::type_error(self, "boolean"))
         }
     }
     /// Checks if the MsgPack is a nil
@@ -667,10 +805,7 @@ impl MsgPack {
     ///     assert_eq!(MsgPack::Nil.is_nil(), true);
     ///     assert_eq!(MsgPack::Boolean(false).is_nil(), false);
     pub fn is_nil (&self) -> bool {
-        match self {
-            MsgPack::Nil => true,
-            _ => false
-        }
+        matches!(self, MsgPack::Nil)
     }
     /// Checks if the MsgPack is a string
     /// 
@@ -679,10 +814,7 @@ impl MsgPack {
     ///     assert_eq!(MsgPack::String("foo".to_string()).is_string(), true);
     ///     assert_eq!(MsgPack::Binary(vec![0x66, 0x6f, 0x6f]).is_string(), false);
     pub fn is_string (&self) -> bool {
-        match self {
-            MsgPack::String(_) => true,
-            _ => false
-        }
+        matches!(self, MsgPack::String(_))
     }
     /// Consumes the MsgPack as a string
     /// 
@@ -692,7 +824,7 @@ impl MsgPack {
     pub fn as_string (self) -> Result<String, ConversionError> {
         match self {
             MsgPack::String(value) => Ok(value),
-            _ => Err(ConversionError { original: self, attempted: "string" })
+            _ => Err(ConversionError::type_error(self, "string"))
         }
     }
     /// Checks if the MsgPack is a binary
@@ -702,10 +834,7 @@ impl MsgPack {
     ///     assert_eq!(MsgPack::Binary(vec![0x66, 0x6f, 0x6f]).is_binary(), true);
     ///     assert_eq!(MsgPack::String("foo".to_string()).is_binary(), false);
     pub fn is_binary (&self) -> bool {
-        match self {
-            MsgPack::Binary(_) => true,
-            _ => false
-        }
+        matches!(self, MsgPack::Binary(_))
     }
     /// Consumes the MsgPack as a binary
     /// 
@@ -715,7 +844,7 @@ impl MsgPack {
     pub fn as_binary (self) -> Result<Vec<u8>, ConversionError> {
         match self {
             MsgPack::Binary(value) => Ok(value),
-            _ => Err(ConversionError { original: self, attempted: "binary" })
+            _ => Err(ConversionError::type_error(self, "binary"))
         }
     }
     /// Checks if the MsgPack is an array
@@ -725,10 +854,7 @@ impl MsgPack {
     ///     assert_eq!(MsgPack::Array(vec![]).is_array(), true);
     ///     assert_eq!(MsgPack::Map(vec![]).is_array(), false);
     pub fn is_array (&self) -> bool {
-        match self {
-            MsgPack::Array(_) => true,
-            _ => false
-        }
+        matches!(self, MsgPack::Array(_))
     }
     /// Consumes the MsgPack as an array
     /// 
@@ -738,7 +864,7 @@ impl MsgPack {
     pub fn as_array (self) -> Result<Vec<MsgPack>, ConversionError> {
         match self {
             MsgPack::Array(value) => Ok(value),
-            _ => Err(ConversionError { original: self, attempted: "array" })
+            _ => Err(ConversionError::type_error(self, "array"))
         }
     }
     /// Checks if the MsgPack is a map
@@ -748,10 +874,7 @@ impl MsgPack {
     ///     assert_eq!(MsgPack::Map(vec![]).is_map(), true);
     ///     assert_eq!(MsgPack::Array(vec![]).is_map(), false);
     pub fn is_map (&self) -> bool {
-        match self {
-            MsgPack::Map(_) => true,
-            _ => false
-        }
+        matches!(self, MsgPack::Map(_))
     }
     /// Consumes the MsgPack as a map
     /// 
@@ -761,7 +884,7 @@ impl MsgPack {
     pub fn as_map (self) -> Result<Vec<MapElement>, ConversionError> {
         match self {
             MsgPack::Map(value) => Ok(value),
-            _ => Err(ConversionError { original: self, attempted: "map" })
+            _ => Err(ConversionError::type_error(self, "map"))
         }
     }
     /// Checks if the MsgPack is an extension
@@ -772,10 +895,7 @@ impl MsgPack {
     ///     assert_eq!(MsgPack::Extension(value).is_extension(), true);
     ///     assert_eq!(MsgPack::Binary(vec![0x42]).is_extension(), false);
     pub fn is_extension (&self) -> bool {
-        match self {
-            MsgPack::Extension(_) => true,
-            _ => false
-        }
+        matches!(self, MsgPack::Extension(_))
     }
     /// Consumes the MsgPack as an extension
     /// 
@@ -786,7 +906,7 @@ impl MsgPack {
     pub fn as_extension (self) -> Result<Extension, ConversionError> {
         match self {
             MsgPack::Extension(value) => Ok(value),
-            _ => Err(ConversionError { original: self, attempted: "extension" })
+            _ => Err(ConversionError::type_error(self, "extension"))
         }
     }
 }
@@ -832,6 +952,7 @@ impl std::fmt::Display for MsgPack {
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use super::*;
 
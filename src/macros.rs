@@ -0,0 +1,215 @@
+//! The msgpack! macro
+
+/// Builds a MsgPack value from a JSON-like literal
+/// 
+/// Maps are written with braces and arrays with brackets, `nil`, `true` and
+/// `false` are the keywords you'd expect, binaries are written as
+/// `bin[bytes...]`, and extensions as `ext(type_id, [bytes...])`. Anything
+/// else is taken as a Rust expression and converted with `MsgPack::from`, so
+/// variables can be interpolated directly. Map keys that are not a single
+/// token need to be wrapped in parentheses.
+/// 
+///     use msgpack_simple::{msgpack, MsgPack, MapElement, Extension};
+/// 
+///     let answer = 42;
+///     let message = msgpack!({
+///         "hello": answer,
+///         "world": [true, nil, bin[0x42, 0xff], ext(2, [0x32, 0x4a, 0x67, 0x11])],
+///         ("computed".to_string()): { 1: "one" }
+///     });
+/// 
+///     assert_eq!(message, MsgPack::Map(vec![
+///         MapElement {
+///             key: MsgPack::String(String::from("hello")),
+///             value: MsgPack::Int(42)
+///         },
+///         MapElement {
+///             key: MsgPack::String(String::from("world")),
+///             value: MsgPack::Array(vec![
+///                 MsgPack::Boolean(true),
+///                 MsgPack::Nil,
+///                 MsgPack::Binary(vec![0x42, 0xff]),
+///                 MsgPack::Extension(Extension {
+///                     type_id: 2,
+///                     value: vec![0x32, 0x4a, 0x67, 0x11]
+///                 })
+///             ])
+///         },
+///         MapElement {
+///             key: MsgPack::String(String::from("computed")),
+///             value: MsgPack::Map(vec![
+///                 MapElement { key: MsgPack::Int(1), value: MsgPack::String(String::from("one")) }
+///             ])
+///         }
+///     ]));
+#[macro_export]
+macro_rules! msgpack {
+    // arrays are munched one element at a time, collecting finished elements
+    // in the brackets
+
+    (@array [$($elems:expr,)*]) => {
+        vec![$($elems,)*]
+    };
+    (@array [$($elems:expr),*]) => {
+        vec![$($elems),*]
+    };
+    (@array [$($elems:expr,)*] nil $($rest:tt)*) => {
+        $crate::msgpack!(@array [$($elems,)* $crate::msgpack!(nil)] $($rest)*)
+    };
+    (@array [$($elems:expr,)*] true $($rest:tt)*) => {
+        $crate::msgpack!(@array [$($elems,)* $crate::msgpack!(true)] $($rest)*)
+    };
+    (@array [$($elems:expr,)*] false $($rest:tt)*) => {
+        $crate::msgpack!(@array [$($elems,)* $crate::msgpack!(false)] $($rest)*)
+    };
+    (@array [$($elems:expr,)*] bin [$($bytes:tt)*] $($rest:tt)*) => {
+        $crate::msgpack!(@array [$($elems,)* $crate::msgpack!(bin [$($bytes)*])] $($rest)*)
+    };
+    (@array [$($elems:expr,)*] ext ($($ext:tt)*) $($rest:tt)*) => {
+        $crate::msgpack!(@array [$($elems,)* $crate::msgpack!(ext ($($ext)*))] $($rest)*)
+    };
+    (@array [$($elems:expr,)*] [$($array:tt)*] $($rest:tt)*) => {
+        $crate::msgpack!(@array [$($elems,)* $crate::msgpack!([$($array)*])] $($rest)*)
+    };
+    (@array [$($elems:expr,)*] {$($map:tt)*} $($rest:tt)*) => {
+        $crate::msgpack!(@array [$($elems,)* $crate::msgpack!({$($map)*})] $($rest)*)
+    };
+    (@array [$($elems:expr,)*] $next:expr, $($rest:tt)*) => {
+        $crate::msgpack!(@array [$($elems,)* $crate::msgpack!($next),] $($rest)*)
+    };
+    (@array [$($elems:expr,)*] $last:expr) => {
+        $crate::msgpack!(@array [$($elems,)* $crate::msgpack!($last)])
+    };
+    (@array [$($elems:expr),*] , $($rest:tt)*) => {
+        $crate::msgpack!(@array [$($elems,)*] $($rest)*)
+    };
+
+    // maps are munched by first collecting the tokens of the key in
+    // parentheses, then the value, and adding the finished element to the
+    // brackets
+
+    (@map [$($elems:expr,)*] ()) => {
+        vec![$($elems,)*]
+    };
+    (@map [$($elems:expr,)*] [$($key:tt)+] ($value:expr) , $($rest:tt)*) => {
+        $crate::msgpack!(@map [$($elems,)* $crate::MapElement { key: $crate::msgpack!($($key)+), value: $value },] () $($rest)*)
+    };
+    (@map [$($elems:expr,)*] [$($key:tt)+] ($value:expr)) => {
+        vec![$($elems,)* $crate::MapElement { key: $crate::msgpack!($($key)+), value: $value }]
+    };
+    (@map [$($elems:expr,)*] ($($key:tt)+) : nil $($rest:tt)*) => {
+        $crate::msgpack!(@map [$($elems,)*] [$($key)+] ($crate::msgpack!(nil)) $($rest)*)
+    };
+    (@map [$($elems:expr,)*] ($($key:tt)+) : true $($rest:tt)*) => {
+        $crate::msgpack!(@map [$($elems,)*] [$($key)+] ($crate::msgpack!(true)) $($rest)*)
+    };
+    (@map [$($elems:expr,)*] ($($key:tt)+) : false $($rest:tt)*) => {
+        $crate::msgpack!(@map [$($elems,)*] [$($key)+] ($crate::msgpack!(false)) $($rest)*)
+    };
+    (@map [$($elems:expr,)*] ($($key:tt)+) : bin [$($bytes:tt)*] $($rest:tt)*) => {
+        $crate::msgpack!(@map [$($elems,)*] [$($key)+] ($crate::msgpack!(bin [$($bytes)*])) $($rest)*)
+    };
+    (@map [$($elems:expr,)*] ($($key:tt)+) : ext ($($ext:tt)*) $($rest:tt)*) => {
+        $crate::msgpack!(@map [$($elems,)*] [$($key)+] ($crate::msgpack!(ext ($($ext)*))) $($rest)*)
+    };
+    (@map [$($elems:expr,)*] ($($key:tt)+) : [$($array:tt)*] $($rest:tt)*) => {
+        $crate::msgpack!(@map [$($elems,)*] [$($key)+] ($crate::msgpack!([$($array)*])) $($rest)*)
+    };
+    (@map [$($elems:expr,)*] ($($key:tt)+) : {$($inner:tt)*} $($rest:tt)*) => {
+        $crate::msgpack!(@map [$($elems,)*] [$($key)+] ($crate::msgpack!({$($inner)*})) $($rest)*)
+    };
+    (@map [$($elems:expr,)*] ($($key:tt)+) : $value:expr , $($rest:tt)*) => {
+        $crate::msgpack!(@map [$($elems,)*] [$($key)+] ($crate::msgpack!($value)) , $($rest)*)
+    };
+    (@map [$($elems:expr,)*] ($($key:tt)+) : $value:expr) => {
+        $crate::msgpack!(@map [$($elems,)*] [$($key)+] ($crate::msgpack!($value)))
+    };
+    (@map [$($elems:expr,)*] () $key:tt $($rest:tt)*) => {
+        $crate::msgpack!(@map [$($elems,)*] ($key) $($rest)*)
+    };
+    (@map [$($elems:expr,)*] ($($key:tt)+) $next:tt $($rest:tt)*) => {
+        $crate::msgpack!(@map [$($elems,)*] ($($key)+ $next) $($rest)*)
+    };
+
+    // entry points
+
+    (nil) => {
+        $crate::MsgPack::Nil
+    };
+    (true) => {
+        $crate::MsgPack::Boolean(true)
+    };
+    (false) => {
+        $crate::MsgPack::Boolean(false)
+    };
+    (bin [$($byte:expr),* $(,)?]) => {
+        $crate::MsgPack::Binary(vec![$($byte),*])
+    };
+    (ext ($type_id:expr, [$($byte:expr),* $(,)?])) => {
+        $crate::MsgPack::Extension($crate::Extension { type_id: $type_id, value: vec![$($byte),*] })
+    };
+    (ext ($type_id:expr, $value:expr)) => {
+        $crate::MsgPack::Extension($crate::Extension { type_id: $type_id, value: $value })
+    };
+    ([]) => {
+        $crate::MsgPack::Array(vec![])
+    };
+    ([$($tt:tt)+]) => {
+        $crate::MsgPack::Array($crate::msgpack!(@array [] $($tt)+))
+    };
+    ({}) => {
+        $crate::MsgPack::Map(vec![])
+    };
+    ({$($tt:tt)+}) => {
+        $crate::MsgPack::Map($crate::msgpack!(@map [] () $($tt)+))
+    };
+    ($other:expr) => {
+        $crate::MsgPack::from($other)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{MsgPack, MapElement, Extension};
+
+    #[test]
+    fn literals () {
+        assert_eq!(msgpack!(nil), MsgPack::Nil);
+        assert_eq!(msgpack!(false), MsgPack::Boolean(false));
+        assert_eq!(msgpack!(-3), MsgPack::Int(-3));
+        assert_eq!(msgpack!(1.5), MsgPack::Float(1.5));
+        assert_eq!(msgpack!("foo"), MsgPack::String("foo".to_string()));
+        assert_eq!(msgpack!(bin[]), MsgPack::Binary(vec![]));
+        assert_eq!(msgpack!(bin[1, 2,]), MsgPack::Binary(vec![1, 2]));
+        assert_eq!(msgpack!(ext(-1, vec![0; 4])), MsgPack::Extension(Extension { type_id: -1, value: vec![0; 4] }));
+        assert_eq!(msgpack!([]), MsgPack::Array(vec![]));
+        assert_eq!(msgpack!({}), MsgPack::Map(vec![]));
+    }
+
+    #[test]
+    fn nested () {
+        let name = "Rust";
+        let tags = vec!["fast", "safe"];
+
+        let message = msgpack!({
+            "name": name,
+            "tags": tags,
+            "sum": 1 + 2,
+            nil: [[], {}, [nil, true], { "a": false, }],
+            (name.len()): ext(3, [1]),
+        });
+
+        assert_eq!(message, MsgPack::Map(vec![
+            MapElement { key: MsgPack::from("name"), value: MsgPack::from("Rust") },
+            MapElement { key: MsgPack::from("tags"), value: MsgPack::Array(vec![MsgPack::from("fast"), MsgPack::from("safe")]) },
+            MapElement { key: MsgPack::from("sum"), value: MsgPack::Int(3) },
+            MapElement { key: MsgPack::Nil, value: MsgPack::Array(vec![
+                MsgPack::Array(vec![]),
+                MsgPack::Map(vec![]),
+                MsgPack::Array(vec![MsgPack::Nil, MsgPack::Boolean(true)]),
+                MsgPack::Map(vec![MapElement { key: MsgPack::from("a"), value: MsgPack::Boolean(false) }])
+            ]) },
+            MapElement { key: MsgPack::Uint(4), value: MsgPack::Extension(Extension { type_id: 3, value: vec![1] }) }
+        ]));
+    }
+}
@@ -0,0 +1,564 @@
+//! A map type with indexed key lookup, see [MsgPackMap](struct.MsgPackMap.html)
+
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::convert::TryFrom;
+use std::fmt::{self, Debug, Formatter};
+use std::hash::{Hash, Hasher};
+use std::iter::FromIterator;
+
+use super::{MsgPack, MapElement};
+use super::cmp::{float_bits, NIL, BOOLEAN, INTEGER, FLOAT, STRING, BINARY, ARRAY, MAP, EXTENSION};
+use super::error::ConversionError;
+use super::index::MapKey;
+
+fn write_value (value: &MsgPack, state: &mut DefaultHasher) {
+    match value {
+        MsgPack::Nil => state.write_u8(NIL),
+        MsgPack::Boolean(value) => { state.write_u8(BOOLEAN); value.hash(state) },
+        MsgPack::Int(value) => { state.write_u8(INTEGER); (*value as i128).hash(state) },
+        MsgPack::Uint(value) => { state.write_u8(INTEGER); (*value as i128).hash(state) },
+        MsgPack::Float(value) => { state.write_u8(FLOAT); float_bits(*value).hash(state) },
+        MsgPack::String(value) => { state.write_u8(STRING); value.hash(state) },
+        MsgPack::Binary(value) => { state.write_u8(BINARY); value.hash(state) },
+        MsgPack::Array(value) => {
+            state.write_u8(ARRAY);
+            state.write_usize(value.len());
+            for item in value { write_value(item, state) }
+        },
+        MsgPack::Map(value) => {
+            state.write_u8(MAP);
+            state.write_usize(value.len());
+            for item in value {
+                write_value(&item.key, state);
+                write_value(&item.value, state);
+            }
+        },
+        MsgPack::Extension(value) => { state.write_u8(EXTENSION); value.hash(state) },
+    }
+}
+
+pub(crate) fn hash_key (key: &MsgPack) -> u64 {
+    let mut state = DefaultHasher::new();
+    write_value(key, &mut state);
+    state.finish()
+}
+
+pub(crate) fn hash_str (key: &str) -> u64 {
+    let mut state = DefaultHasher::new();
+    state.write_u8(STRING);
+    key.hash(&mut state);
+    state.finish()
+}
+
+pub(crate) fn hash_int (key: i128) -> u64 {
+    let mut state = DefaultHasher::new();
+    state.write_u8(INTEGER);
+    key.hash(&mut state);
+    state.finish()
+}
+
+/// A MessagePack map with fast key lookup
+/// 
+/// `MsgPack::Map` stores its elements in a `Vec<MapElement>`, so finding a key
+/// means checking every element. MsgPackMap keeps the same vector, in
+/// insertion order and with any duplicate keys, so it encodes back to exactly
+/// the same map, but it also maintains a hash index over the keys.
+/// 
+/// Lookups use [MapKey](../trait.MapKey.html), just like `MsgPack::get()`, and
+/// so do `insert()` and `entry()`, so an Int and a Uint with the same value are
+/// the same key everywhere. If a key appears more than once, lookups find the
+/// first one. Removing an element
+/// takes linear time, since the elements after it are shifted to keep the
+/// order.
+/// 
+///     use std::convert::TryFrom;
+///     use msgpack_simple::{msgpack, MsgPack, MsgPackMap};
+/// 
+///     let message = msgpack!({ "id": 42, "name": "Ferris" });
+///     let mut map = MsgPackMap::try_from(message).unwrap();
+/// 
+///     assert_eq!(map.get("id"), Some(&msgpack!(42)));
+/// 
+///     map.insert("name", "Corro");
+///     map.insert("age", 7);
+///     *map.entry("id").or_insert(0) = msgpack!(43);
+///     map.remove("age");
+/// 
+///     assert_eq!(MsgPack::from(map), msgpack!({ "id": 43, "name": "Corro" }));
+#[derive(Clone, Default)]
+pub struct MsgPackMap {
+    entries: Vec<MapElement>,
+    index: HashMap<u64, Vec<usize>>,
+}
+
+impl MsgPackMap {
+    /// Creates an empty map
+    pub fn new () -> MsgPackMap {
+        MsgPackMap::default()
+    }
+
+    /// Creates an empty map with space for `capacity` elements
+    pub fn with_capacity (capacity: usize) -> MsgPackMap {
+        MsgPackMap { entries: Vec::with_capacity(capacity), index: HashMap::with_capacity(capacity) }
+    }
+
+    /// The number of elements in the map, counting duplicate keys separately
+    pub fn len (&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Checks if the map has no elements
+    pub fn is_empty (&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn position <K: MapKey> (&self, key: &K) -> Option<usize> {
+        match key.key_hash() {
+            Some(hash) => self.index.get(&hash)?.iter().copied().find(|&position| key.matches_key(&self.entries[position].key)),
+            None => self.entries.iter().position(|element| key.matches_key(&element.key)),
+        }
+    }
+
+    /// Looks up the value of a key
+    pub fn get <K: MapKey> (&self, key: K) -> Option<&MsgPack> {
+        let position = self.position(&key)?;
+        Some(&self.entries[position].value)
+    }
+
+    /// Looks up the value of a key, as a mutable reference
+    pub fn get_mut <K: MapKey> (&mut self, key: K) -> Option<&mut MsgPack> {
+        let position = self.position(&key)?;
+        Some(&mut self.entries[position].value)
+    }
+
+    /// Gets the values of every element with a matching key, in order
+    /// 
+    ///     use msgpack_simple::{msgpack, MsgPackMap};
+    /// 
+    ///     let mut map = MsgPackMap::new();
+    ///     map.push("tag", "foo");
+    ///     map.push("tag", "bar");
+    /// 
+    ///     assert_eq!(map.len(), 2);
+    ///     assert_eq!(map.get("tag"), Some(&msgpack!("foo")));
+    ///     assert_eq!(map.get_all("tag"), vec![&msgpack!("foo"), &msgpack!("bar")]);
+    pub fn get_all <K: MapKey> (&self, key: K) -> Vec<&MsgPack> {
+        let positions: Vec<usize> = match key.key_hash() {
+            Some(hash) => self.index.get(&hash).cloned().unwrap_or_default(),
+            None => (0..self.entries.len()).collect(),
+        };
+
+        positions.into_iter()
+            .filter(|&position| key.matches_key(&self.entries[position].key))
+            .map(|position| &self.entries[position].value)
+            .collect()
+    }
+
+    /// Checks if the map has a matching key
+    pub fn contains_key <K: MapKey> (&self, key: K) -> bool {
+        self.position(&key).is_some()
+    }
+
+    /// Sets the value of a key, returning the previous one if the key was
+    /// already in the map. New keys are added to the end. Integer keys replace
+    /// equal integers of either variant.
+    pub fn insert <K: Into<MsgPack>, V: Into<MsgPack>> (&mut self, key: K, value: V) -> Option<MsgPack> {
+        match self.entry(key) {
+            Entry::Occupied(mut entry) => Some(entry.insert(value)),
+            Entry::Vacant(entry) => {
+                entry.insert(value);
+                None
+            }
+        }
+    }
+
+    /// Adds an element to the end of the map, even if the key is already in it
+    pub fn push <K: Into<MsgPack>, V: Into<MsgPack>> (&mut self, key: K, value: V) {
+        let key = key.into();
+        let hash = hash_key(&key);
+        self.push_hashed(key, hash, value.into());
+    }
+
+    fn push_hashed (&mut self, key: MsgPack, hash: u64, value: MsgPack) -> usize {
+        let position = self.entries.len();
+        self.entries.push(MapElement { key, value });
+        self.index.entry(hash).or_default().push(position);
+        position
+    }
+
+    /// Removes the first element with a matching key and returns its value
+    pub fn remove <K: MapKey> (&mut self, key: K) -> Option<MsgPack> {
+        let position = self.position(&key)?;
+        Some(self.remove_at(position).value)
+    }
+
+    fn remove_at (&mut self, position: usize) -> MapElement {
+        let element = self.entries.remove(position);
+
+        let hash = hash_key(&element.key);
+        if let Some(positions) = self.index.get_mut(&hash) {
+            positions.retain(|&other| other != position);
+            if positions.is_empty() { self.index.remove(&hash); }
+        }
+
+        // every element after it moved down by one
+        for (offset, moved) in self.entries[position..].iter().enumerate() {
+            if let Some(positions) = self.index.get_mut(&hash_key(&moved.key)) {
+                for other in positions.iter_mut() {
+                    if *other == position + offset + 1 { *other -= 1 }
+                }
+            }
+        }
+
+        element
+    }
+
+    /// Gets the entry of a key for in-place manipulation
+    /// 
+    ///     use msgpack_simple::{msgpack, MsgPackMap};
+    /// 
+    ///     let mut map = MsgPackMap::new();
+    ///     for word in vec!["a", "b", "a"] {
+    ///         let count = map.entry(word).or_insert(0);
+    ///         *count = msgpack!(count.clone().as_some_int().unwrap() + 1);
+    ///     }
+    /// 
+    ///     assert_eq!(map.get("a"), Some(&msgpack!(2)));
+    ///     assert_eq!(map.get("b"), Some(&msgpack!(1)));
+    pub fn entry <K: Into<MsgPack>> (&mut self, key: K) -> Entry<'_> {
+        let key = key.into();
+        let hash = hash_key(&key);
+
+        let found = self.index.get(&hash)
+            .and_then(|positions| positions.iter().copied().find(|&position| key.matches_key(&self.entries[position].key)));
+
+        match found {
+            Some(position) => Entry::Occupied(OccupiedEntry { map: self, position }),
+            None => Entry::Vacant(VacantEntry { map: self, key, hash }),
+        }
+    }
+
+    /// Iterates over the elements in order
+    pub fn iter (&self) -> std::slice::Iter<'_, MapElement> {
+        self.entries.iter()
+    }
+
+    /// Iterates over the elements in order, with mutable access to the values
+    pub fn iter_mut (&mut self) -> impl Iterator<Item = (&MsgPack, &mut MsgPack)> {
+        self.entries.iter_mut().map(|element| (&element.key, &mut element.value))
+    }
+
+    /// Iterates over the keys in order
+    pub fn keys (&self) -> impl Iterator<Item = &MsgPack> {
+        self.entries.iter().map(|element| &element.key)
+    }
+
+    /// Iterates over the values in order
+    pub fn values (&self) -> impl Iterator<Item = &MsgPack> {
+        self.entries.iter().map(|element| &element.value)
+    }
+
+    /// The elements of the map, in order
+    pub fn as_slice (&self) -> &[MapElement] {
+        &self.entries
+    }
+
+    /// Consumes the map, returning its elements in order
+    pub fn into_vec (self) -> Vec<MapElement> {
+        self.entries
+    }
+}
+
+/// An entry of a [MsgPackMap](struct.MsgPackMap.html), from
+/// `MsgPackMap::entry()`
+pub enum Entry<'a> {
+    /// The key is in the map
+    Occupied(OccupiedEntry<'a>),
+    /// The key is not in the map
+    Vacant(VacantEntry<'a>),
+}
+
+/// An entry for a key that is in the map
+pub struct OccupiedEntry<'a> {
+    map: &'a mut MsgPackMap,
+    position: usize,
+}
+
+/// An entry for a key that is not in the map
+pub struct VacantEntry<'a> {
+    map: &'a mut MsgPackMap,
+    key: MsgPack,
+    hash: u64,
+}
+
+impl <'a> Entry<'a> {
+    /// The key of the entry
+    pub fn key (&self) -> &MsgPack {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    /// Inserts a value if the key is not in the map, and returns the value
+    pub fn or_insert <V: Into<MsgPack>> (self, default: V) -> &'a mut MsgPack {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default),
+        }
+    }
+
+    /// Inserts the result of a function if the key is not in the map, and
+    /// returns the value
+    pub fn or_insert_with <V: Into<MsgPack>, F: FnOnce() -> V> (self, default: F) -> &'a mut MsgPack {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    /// Modifies the value if the key is in the map
+    pub fn and_modify <F: FnOnce(&mut MsgPack)> (self, function: F) -> Entry<'a> {
+        match self {
+            Entry::Occupied(mut entry) => {
+                function(entry.get_mut());
+                Entry::Occupied(entry)
+            },
+            Entry::Vacant(entry) => Entry::Vacant(entry),
+        }
+    }
+}
+
+impl <'a> OccupiedEntry<'a> {
+    /// The key of the entry
+    pub fn key (&self) -> &MsgPack {
+        &self.map.entries[self.position].key
+    }
+
+    /// The value of the entry
+    pub fn get (&self) -> &MsgPack {
+        &self.map.entries[self.position].value
+    }
+
+    /// The value of the entry, as a mutable reference
+    pub fn get_mut (&mut self) -> &mut MsgPack {
+        &mut self.map.entries[self.position].value
+    }
+
+    /// Converts the entry into a mutable reference to its value
+    pub fn into_mut (self) -> &'a mut MsgPack {
+        &mut self.map.entries[self.position].value
+    }
+
+    /// Sets the value of the entry, returning the previous one
+    pub fn insert <V: Into<MsgPack>> (&mut self, value: V) -> MsgPack {
+        std::mem::replace(self.get_mut(), value.into())
+    }
+
+    /// Removes the entry from the map, returning its value
+    pub fn remove (self) -> MsgPack {
+        self.map.remove_at(self.position).value
+    }
+}
+
+impl <'a> VacantEntry<'a> {
+    /// The key of the entry
+    pub fn key (&self) -> &MsgPack {
+        &self.key
+    }
+
+    /// Adds the key to the end of the map with a value, and returns the value
+    pub fn insert <V: Into<MsgPack>> (self, value: V) -> &'a mut MsgPack {
+        let position = self.map.push_hashed(self.key, self.hash, value.into());
+        &mut self.map.entries[position].value
+    }
+}
+
+impl Debug for MsgPackMap {
+    fn fmt (&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_map().entries(self.entries.iter().map(|element| (&element.key, &element.value))).finish()
+    }
+}
+
+impl PartialEq for MsgPackMap {
+    fn eq (&self, other: &MsgPackMap) -> bool {
+        self.entries == other.entries
+    }
+}
+
+impl From<Vec<MapElement>> for MsgPackMap {
+    fn from (entries: Vec<MapElement>) -> MsgPackMap {
+        entries.into_iter().collect()
+    }
+}
+
+impl From<MsgPackMap> for Vec<MapElement> {
+    fn from (map: MsgPackMap) -> Vec<MapElement> {
+        map.entries
+    }
+}
+
+impl From<MsgPackMap> for MsgPack {
+    fn from (map: MsgPackMap) -> MsgPack {
+        MsgPack::Map(map.entries)
+    }
+}
+
+impl TryFrom<MsgPack> for MsgPackMap {
+    type Error = ConversionError;
+
+    fn try_from (value: MsgPack) -> Result<MsgPackMap, ConversionError> {
+        Ok(MsgPackMap::from(value.as_map()?))
+    }
+}
+
+impl FromIterator<MapElement> for MsgPackMap {
+    fn from_iter <I: IntoIterator<Item = MapElement>> (iter: I) -> MsgPackMap {
+        let mut map = MsgPackMap::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl Extend<MapElement> for MsgPackMap {
+    fn extend <I: IntoIterator<Item = MapElement>> (&mut self, iter: I) {
+        for element in iter {
+            self.push(element.key, element.value);
+        }
+    }
+}
+
+impl IntoIterator for MsgPackMap {
+    type Item = MapElement;
+    type IntoIter = std::vec::IntoIter<MapElement>;
+
+    fn into_iter (self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+impl <'a> IntoIterator for &'a MsgPackMap {
+    type Item = &'a MapElement;
+    type IntoIter = std::slice::Iter<'a, MapElement>;
+
+    fn into_iter (self) -> Self::IntoIter {
+        self.entries.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn element (key: MsgPack, value: MsgPack) -> MapElement {
+        MapElement { key, value }
+    }
+
+    #[test]
+    fn lookup () {
+        let map = MsgPackMap::from(vec![
+            element(MsgPack::Uint(1), MsgPack::from("uint")),
+            element(MsgPack::from("1"), MsgPack::from("string")),
+            element(MsgPack::Int(-1), MsgPack::from("int")),
+            element(MsgPack::Binary(vec![1]), MsgPack::from("binary")),
+            element(MsgPack::Int(1), MsgPack::from("duplicate")),
+        ]);
+
+        assert_eq!(map.get(1), Some(&MsgPack::from("uint")));
+        assert_eq!(map.get(1u64), Some(&MsgPack::from("uint")));
+        assert_eq!(map.get("1"), Some(&MsgPack::from("string")));
+        assert_eq!(map.get("1".to_string()), Some(&MsgPack::from("string")));
+        assert_eq!(map.get(-1i8), Some(&MsgPack::from("int")));
+        assert_eq!(map.get(MsgPack::Binary(vec![1])), Some(&MsgPack::from("binary")));
+        assert_eq!(map.get(MsgPack::Int(1)), Some(&MsgPack::from("uint")));
+        assert_eq!(map.get(2), None);
+        assert_eq!(map.get_all(1), vec![&MsgPack::from("uint"), &MsgPack::from("duplicate")]);
+        assert_eq!(map.len(), 5);
+    }
+
+    #[test]
+    fn modify () {
+        let mut map = MsgPackMap::new();
+        assert_eq!(map.insert("a", 1), None);
+        assert_eq!(map.insert("b", 2), None);
+        assert_eq!(map.insert("c", 3), None);
+        assert_eq!(map.insert("a", 4), Some(MsgPack::Int(1)));
+        assert_eq!(map.insert(7u8, 1), None);
+        assert_eq!(map.insert(7i64, 2), Some(MsgPack::Int(1)));
+        assert_eq!(map.remove(7), Some(MsgPack::Int(2)));
+
+        assert_eq!(map.remove("b"), Some(MsgPack::Int(2)));
+        assert_eq!(map.remove("b"), None);
+        assert_eq!(map.get("c"), Some(&MsgPack::Int(3)));
+
+        match map.entry("c") {
+            Entry::Occupied(entry) => assert_eq!(entry.remove(), MsgPack::Int(3)),
+            Entry::Vacant(_) => panic!("c should be in the map"),
+        }
+
+        map.entry("d").and_modify(|_| panic!("d should not be in the map")).or_insert_with(|| 5);
+        map.entry("a").and_modify(|value| *value = MsgPack::Nil);
+        for (_, value) in map.iter_mut() {
+            if value.is_nil() { *value = MsgPack::Int(6) }
+        }
+
+        let keys: Vec<&MsgPack> = map.keys().collect();
+        assert_eq!(keys, vec![&MsgPack::from("a"), &MsgPack::from("d")]);
+        assert_eq!(map.get("d"), Some(&MsgPack::Int(5)));
+        assert_eq!(map.into_vec(), vec![
+            element(MsgPack::from("a"), MsgPack::Int(6)),
+            element(MsgPack::from("d"), MsgPack::Int(5)),
+        ]);
+    }
+
+    #[test]
+    fn integer_keys () {
+        let mut map = MsgPackMap::new();
+        map.insert(MsgPack::Int(1), "int");
+
+        assert_eq!(map.get(MsgPack::Uint(1)), Some(&MsgPack::from("int")));
+        assert!(map.contains_key(1u8));
+
+        assert_eq!(map.insert(MsgPack::Uint(1), "uint"), Some(MsgPack::from("int")));
+        assert_eq!(map.len(), 1);
+        assert_eq!(map.get(MsgPack::Int(1)), Some(&MsgPack::from("uint")));
+    }
+
+    #[test]
+    fn removal_keeps_index () {
+        let mut map = MsgPackMap::new();
+        map.push("a", 1);
+        map.push("b", 2);
+        map.push("c", 3);
+        map.push("b", 4);
+        map.push(5, 5);
+
+        assert_eq!(map.remove("a"), Some(MsgPack::Int(1)));
+        assert_eq!(map.get_all("b"), vec![&MsgPack::Int(2), &MsgPack::Int(4)]);
+        assert_eq!(map.remove("b"), Some(MsgPack::Int(2)));
+        assert_eq!(map.get("b"), Some(&MsgPack::Int(4)));
+        assert_eq!(map.get("c"), Some(&MsgPack::Int(3)));
+        assert_eq!(map.get(5), Some(&MsgPack::Int(5)));
+        assert_eq!(map.get("a"), None);
+
+        map.insert("a", 6);
+        assert_eq!(map.keys().collect::<Vec<_>>(), vec![&MsgPack::from("c"), &MsgPack::from("b"), &MsgPack::Int(5), &MsgPack::from("a")]);
+        assert_eq!(map.index.values().map(Vec::len).sum::<usize>(), map.len());
+    }
+
+    #[test]
+    fn round_trip () {
+        let elements = vec![
+            element(MsgPack::from("a"), MsgPack::Int(1)),
+            element(MsgPack::from("a"), MsgPack::Int(2)),
+        ];
+
+        let message = MsgPack::Map(elements.clone());
+        let map = MsgPackMap::try_from(MsgPack::parse(&message.encode()).unwrap()).unwrap();
+        assert_eq!(Vec::from(map.clone()), elements);
+        assert_eq!(MsgPack::from(map).encode(), message.encode());
+
+        assert!(MsgPackMap::try_from(MsgPack::Nil).is_err());
+    }
+}
@@ -0,0 +1,260 @@
+//! The MessagePack formats, shared by the parser, the encoder and the serde
+//! support
+
+use std::io::{self, Write};
+
+use byteorder::{BigEndian, WriteBytesExt};
+
+use super::error::ParseError;
+
+pub(crate) fn read_8 (raw: &[u8]) -> u64 {
+    raw[0] as u64
+}
+
+pub(crate) fn read_16 (raw: &[u8]) -> u64 {
+    raw[1] as u64 | (raw[0] as u64) << 8
+}
+
+pub(crate) fn read_32 (raw: &[u8]) -> u64 {
+    raw[3] as u64 | (raw[2] as u64) << 8 | (raw[1] as u64) << 16 | (raw[0] as u64) << 24
+}
+
+pub(crate) fn read_64 (raw: &[u8]) -> u64 {
+    raw[7] as u64 | (raw[6] as u64) << 8 | (raw[5] as u64) << 16 | (raw[4] as u64) << 24
+    | (raw[3] as u64) << 32 | (raw[2] as u64) << 40 | (raw[1] as u64) << 48 | (raw[0] as u64) << 56
+}
+
+/// What a marker introduces: a complete scalar, or the length of the payload
+/// or the number of items that follow the header
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Header {
+    Nil,
+    Boolean(bool),
+    Int(i64),
+    Uint(u64),
+    Float(f64),
+    String(usize),
+    Binary(usize),
+    Extension(i8, usize),
+    Array(usize),
+    Map(usize),
+}
+
+// the bytes after the marker, if there are at least `size` bytes in total
+fn after_marker (raw: &[u8], size: usize) -> Result<&[u8], ParseError> {
+    if raw.len() < size { return Err(ParseError { byte: 1 }) }
+    Ok(&raw[1..])
+}
+
+/// Reads the marker at the start of the data and the header that follows it,
+/// returning what it introduces and the size of the header
+/// 
+/// Fixed size extensions are checked whole, every other payload is left to
+/// the caller.
+pub(crate) fn read_header (raw: &[u8]) -> Result<(Header, usize), ParseError> {
+    if raw.is_empty() { return Err(ParseError { byte: 0 }) }
+    let marker = raw[0];
+
+    let header = match marker {
+        0x00..=0x7f => (Header::Int(marker as i64), 1), // positive fixint
+        0x80..=0x8f => (Header::Map((marker & 0x0f) as usize), 1), // fixmap
+        0x90..=0x9f => (Header::Array((marker & 0x0f) as usize), 1), // fixarray
+        0xa0..=0xbf => (Header::String((marker & 0x1f) as usize), 1), // fixstr
+        0xc0 => (Header::Nil, 1),
+        0xc1 => return Err(ParseError { byte: 0 }), // never used
+        0xc2 => (Header::Boolean(false), 1),
+        0xc3 => (Header::Boolean(true), 1),
+        0xc4 => (Header::Binary(read_8(after_marker(raw, 2)?) as usize), 2),
+        0xc5 => (Header::Binary(read_16(after_marker(raw, 3)?) as usize), 3),
+        0xc6 => (Header::Binary(read_32(after_marker(raw, 5)?) as usize), 5),
+        0xc7 => (Header::Extension(after_marker(raw, 3)?[1] as i8, read_8(&raw[1..]) as usize), 3),
+        0xc8 => (Header::Extension(after_marker(raw, 4)?[2] as i8, read_16(&raw[1..]) as usize), 4),
+        0xc9 => (Header::Extension(after_marker(raw, 6)?[4] as i8, read_32(&raw[1..]) as usize), 6),
+        0xca => (Header::Float(f32::from_bits(read_32(after_marker(raw, 5)?) as u32) as f64), 5),
+        0xcb => (Header::Float(f64::from_bits(read_64(after_marker(raw, 9)?))), 9),
+        0xcc => (Header::Uint(read_8(after_marker(raw, 2)?)), 2),
+        0xcd => (Header::Uint(read_16(after_marker(raw, 3)?)), 3),
+        0xce => (Header::Uint(read_32(after_marker(raw, 5)?)), 5),
+        0xcf => (Header::Uint(read_64(after_marker(raw, 9)?)), 9),
+        0xd0 => (Header::Int(read_8(after_marker(raw, 2)?) as i8 as i64), 2),
+        0xd1 => (Header::Int(read_16(after_marker(raw, 3)?) as i16 as i64), 3),
+        0xd2 => (Header::Int(read_32(after_marker(raw, 5)?) as i32 as i64), 5),
+        0xd3 => (Header::Int(read_64(after_marker(raw, 9)?) as i64), 9),
+        0xd4..=0xd8 => {
+            // fixext 1, 2, 4, 8 and 16
+            let length = 1 << (marker - 0xd4);
+            (Header::Extension(after_marker(raw, 2 + length)?[0] as i8, length), 2)
+        },
+        0xd9 => (Header::String(read_8(after_marker(raw, 2)?) as usize), 2),
+        0xda => (Header::String(read_16(after_marker(raw, 3)?) as usize), 3),
+        0xdb => (Header::String(read_32(after_marker(raw, 5)?) as usize), 5),
+        0xdc => (Header::Array(read_16(after_marker(raw, 3)?) as usize), 3),
+        0xdd => (Header::Array(read_32(after_marker(raw, 5)?) as usize), 5),
+        0xde => (Header::Map(read_16(after_marker(raw, 3)?) as usize), 3),
+        0xdf => (Header::Map(read_32(after_marker(raw, 5)?) as usize), 5),
+        0xe0..=0xff => (Header::Int(marker as i64 - 256), 1), // negative fixint
+    };
+
+    Ok(header)
+}
+
+pub(crate) fn write_int <W: Write> (out: &mut W, value: i64) -> io::Result<()> {
+    if (0..128).contains(&value) { return out.write_u8(value as u8) }
+    if (-31..0).contains(&value) { return out.write_i8(value as i8) }
+
+    if (-0x80..0x80).contains(&value) {
+        out.write_u8(0xd0)?;
+        out.write_i8(value as i8)
+    } else if (-0x8000..0x8000).contains(&value) {
+        out.write_u8(0xd1)?;
+        out.write_i16::<BigEndian>(value as i16)
+    } else if (-0x8000_0000..0x8000_0000).contains(&value) {
+        out.write_u8(0xd2)?;
+        out.write_i32::<BigEndian>(value as i32)
+    } else {
+        out.write_u8(0xd3)?;
+        out.write_i64::<BigEndian>(value)
+    }
+}
+
+pub(crate) fn write_uint <W: Write> (out: &mut W, value: u64) -> io::Result<()> {
+    // not writing Uint as fixint retains integer types in the decoded value
+    // if value < 128 { return vec![value as u8] }
+
+    if value <= 0x88 {
+        out.write_u8(0xcc)?;
+        out.write_u8(value as u8)
+    } else if value <= 0x8888 {
+        out.write_u8(0xcd)?;
+        out.write_u16::<BigEndian>(value as u16)
+    } else if value <= 0x8888_8888 {
+        out.write_u8(0xce)?;
+        out.write_u32::<BigEndian>(value as u32)
+    } else {
+        out.write_u8(0xcf)?;
+        out.write_u64::<BigEndian>(value)
+    }
+}
+
+pub(crate) fn write_float <W: Write> (out: &mut W, value: f64) -> io::Result<()> {
+    // since it's nontrivial when float32 is enough and when it's not, we're just going to always use float64
+    out.write_u8(0xcb)?;
+    out.write_u64::<BigEndian>(value.to_bits())
+}
+
+pub(crate) fn write_string_header <W: Write> (out: &mut W, length: usize) -> io::Result<()> {
+    if length < 32 {
+        out.write_u8(0xa0 | length as u8)
+    } else if length <= 0x88 {
+        out.write_u8(0xd9)?;
+        out.write_u8(length as u8)
+    } else if length <= 0x8888 {
+        out.write_u8(0xda)?;
+        out.write_u16::<BigEndian>(length as u16)
+    } else {
+        out.write_u8(0xdb)?;
+        out.write_u32::<BigEndian>(length as u32)
+    }
+}
+
+pub(crate) fn write_binary_header <W: Write> (out: &mut W, length: usize) -> io::Result<()> {
+    if length <= 0x88 {
+        out.write_u8(0xc4)?;
+        out.write_u8(length as u8)
+    } else if length <= 0x8888 {
+        out.write_u8(0xc5)?;
+        out.write_u16::<BigEndian>(length as u16)
+    } else {
+        out.write_u8(0xc6)?;
+        out.write_u32::<BigEndian>(length as u32)
+    }
+}
+
+pub(crate) fn write_extension_header <W: Write> (out: &mut W, length: usize, type_id: i8) -> io::Result<()> {
+    // encode length (wow there are a lot of options here)
+    match length {
+        1 => out.write_u8(0xd4)?,
+        2 => out.write_u8(0xd5)?,
+        4 => out.write_u8(0xd6)?,
+        8 => out.write_u8(0xd7)?,
+        16 => out.write_u8(0xd8)?,
+        _ if length <= 0x88 => {
+            out.write_u8(0xc7)?;
+            out.write_u8(length as u8)?;
+        },
+        _ if length <= 0x8888 => {
+            out.write_u8(0xc8)?;
+            out.write_u16::<BigEndian>(length as u16)?;
+        },
+        _ => {
+            out.write_u8(0xc9)?;
+            out.write_u32::<BigEndian>(length as u32)?;
+        }
+    }
+
+    out.write_i8(type_id)
+}
+
+pub(crate) fn write_array_header <W: Write> (out: &mut W, length: usize) -> io::Result<()> {
+    if length < 16 {
+        out.write_u8(0x90 | length as u8)
+    } else if length <= 0x8888 {
+        out.write_u8(0xdc)?;
+        out.write_u16::<BigEndian>(length as u16)
+    } else {
+        out.write_u8(0xdd)?;
+        out.write_u32::<BigEndian>(length as u32)
+    }
+}
+
+pub(crate) fn write_map_header <W: Write> (out: &mut W, length: usize) -> io::Result<()> {
+    if length < 16 {
+        out.write_u8(0x80 | length as u8)
+    } else if length <= 0x8888 {
+        out.write_u8(0xde)?;
+        out.write_u16::<BigEndian>(length as u16)
+    } else {
+        out.write_u8(0xdf)?;
+        out.write_u32::<BigEndian>(length as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headers () {
+        assert_eq!(read_header(&[0x05]).unwrap(), (Header::Int(5), 1));
+        assert_eq!(read_header(&[0xff]).unwrap(), (Header::Int(-1), 1));
+        assert_eq!(read_header(&[0xa3]).unwrap(), (Header::String(3), 1));
+        assert_eq!(read_header(&[0xc8, 0x01, 0x00, 0x07]).unwrap(), (Header::Extension(7, 256), 4));
+        assert_eq!(read_header(&[0xd5, 0xfe, 0x00, 0x00]).unwrap(), (Header::Extension(-2, 2), 2));
+        assert_eq!(read_header(&[0xca, 0x3f, 0xc0, 0x00, 0x00]).unwrap(), (Header::Float(1.5), 5));
+
+        assert_eq!(read_header(&[]).unwrap_err().byte, 0);
+        assert_eq!(read_header(&[0xc1]).unwrap_err().byte, 0);
+        assert_eq!(read_header(&[0xcd, 0x01]).unwrap_err().byte, 1);
+        assert_eq!(read_header(&[0xd5, 0x01, 0x00]).unwrap_err().byte, 1);
+    }
+
+    #[test]
+    fn round_trip () {
+        let mut data = vec![];
+        write_int(&mut data, -300).unwrap();
+        write_uint(&mut data, 70_000).unwrap();
+        write_extension_header(&mut data, 3, -5).unwrap();
+        write_map_header(&mut data, 20).unwrap();
+
+        let mut cursor = 0;
+        let mut headers = vec![];
+        for _ in 0..4 {
+            let (header, size) = read_header(&data[cursor..]).unwrap();
+            headers.push(header);
+            cursor += size;
+        }
+
+        assert_eq!(headers, vec![Header::Int(-300), Header::Uint(70_000), Header::Extension(-5, 3), Header::Map(20)]);
+        assert_eq!(cursor, data.len());
+    }
+}
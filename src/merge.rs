@@ -0,0 +1,206 @@
+//! Deep merging of MsgPack values
+
+use super::{MsgPack, MapElement};
+use super::index::MapKey;
+
+/// How [Merge](struct.Merge.html) combines two arrays
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ArrayMerge {
+    /// The array in the patch replaces the original one, like every other
+    /// value that is not a map
+    #[default]
+    Replace,
+    /// The elements of the patch are appended to the original array
+    Concat,
+    /// Elements at the same index are merged, and extra elements of the patch
+    /// are appended
+    ByIndex,
+}
+
+/// Settings for deep merging, see
+/// [MsgPack::merge](enum.MsgPack.html#method.merge)
+/// 
+/// By default, merging follows JSON Merge Patch (RFC 7386): maps are merged
+/// key by key, a Nil value in the patch deletes the key, and every other value
+/// replaces the original, arrays included. Keys are matched the way
+/// [get](enum.MsgPack.html#method.get) matches them, so an int and a uint key
+/// with the same value are the same key.
+/// 
+///     use msgpack_simple::{msgpack, ArrayMerge, Merge};
+/// 
+///     let mut config = msgpack!({ "plugins": ["a"], "ports": [80, 443] });
+/// 
+///     Merge::new().arrays(ArrayMerge::Concat).apply(&mut config, msgpack!({ "plugins": ["b"] }));
+///     Merge::new().arrays(ArrayMerge::ByIndex).apply(&mut config, msgpack!({ "ports": [8080] }));
+/// 
+///     assert_eq!(config, msgpack!({ "plugins": ["a", "b"], "ports": [8080, 443] }));
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Merge {
+    arrays: ArrayMerge,
+    nil_deletes: bool,
+}
+
+impl Default for Merge {
+    fn default () -> Merge {
+        Merge { arrays: ArrayMerge::Replace, nil_deletes: true }
+    }
+}
+
+impl Merge {
+    /// Creates the default settings, following RFC 7386
+    pub fn new () -> Merge {
+        Merge::default()
+    }
+
+    /// Sets how arrays are combined
+    pub fn arrays (mut self, arrays: ArrayMerge) -> Merge {
+        self.arrays = arrays;
+        self
+    }
+
+    /// Sets whether a Nil value in a patch map deletes the key. If disabled,
+    /// the key is set to Nil instead.
+    pub fn nil_deletes (mut self, enabled: bool) -> Merge {
+        self.nil_deletes = enabled;
+        self
+    }
+
+    /// Merges the patch into the target
+    pub fn apply (&self, target: &mut MsgPack, patch: MsgPack) {
+        match patch {
+            MsgPack::Map(patch) => {
+                if !target.is_map() { *target = MsgPack::Map(vec![]) }
+
+                if let MsgPack::Map(map) = target {
+                    for MapElement { key, value } in patch {
+                        if value.is_nil() && self.nil_deletes {
+                            map.retain(|element| !key.matches_key(&element.key));
+                            continue;
+                        }
+
+                        match map.iter_mut().find(|element| key.matches_key(&element.key)) {
+                            Some(element) => self.apply(&mut element.value, value),
+                            None => {
+                                let mut merged = MsgPack::Nil;
+                                self.apply(&mut merged, value);
+                                map.push(MapElement { key, value: merged });
+                            }
+                        }
+                    }
+                }
+            },
+            MsgPack::Array(patch) => match (self.arrays, target) {
+                (ArrayMerge::Concat, MsgPack::Array(array)) => array.extend(patch),
+                (ArrayMerge::ByIndex, MsgPack::Array(array)) => {
+                    for (index, value) in patch.into_iter().enumerate() {
+                        match array.get_mut(index) {
+                            Some(item) => self.apply(item, value),
+                            None => {
+                                let mut merged = MsgPack::Nil;
+                                self.apply(&mut merged, value);
+                                array.push(merged);
+                            }
+                        }
+                    }
+                },
+                (_, target) => *target = MsgPack::Array(patch)
+            },
+            patch => *target = patch
+        }
+    }
+}
+
+impl MsgPack {
+    /// Merges another value into this one, following JSON Merge Patch
+    /// (RFC 7386)
+    /// 
+    /// If the patch is a map, its keys are merged into this value recursively,
+    /// turning it into a map first if it wasn't one. Keys with a Nil value in
+    /// the patch are deleted. Any other patch, including arrays, replaces this
+    /// value. Use [Merge](struct.Merge.html) to change how arrays and Nils are
+    /// handled.
+    /// 
+    ///     use msgpack_simple::msgpack;
+    /// 
+    ///     let mut config = msgpack!({
+    ///         "server": { "host": "localhost", "port": 8080 },
+    ///         "debug": true
+    ///     });
+    /// 
+    ///     config.merge(msgpack!({
+    ///         "server": { "port": 80, "tls": { "cert": "a.pem", "key": nil } },
+    ///         "debug": nil
+    ///     }));
+    /// 
+    ///     assert_eq!(config, msgpack!({
+    ///         "server": { "host": "localhost", "port": 80, "tls": { "cert": "a.pem" } }
+    ///     }));
+    pub fn merge (&mut self, other: MsgPack) {
+        Merge::new().apply(self, other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map (entries: Vec<(&str, MsgPack)>) -> MsgPack {
+        MsgPack::Map(entries.into_iter().map(|(key, value)| MapElement { key: MsgPack::from(key), value }).collect())
+    }
+
+    #[test]
+    fn merge_patch () {
+        // examples from the appendix of RFC 7386
+        let cases = vec![
+            (map(vec![("a", MsgPack::from("b"))]), map(vec![("a", MsgPack::from("c"))]), map(vec![("a", MsgPack::from("c"))])),
+            (map(vec![("a", MsgPack::from("b"))]), map(vec![("b", MsgPack::from("c"))]), map(vec![("a", MsgPack::from("b")), ("b", MsgPack::from("c"))])),
+            (map(vec![("a", MsgPack::from("b"))]), map(vec![("a", MsgPack::Nil)]), map(vec![])),
+            (map(vec![("a", MsgPack::from(vec!["b"]))]), map(vec![("a", MsgPack::from("c"))]), map(vec![("a", MsgPack::from("c"))])),
+            (map(vec![("a", MsgPack::from("c"))]), map(vec![("a", MsgPack::from(vec!["b"]))]), map(vec![("a", MsgPack::from(vec!["b"]))])),
+            (MsgPack::from(vec!["a", "b"]), MsgPack::from(vec!["c", "d"]), MsgPack::from(vec!["c", "d"])),
+            (map(vec![("a", MsgPack::from("b"))]), MsgPack::from(vec!["c"]), MsgPack::from(vec!["c"])),
+            (map(vec![("a", MsgPack::from("foo"))]), MsgPack::Nil, MsgPack::Nil),
+            (map(vec![("e", MsgPack::Nil)]), map(vec![("a", MsgPack::Int(1))]), map(vec![("e", MsgPack::Nil), ("a", MsgPack::Int(1))])),
+            (MsgPack::from(vec![1, 2]), map(vec![("a", MsgPack::from("b")), ("c", MsgPack::Nil)]), map(vec![("a", MsgPack::from("b"))])),
+            (map(vec![]), map(vec![("a", map(vec![("bb", map(vec![("ccc", MsgPack::Nil)]))]))]), map(vec![("a", map(vec![("bb", map(vec![]))]))])),
+        ];
+
+        for (mut target, patch, expected) in cases {
+            target.merge(patch);
+            assert_eq!(target, expected);
+        }
+    }
+
+    #[test]
+    fn options () {
+        let mut target = map(vec![("a", MsgPack::Int(1)), ("list", MsgPack::Array(vec![map(vec![("x", MsgPack::Int(1))]), MsgPack::Int(2)]))]);
+        let patch = map(vec![("a", MsgPack::Nil), ("list", MsgPack::Array(vec![map(vec![("y", MsgPack::Int(2))]), MsgPack::Int(3), MsgPack::Int(4)]))]);
+
+        Merge::new().arrays(ArrayMerge::ByIndex).nil_deletes(false).apply(&mut target, patch);
+
+        assert_eq!(target, map(vec![
+            ("a", MsgPack::Nil),
+            ("list", MsgPack::Array(vec![map(vec![("x", MsgPack::Int(1)), ("y", MsgPack::Int(2))]), MsgPack::Int(3), MsgPack::Int(4)])),
+        ]));
+
+        let mut target = MsgPack::from(vec![1]);
+        Merge::new().arrays(ArrayMerge::Concat).apply(&mut target, MsgPack::from(vec![2]));
+        assert_eq!(target, MsgPack::from(vec![1, 2]));
+
+        // appended elements are merged onto nil, like new keys
+        let mut target = MsgPack::from(vec![1]);
+        let patch = MsgPack::Array(vec![MsgPack::Int(1), map(vec![("x", MsgPack::Nil), ("y", MsgPack::Int(2))])]);
+        Merge::new().arrays(ArrayMerge::ByIndex).apply(&mut target, patch);
+        assert_eq!(target, MsgPack::Array(vec![MsgPack::Int(1), map(vec![("y", MsgPack::Int(2))])]));
+    }
+
+    #[test]
+    fn integer_keys () {
+        let entry = |key, value| MapElement { key, value };
+
+        let mut target = MsgPack::Map(vec![entry(MsgPack::Int(1), MsgPack::from("a")), entry(MsgPack::Int(2), MsgPack::from("c"))]);
+        target.merge(MsgPack::Map(vec![entry(MsgPack::Uint(1), MsgPack::from("b")), entry(MsgPack::Uint(2), MsgPack::Nil)]));
+
+        assert_eq!(target, MsgPack::Map(vec![entry(MsgPack::Int(1), MsgPack::from("b"))]));
+    }
+}
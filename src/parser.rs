@@ -1,24 +1,8 @@
 //! The actual parser under the hood
 
 use super::{MsgPack, MapElement, Extension};
-use super::error::ParseError;
-
-fn read_8 (raw: &[u8]) -> u64 {
-    raw[0] as u64
-}
-
-fn read_16 (raw: &[u8]) -> u64 {
-    raw[1] as u64 | (raw[0] as u64) << 8
-}
-
-fn read_32 (raw: &[u8]) -> u64 {
-    raw[3] as u64 | (raw[2] as u64) << 8 | (raw[1] as u64) << 16 | (raw[0] as u64) << 24
-}
-
-fn read_64 (raw: &[u8]) -> u64 {
-    raw[7] as u64 | (raw[6] as u64) << 8 | (raw[5] as u64) << 16 | (raw[4] as u64) << 24
-    | (raw[3] as u64) << 32 | (raw[2] as u64) << 40 | (raw[1] as u64) << 48 | (raw[0] as u64) << 56
-}
+use super::error::{ParseError, PartialError};
+use super::marker::{Header, read_header, read_8, read_16, read_32};
 
 /// Parses binary data as MsgPack, returning both the result and the length of
 /// the data. Useful when you have other data directly following in the slice.
@@ -32,334 +16,387 @@ fn read_64 (raw: &[u8]) -> u64 {
 ///     assert_eq!(decoded.as_string().unwrap(), "Hello Rust".to_string());
 ///     assert_eq!(length, 11);
 pub fn parse (raw: &[u8]) -> Result<(MsgPack, usize), ParseError> {
-    if raw.len() < 1 { return Err(ParseError { byte: 0 }) }
-    let first_byte = raw[0];
-
-    if first_byte <= 0x7f { // positive fixint
-        return Ok((MsgPack::Int(first_byte as i64), 1))
-    }
-    if first_byte >= 0xe0 { // negative fixint
-        return Ok((MsgPack::Int(first_byte as i64 - 256), 1))
-    }
-
-    if first_byte >= 0x80 && first_byte <= 0x8f { // fixmap
-        let len = (first_byte & 0x0f) as usize;
-        let (value, size) = ParseError::offset_result(parse_map(&raw[1..], len), 1)?;
-
-        return Ok((MsgPack::Map(value), 1 + size));
-    }
-
-    if first_byte >= 0x90 && first_byte <= 0x9f { // fixarray
-        let len = (first_byte & 0x0f) as usize;
-        let (value, size) = ParseError::offset_result(parse_array(&raw[1..], len), 1)?;
-
-        return Ok((MsgPack::Array(value), 1 + size));
-    }
-
-    if first_byte >= 0xa0 && first_byte <= 0xbf { // fixstr
-        let len = (first_byte & 0x1f) as usize;
-        if raw.len() < 1 + len { return Err(ParseError { byte: 1 }) }
-
-        let value = String::from_utf8(raw[1..1 + len].to_vec()).map_err(|_| ParseError { byte: 1 })?;
-        return Ok((MsgPack::String(value), 1 + len));
-    }
-
-    if first_byte == 0xc0 { return Ok((MsgPack::Nil, 1)) } // nil
-    if first_byte == 0xc1 { return Err(ParseError { byte: 0 }) } // never used
-    if first_byte == 0xc2 { return Ok((MsgPack::Boolean(false), 1)) } // false
-    if first_byte == 0xc3 { return Ok((MsgPack::Boolean(true), 1)) } // true
-
-    if first_byte == 0xc4 { // bin 8
-        if raw.len() < 2 { return Err(ParseError { byte: 1 }) }
-        let len = read_8(&raw[1..]) as usize;
-
-        if raw.len() < 2 + len { return Err(ParseError {byte: 2}) }
-        let value = raw[2..2 + len].to_vec();
-
-        return Ok((MsgPack::Binary(value), 2 + len))
-    }
-
-    if first_byte == 0xc5 { // bin 16
-        if raw.len() < 3 { return Err(ParseError { byte: 1 }) }
-        let len = read_16(&raw[1..]) as usize;
-
-        if raw.len() < 3 + len { return Err(ParseError {byte: 3}) }
-        let value = raw[3..3 + len].to_vec();
-
-        return Ok((MsgPack::Binary(value), 3 + len))
-    }
-
-    if first_byte == 0xc6 { // bin 32
-        if raw.len() < 5 { return Err(ParseError { byte: 1 }) }
-        let len = read_32(&raw[1..]) as usize;
-
-        if raw.len() < 5 + len { return Err(ParseError { byte: 5 }) }
-        let value = raw[5..5 + len].to_vec();
-
-        return Ok((MsgPack::Binary(value), 5 + len))
-    }
-
-    if first_byte == 0xc7 { // ext 8
-        if raw.len() < 3 { return Err(ParseError { byte: 1 }) }
-        let len = read_8(&raw[1..]) as usize;
-        let type_id = unsafe { std::mem::transmute::<u8, i8>(raw[2]) };
-
-        if raw.len() < 3 + len { return Err(ParseError { byte: 3 }) }
-        let value = raw[3..3 + len].to_vec();
-
-        return Ok((MsgPack::Extension(Extension { type_id, value }), 3 + len))
-    }
-
-    if first_byte == 0xc8 { // ext 16
-        if raw.len() < 4 { return Err(ParseError { byte: 1 }) }
-        let len = read_16(&raw[1..]) as usize;
-        let type_id = unsafe { std::mem::transmute::<u8, i8>(raw[3]) };
-
-        if raw.len() < 4 + len { return Err(ParseError { byte: 4 }) }
-        let value = raw[4..4 + len].to_vec();
-
-        return Ok((MsgPack::Extension(Extension { type_id, value }), 4 + len))
-    }
-
-    if first_byte == 0xc9 { // ext 32
-        if raw.len() < 6 { return Err(ParseError { byte: 1 }) }
-        let len = read_32(&raw[1..]) as usize;
-        let type_id = unsafe { std::mem::transmute::<u8, i8>(raw[5]) };
-
-        if raw.len() < 6 + len { return Err(ParseError { byte: 6 }) }
-        let value = raw[6..6 + len].to_vec();
-
-        return Ok((MsgPack::Extension(Extension { type_id, value }), 6 + len))
-    }
-
-    if first_byte == 0xca { // float 32
-        if raw.len() < 5 { return Err(ParseError { byte: 1 }) }
-
-        let raw_value = read_32(&raw[1..]) as u32;
-        let value = unsafe { std::mem::transmute::<u32, f32>(raw_value) };
-
-        return Ok((MsgPack::Float(value as f64), 5));
-    }
-
-    if first_byte == 0xcb { // float 64
-        if raw.len() < 9 { return Err(ParseError { byte: 1 }) }
-
-        let raw_value = read_64(&raw[1..]);
-        let value = unsafe { std::mem::transmute::<u64, f64>(raw_value) };
-
-        return Ok((MsgPack::Float(value), 9));
-    }
-
-    if first_byte == 0xcc { // uint 8
-        if raw.len() < 2 { return Err(ParseError { byte: 1 }) }
-
-        let value = read_8(&raw[1..]);
-        return Ok((MsgPack::Uint(value), 2));
-    }
-
-    if first_byte == 0xcd { // uint 16
-        if raw.len() < 3 { return Err(ParseError { byte: 1 }) }
-
-        let value = read_16(&raw[1..]);
-        return Ok((MsgPack::Uint(value), 3));
-    }
-
-    if first_byte == 0xce { // uint 32
-        if raw.len() < 5 { return Err(ParseError { byte: 1 }) }
-
-        let value = read_32(&raw[1..]);
-        return Ok((MsgPack::Uint(value), 5));
-    }
-    
-    if first_byte == 0xcf { // uint 64
-        if raw.len() < 9 { return Err(ParseError { byte: 1 }) }
-
-        let value = read_64(&raw[1..]);
-        return Ok((MsgPack::Uint(value), 9));
-    }
-
-    if first_byte == 0xd0 { // int 8
-        if raw.len() < 2 { return Err(ParseError { byte: 1 }) }
-
-        let raw_value = read_8(&raw[1..]);
-        let value = unsafe { std::mem::transmute::<u64, i64>(raw_value) };
-
-        return Ok((MsgPack::Int(value), 2));
-    }
-
-    if first_byte == 0xd1 { // int 16
-        if raw.len() < 3 { return Err(ParseError { byte: 1 }) }
-
-        let raw_value = read_16(&raw[1..]);
-        let value = unsafe { std::mem::transmute::<u64, i64>(raw_value) };
-
-        return Ok((MsgPack::Int(value), 3));
-    }
-
-    if first_byte == 0xd2 { // int 32
-        if raw.len() < 5 { return Err(ParseError { byte: 1 }) }
-
-        let raw_value = read_32(&raw[1..]);
-        let value = unsafe { std::mem::transmute::<u64, i64>(raw_value) };
-
-        return Ok((MsgPack::Int(value), 5));
-    }
-    
-    if first_byte == 0xd3 { // int 64
-        if raw.len() < 9 { return Err(ParseError { byte: 1 }) }
-
-        let raw_value = read_64(&raw[1..]);
-        let value = unsafe { std::mem::transmute::<u64, i64>(raw_value) };
-
-        return Ok((MsgPack::Int(value), 9));
-    }
-
-    if first_byte == 0xd4 { // fixext 1
-        if raw.len() < 3 { return Err(ParseError { byte: 1 }) }
-
-        let type_id = unsafe { std::mem::transmute::<u8, i8>(raw[1]) };
-        let value = raw[2..3].to_vec();
-
-        return Ok((MsgPack::Extension(Extension { type_id, value }), 3));
-    }
-
-    if first_byte == 0xd5 { // fixext 2
-        if raw.len() < 4 { return Err(ParseError { byte: 1 }) }
-
-        let type_id = unsafe { std::mem::transmute::<u8, i8>(raw[1]) };
-        let value = raw[2..4].to_vec();
-
-        return Ok((MsgPack::Extension(Extension { type_id, value }), 4));
-    }
-
-    if first_byte == 0xd6 { // fixext 4
-        if raw.len() < 6 { return Err(ParseError { byte: 1 }) }
-
-        let type_id = unsafe { std::mem::transmute::<u8, i8>(raw[1]) };
-        let value = raw[2..6].to_vec();
+    parse_with(raw, &mut |_| true)
+}
 
-        return Ok((MsgPack::Extension(Extension { type_id, value }), 6));
+// parses like parse, but hands every extension to the check first, failing at
+// the marker of the extension if it's rejected
+pub(crate) fn parse_with (raw: &[u8], check: &mut dyn FnMut(&Extension) -> bool) -> Result<(MsgPack, usize), ParseError> {
+    let (header, size) = read_header(raw)?;
+
+    // the payload after the header, pointing at its start if it's cut short
+    let payload = |length: usize| {
+        if raw.len() < size + length { return Err(ParseError { byte: size }) }
+        Ok(&raw[size..size + length])
+    };
+
+    match header {
+        Header::Nil => Ok((MsgPack::Nil, size)),
+        Header::Boolean(value) => Ok((MsgPack::Boolean(value), size)),
+        Header::Int(value) => Ok((MsgPack::Int(value), size)),
+        Header::Uint(value) => Ok((MsgPack::Uint(value), size)),
+        Header::Float(value) => Ok((MsgPack::Float(value), size)),
+        Header::String(length) => {
+            let value = String::from_utf8(payload(length)?.to_vec()).map_err(|_| ParseError { byte: size })?;
+            Ok((MsgPack::String(value), size + length))
+        },
+        Header::Binary(length) => Ok((MsgPack::Binary(payload(length)?.to_vec()), size + length)),
+        Header::Extension(type_id, length) => {
+            let value = payload(length)?.to_vec();
+            checked(Extension { type_id, value }, size + length, check)
+        },
+        Header::Array(length) => {
+            let (value, items) = ParseError::offset_result(parse_array(&raw[size..], length, check), size)?;
+            Ok((MsgPack::Array(value), size + items))
+        },
+        Header::Map(length) => {
+            let (value, items) = ParseError::offset_result(parse_map(&raw[size..], length, check), size)?;
+            Ok((MsgPack::Map(value), size + items))
+        }
     }
+}
 
-    if first_byte == 0xd7 { // fixext 8
-        if raw.len() < 10 { return Err(ParseError { byte: 1 }) }
+fn checked (extension: Extension, size: usize, check: &mut dyn FnMut(&Extension) -> bool) -> Result<(MsgPack, usize), ParseError> {
+    if !check(&extension) { return Err(ParseError { byte: 0 }) }
+    Ok((MsgPack::Extension(extension), size))
+}
 
-        let type_id = unsafe { std::mem::transmute::<u8, i8>(raw[1]) };
-        let value = raw[2..10].to_vec();
+fn parse_array (raw: &[u8], length: usize, check: &mut dyn FnMut(&Extension) -> bool) -> Result<(Vec<MsgPack>, usize), ParseError> {
+    let mut cursor = 0usize;
+    let mut result = Vec::with_capacity(length);
 
-        return Ok((MsgPack::Extension(Extension { type_id, value }), 10));
+    for _ in 0..length {
+        let (value, size) = ParseError::offset_result(parse_with(&raw[cursor..], check), cursor)?;
+        result.push(value);
+        cursor += size;
     }
 
-    if first_byte == 0xd8 { // fixext 16
-        if raw.len() < 18 { return Err(ParseError { byte: 1 }) }
-
-        let type_id = unsafe { std::mem::transmute::<u8, i8>(raw[1]) };
-        let value = raw[2..18].to_vec();
+    Ok((result, cursor))
+}
 
-        return Ok((MsgPack::Extension(Extension { type_id, value }), 18));
-    }
+fn parse_map (raw: &[u8], length: usize, check: &mut dyn FnMut(&Extension) -> bool) -> Result<(Vec<MapElement>, usize), ParseError> {
+    let (elements, size) = parse_array(raw, length * 2, check)?;
+    let mut result = Vec::with_capacity(length);
 
-    if first_byte == 0xd9 { // str 8
-        if raw.len() < 2 { return Err(ParseError { byte: 1 }) }
+    if elements.len() != length * 2 { unreachable!() }
+    let mut iter = elements.into_iter();
 
-        let len = read_8(&raw[1..]) as usize;
-        if raw.len() < 2 + len { return Err(ParseError { byte: 2 }) }
+    for _ in 0..length {
+        let key = iter.next().unwrap();
+        let value = iter.next().unwrap();
 
-        let value = String::from_utf8(raw[2..2 + len].to_vec()).map_err(|_| ParseError { byte: 2 })?;
-        return Ok((MsgPack::String(value), 2 + len));
+        let element = MapElement { key, value };
+        result.push(element);
     }
 
-    if first_byte == 0xda { // str 16
-        if raw.len() < 3 { return Err(ParseError { byte: 1 }) }
-
-        let len = read_16(&raw[1..]) as usize;
-        if raw.len() < 3 + len { return Err(ParseError { byte: 3 }) }
-
-        let value = String::from_utf8(raw[3..3 + len].to_vec()).map_err(|_| ParseError { byte: 3 })?;
-        return Ok((MsgPack::String(value), 3 + len));
-    }
+    Ok((result, size))
+}
 
-    if first_byte == 0xdb { // str 32
-        if raw.len() < 5 { return Err(ParseError { byte: 1 }) }
+/// Parses as much of the data as possible, for salvaging damaged or truncated
+/// messages. Returns the recovered value, the length of the data consumed, and
+/// the problems found along the way.
+/// 
+/// Arrays and maps that run out of data are cut short, and values that can't
+/// be read are replaced with a Nil placeholder. Map keys are not replaced,
+/// since the value after a broken key can't be found, so a map ends at the
+/// first key that can't be read in full. Each problem holds the path of
+/// the affected node in the recovered tree, see
+/// [PartialError](../struct.PartialError.html).
+/// 
+///     use msgpack_simple::{MsgPack, parser};
+/// 
+///     // an array of three strings, cut off in the middle of the second one
+///     let data = vec![0x93, 0xa3, 0x66, 0x6f, 0x6f, 0xa3, 0x62];
+///     let (decoded, length, errors) = parser::parse_partial(&data);
+/// 
+///     assert_eq!(decoded, MsgPack::Array(vec![MsgPack::String("foo".to_string()), MsgPack::Nil]));
+///     assert_eq!(length, 7);
+///     assert_eq!(errors.len(), 1);
+///     assert_eq!(errors[0].path, vec![1]);
+///     assert!(errors[0].placeholder);
+pub fn parse_partial (raw: &[u8]) -> (MsgPack, usize, Vec<PartialError>) {
+    let mut errors = vec![];
+    let (value, end, _) = parse_partial_at(raw, 0, &mut vec![], &mut errors);
+
+    (value, end, errors)
+}
 
-        let len = read_32(&raw[1..]) as usize;
-        if raw.len() < 5 + len { return Err(ParseError { byte: 5 }) }
+// returns the value, the position after it, and whether the data ran out
+fn parse_partial_at (raw: &[u8], position: usize, path: &mut Vec<usize>, errors: &mut Vec<PartialError>) -> (MsgPack, usize, bool) {
+    if position >= raw.len() {
+        errors.push(PartialError { error: ParseError { byte: position }, path: path.clone(), placeholder: true, key: false });
+        return (MsgPack::Nil, position, true)
+    }
+
+    let data = &raw[position..];
+    let (length, header, is_map) = match collection_header(data) {
+        Some(Ok(header)) => header,
+        Some(Err(error)) => {
+            errors.push(PartialError { error: error.offset(position), path: path.clone(), placeholder: true, key: false });
+            return (MsgPack::Nil, raw.len(), true)
+        },
+        None => match parse(data) {
+            Ok((value, size)) => return (value, position + size, false),
+            Err(error) => {
+                let error = error.offset(position);
+                let skip = skippable_size(data);
+                errors.push(PartialError { error, path: path.clone(), placeholder: true, key: false });
+
+                return match skip {
+                    Some(size) => (MsgPack::Nil, position + size, false),
+                    None => (MsgPack::Nil, raw.len(), true),
+                }
+            }
+        }
+    };
+
+    let mut cursor = position + header;
+    let mut items = Vec::with_capacity(std::cmp::min(length, data.len()));
+    let mut entries = Vec::with_capacity(if is_map { std::cmp::min(length, data.len()) } else { 0 });
+    let mut ended = false;
+
+    for index in 0..length {
+        if cursor >= raw.len() {
+            let error = ParseError { byte: cursor };
+            errors.push(PartialError { error, path: path.clone(), placeholder: false, key: false });
+            ended = true;
+            break
+        }
+
+        path.push(index);
+
+        if !is_map {
+            let (value, end, ran_out) = parse_partial_at(raw, cursor, path, errors);
+            cursor = end;
+            items.push(value);
+            ended = ran_out;
+        } else {
+            // keys are read strictly, without one there's no telling where its
+            // value starts, so the map ends there
+            let key = match parse(&raw[cursor..]) {
+                Ok((key, size)) => {
+                    cursor += size;
+                    key
+                },
+                Err(error) => {
+                    errors.push(PartialError { error: error.offset(cursor), path: path.clone(), placeholder: false, key: true });
+                    path.pop();
+                    cursor = raw.len();
+                    ended = true;
+                    break
+                }
+            };
+
+            if cursor >= raw.len() {
+                errors.push(PartialError { error: ParseError { byte: cursor }, path: path.clone(), placeholder: true, key: false });
+                entries.push(MapElement { key, value: MsgPack::Nil });
+                ended = true;
+            } else {
+                let (value, end, ran_out) = parse_partial_at(raw, cursor, path, errors);
+                cursor = end;
+                entries.push(MapElement { key, value });
+                ended = ran_out;
+            }
+        }
+
+        path.pop();
+        if ended { break }
+    }
+
+    let value = if is_map { MsgPack::Map(entries) } else { MsgPack::Array(items) };
+    (value, cursor, ended)
+}
 
-        let value = String::from_utf8(raw[5..5 + len].to_vec()).map_err(|_| ParseError { byte: 5 })?;
-        return Ok((MsgPack::String(value), 5 + len));
+// length, header size, and whether it's a map, if the data starts with an
+// array or map marker
+fn collection_header (raw: &[u8]) -> Option<Result<(usize, usize, bool), ParseError>> {
+    match raw[0] {
+        0x80..=0x9f | 0xdc..=0xdf => {},
+        _ => return None,
     }
 
-    if first_byte == 0xdc { // array 16
-        if raw.len() < 3 { return Err(ParseError { byte: 1 }) }
-        
-        let len = read_16(&raw[1..]) as usize;
-        let (value, size) = ParseError::offset_result(parse_array(&raw[3..], len), 3)?;
-
-        return Ok((MsgPack::Array(value), 3 + size));
-    }
+    Some(read_header(raw).map(|(header, size)| match header {
+        Header::Map(length) => (length, size, true),
+        Header::Array(length) => (length, size, false),
+        _ => unreachable!()
+    }))
+}
 
-    if first_byte == 0xdd { // array 32
-        if raw.len() < 5 { return Err(ParseError { byte: 1 }) }
-        
-        let len = read_32(&raw[1..]) as usize;
-        let (value, size) = ParseError::offset_result(parse_array(&raw[5..], len), 5)?;
+// size of a value that failed to parse but can be skipped over, which is the
+// case for invalid UTF-8 strings and the unused marker
+fn skippable_size (raw: &[u8]) -> Option<usize> {
+    let (length_bytes, length) = match raw[0] {
+        0xc1 => return Some(1),
+        0xa0..=0xbf => (0, (raw[0] & 0x1f) as usize),
+        0xd9 if raw.len() >= 2 => (1, read_8(&raw[1..]) as usize),
+        0xda if raw.len() >= 3 => (2, read_16(&raw[1..]) as usize),
+        0xdb if raw.len() >= 5 => (4, read_32(&raw[1..]) as usize),
+        _ => return None,
+    };
+
+    let size = 1 + length_bytes + length;
+    if raw.len() >= size { Some(size) } else { None }
+}
 
-        return Ok((MsgPack::Array(value), 5 + size));
+/// The name of the MessagePack format a marker byte introduces, as used in
+/// error descriptions
+pub(crate) fn marker_name (marker: u8) -> &'static str {
+    match marker {
+        0x00..=0x7f => "positive fixint",
+        0x80..=0x8f => "fixmap",
+        0x90..=0x9f => "fixarray",
+        0xa0..=0xbf => "fixstr",
+        0xc0 => "nil",
+        0xc1 => "unused marker 0xc1",
+        0xc2 => "false",
+        0xc3 => "true",
+        0xc4 => "bin8",
+        0xc5 => "bin16",
+        0xc6 => "bin32",
+        0xc7 => "ext8",
+        0xc8 => "ext16",
+        0xc9 => "ext32",
+        0xca => "float32",
+        0xcb => "float64",
+        0xcc => "uint8",
+        0xcd => "uint16",
+        0xce => "uint32",
+        0xcf => "uint64",
+        0xd0 => "int8",
+        0xd1 => "int16",
+        0xd2 => "int32",
+        0xd3 => "int64",
+        0xd4 => "fixext1",
+        0xd5 => "fixext2",
+        0xd6 => "fixext4",
+        0xd7 => "fixext8",
+        0xd8 => "fixext16",
+        0xd9 => "str8",
+        0xda => "str16",
+        0xdb => "str32",
+        0xdc => "array16",
+        0xdd => "array32",
+        0xde => "map16",
+        0xdf => "map32",
+        0xe0..=0xff => "negative fixint",
     }
+}
 
-    if first_byte == 0xde { // map 16
-        if raw.len() < 3 { return Err(ParseError { byte: 1 }) }
-
-        let len = read_16(&raw[1..]) as usize;
-        let (value, size) = ParseError::offset_result(parse_map(&raw[3..], len), 3)?;
-
-        return Ok((MsgPack::Map(value), 3 + size));
-    }
+/// Explanation of a parse failure, see `explain`
+pub(crate) struct Explanation {
+    /// Position of the marker that was being decoded
+    pub marker: usize,
+    /// Human readable description of the problem
+    pub message: String,
+}
 
-    if first_byte == 0xdf { // map 32
-        if raw.len() < 5 { return Err(ParseError { byte: 1 }) }
+/// Walks the data the same way `parse` does and describes the first problem
+/// found, naming the marker that was being decoded. Returns `None` if the data
+/// is valid.
+pub(crate) fn explain (raw: &[u8]) -> Option<Explanation> {
+    explain_at(raw, 0).err()
+}
 
-        let len = read_32(&raw[1..]) as usize;
-        let (value, size) = ParseError::offset_result(parse_map(&raw[5..], len), 5)?;
+fn explain_at (raw: &[u8], position: usize) -> Result<usize, Explanation> {
+    if position >= raw.len() {
+        return Err(Explanation { marker: position, message: "unexpected end of data".to_string() })
+    }
+
+    let marker = raw[position];
+    let name = marker_name(marker);
+    let available = raw.len() - position - 1;
+    let fail = |message: String| Err(Explanation { marker: position, message });
+
+    // (bytes of the length field, extra header bytes, fixed payload size)
+    let (length_bytes, extra, fixed) = match marker {
+        0x00..=0x7f | 0xc0 | 0xc2 | 0xc3 | 0xe0..=0xff => return Ok(position + 1),
+        0xc1 => return fail(format!("{} is never used", name)),
+        0x80..=0x8f => return explain_items(raw, position, name, (marker & 0x0f) as usize, true, 1),
+        0x90..=0x9f => return explain_items(raw, position, name, (marker & 0x0f) as usize, false, 1),
+        0xa0..=0xbf => (0, 0, Some((marker & 0x1f) as usize)),
+        0xc4 | 0xd9 => (1, 0, None),
+        0xc5 | 0xda => (2, 0, None),
+        0xc6 | 0xdb => (4, 0, None),
+        0xc7 => (1, 1, None),
+        0xc8 => (2, 1, None),
+        0xc9 => (4, 1, None),
+        0xca | 0xce | 0xd2 => (0, 0, Some(4)),
+        0xcb | 0xcf | 0xd3 => (0, 0, Some(8)),
+        0xcc | 0xd0 => (0, 0, Some(1)),
+        0xcd | 0xd1 => (0, 0, Some(2)),
+        0xd4 => (0, 1, Some(1)),
+        0xd5 => (0, 1, Some(2)),
+        0xd6 => (0, 1, Some(4)),
+        0xd7 => (0, 1, Some(8)),
+        0xd8 => (0, 1, Some(16)),
+        0xdc..=0xdf => {
+            let length_bytes = if marker == 0xdc || marker == 0xde { 2 } else { 4 };
+            if available < length_bytes {
+                return fail(format!("{} needs {} length bytes, {} available", name, length_bytes, available))
+            }
+
+            let length = read_length(&raw[position + 1..], length_bytes);
+            return explain_items(raw, position, name, length, marker >= 0xde, 1 + length_bytes)
+        }
+    };
+
+    let header = length_bytes + extra;
+    if available < header {
+        return fail(format!("{} needs {} header bytes, {} available", name, header, available))
+    }
+
+    let payload = match fixed {
+        Some(size) => size,
+        None => read_length(&raw[position + 1..], length_bytes),
+    };
+
+    if available - header < payload {
+        return match fixed {
+            Some(_) if header == 0 => fail(format!("{} needs {} bytes, {} available", name, payload, available)),
+            _ => fail(format!("{} declared {} bytes, {} available", name, payload, available - header)),
+        }
+    }
+
+    let start = position + 1 + header;
+    let is_string = (0xa0..=0xbf).contains(&marker) || (0xd9..=0xdb).contains(&marker);
+    if is_string && std::str::from_utf8(&raw[start..start + payload]).is_err() {
+        return fail(format!("{} is not valid UTF-8", name))
+    }
+
+    Ok(start + payload)
+}
 
-        return Ok((MsgPack::Map(value), 5 + size));
-    }
+fn explain_items (raw: &[u8], position: usize, name: &str, length: usize, is_map: bool, header: usize) -> Result<usize, Explanation> {
+    let items = if is_map { length * 2 } else { length };
+    let mut cursor = position + header;
 
-    Err(ParseError { byte: 0 })
-}
+    for index in 0..items {
+        if cursor >= raw.len() {
+            let message = if is_map {
+                format!("{} declared {} entries, data ends after {}", name, length, index / 2)
+            } else {
+                format!("{} declared {} elements, data ends after {}", name, length, index)
+            };
 
-fn parse_array (raw: &[u8], length: usize) -> Result<(Vec<MsgPack>, usize), ParseError> {
-    let mut cursor = 0usize;
-    let mut result = Vec::with_capacity(length);
+            return Err(Explanation { marker: position, message })
+        }
 
-    for _ in 0..length {
-        let (value, size) = ParseError::offset_result(parse(&raw[cursor..]), cursor)?;
-        result.push(value);
-        cursor += size;
+        cursor = explain_at(raw, cursor)?;
     }
 
-    Ok((result, cursor))
+    Ok(cursor)
 }
 
-fn parse_map (raw: &[u8], length: usize) -> Result<(Vec<MapElement>, usize), ParseError> {
-    let (elements, size) = parse_array(&raw, length * 2)?;
-    let mut result = Vec::with_capacity(length);
-
-    if elements.len() != length * 2 { unreachable!() }
-    let mut iter = elements.into_iter();
-
-    for _ in 0..length {
-        let key = iter.next().unwrap();
-        let value = iter.next().unwrap();
-
-        let element = MapElement { key, value };
-        result.push(element);
+fn read_length (raw: &[u8], bytes: usize) -> usize {
+    match bytes {
+        1 => read_8(raw) as usize,
+        2 => read_16(raw) as usize,
+        _ => read_32(raw) as usize,
     }
-
-    Ok((result, size))
 }
 
 #[cfg(test)]
+#[allow(clippy::useless_vec, clippy::bool_assert_comparison)]
 mod tests {
     use super::*;
 
@@ -395,6 +432,75 @@ mod tests {
         assert_eq!(parsed.as_float().unwrap(), 1.42);
     }
 
+    #[test]
+    fn signed_ints () {
+        let (parsed, length) = parse(&[0xd0, 0x9c]).unwrap();
+        assert_eq!(length, 2);
+        assert_eq!(parsed.as_int().unwrap(), -100);
+
+        let (parsed, length) = parse(&[0xd1, 0xfc, 0x18]).unwrap();
+        assert_eq!(length, 3);
+        assert_eq!(parsed.as_int().unwrap(), -1000);
+
+        let (parsed, length) = parse(&[0xd2, 0xff, 0xff, 0xff, 0xfe]).unwrap();
+        assert_eq!(length, 5);
+        assert_eq!(parsed.as_int().unwrap(), -2);
+    }
+
+    #[test]
+    fn partial () {
+        // complete data parses normally
+        let data = [0x92, 0x01, 0xa1, 0x61];
+        let (parsed, length, errors) = parse_partial(&data);
+        assert_eq!(parsed, parse(&data).unwrap().0);
+        assert_eq!(length, 4);
+        assert!(errors.is_empty());
+
+        // invalid strings and unused markers are skipped over
+        let data = [0x94, 0xa2, 0xff, 0xfe, 0xc1, 0xd9, 0x01, 0x80, 0x07];
+        let (parsed, length, errors) = parse_partial(&data);
+        assert_eq!(parsed, MsgPack::Array(vec![MsgPack::Nil, MsgPack::Nil, MsgPack::Nil, MsgPack::Int(7)]));
+        assert_eq!(length, 9);
+        assert_eq!(errors.iter().map(|error| error.path.clone()).collect::<Vec<_>>(), vec![vec![0], vec![1], vec![2]]);
+        assert_eq!(errors[0].error.byte, 2);
+
+        // nested collections are kept up to where the data ends
+        let data = [0x81, 0xa1, 0x61, 0x93, 0x01, 0x02];
+        let (parsed, length, errors) = parse_partial(&data);
+        assert_eq!(parsed, MsgPack::Map(vec![MapElement {
+            key: MsgPack::String("a".to_string()),
+            value: MsgPack::Array(vec![MsgPack::Int(1), MsgPack::Int(2)])
+        }]));
+        assert_eq!(length, 6);
+        assert_eq!(errors, vec![PartialError { error: ParseError { byte: 6 }, path: vec![0], placeholder: false, key: false }]);
+
+        // a map ending after a key gets a placeholder value
+        let data = [0x82, 0xa1, 0x61, 0x01, 0xa1, 0x62];
+        let (parsed, _, errors) = parse_partial(&data);
+        assert_eq!(parsed.as_map().unwrap()[1].value, MsgPack::Nil);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].placeholder);
+        assert!(!errors[0].key);
+
+        // a broken or cut off key ends the map instead of becoming an entry
+        let data = [0x92, 0x83, 0xa1, 0x61, 0x01, 0xc1, 0x02, 0xa1, 0x62, 0x03, 0x07];
+        let (parsed, length, errors) = parse_partial(&data);
+        assert_eq!(parsed, MsgPack::Array(vec![MsgPack::Map(vec![MapElement { key: MsgPack::String("a".to_string()), value: MsgPack::Int(1) }])]));
+        assert_eq!(length, data.len());
+        assert_eq!(errors, vec![PartialError { error: ParseError { byte: 5 }, path: vec![0, 1], placeholder: false, key: true }]);
+
+        let data = [0x82, 0xa1, 0x61, 0x01, 0xa3, 0x62];
+        let (parsed, _, errors) = parse_partial(&data);
+        assert_eq!(parsed.as_map().unwrap().len(), 1);
+        assert_eq!(errors.len(), 1);
+        assert_eq!((errors[0].path.clone(), errors[0].key), (vec![1], true));
+
+        let (parsed, length, errors) = parse_partial(&[]);
+        assert!(parsed.is_nil());
+        assert_eq!(length, 0);
+        assert_eq!(errors.len(), 1);
+    }
+
     #[test]
     fn objects () {
         let (parsed, length) = parse(&vec![0x82, 0xa7, 0x63, 0x6f, 0x6d, 0x70, 0x61, 0x63, 0x74, 0xc3, 0xa6, 0x73, 0x63, 0x68, 0x65, 0x6d, 0x61, 0x93, 0x01, 0x02, 0xcb, 0x3f, 0xf5, 0x1e, 0xb8, 0x51, 0xeb, 0x85, 0x1f]).unwrap();
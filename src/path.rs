@@ -0,0 +1,444 @@
+//! Addressing values deep inside a MsgPack tree
+
+use std::fmt::{self, Display, Formatter};
+
+use super::{MsgPack, MapElement};
+use super::error::PathError;
+use super::index::MapKey;
+
+/// A single step in a [Path](struct.Path.html)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathSegment {
+    /// A map key. String keys that are valid indexes, and non-negative integer
+    /// keys, can also select an element of an array.
+    Key(MsgPack),
+    /// An array index. On maps, it selects the integer key with the same value.
+    Index(usize),
+}
+
+/// A path to a value inside a MsgPack tree
+/// 
+/// Paths can be parsed from pointer strings in the style of JSON Pointer
+/// (RFC 6901), such as `"/users/3/name"`, where each token is a string key or
+/// an array index. `/` in keys is written as `~1` and `~` as `~0`. The leading
+/// `/` is optional, and the empty string points to the root.
+/// 
+/// Keys that are not strings, like integers or binaries, can only be addressed
+/// by building the path directly:
+/// 
+///     use msgpack_simple::{msgpack, MsgPack, Path};
+/// 
+///     let message = msgpack!({ "a/b": { 42: [bin[0x01], "found"] } });
+///     let path = Path::root().key("a/b").key(42).index(1);
+/// 
+///     assert_eq!(path.to_string(), "/a~1b/42/1");
+///     assert_eq!(message.pointer(&path), Some(&msgpack!("found")));
+///     assert_eq!(message.pointer("/a~1b"), message.get("a/b"));
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Path {
+    segments: Vec<PathSegment>,
+}
+
+impl Path {
+    /// Creates an empty path, pointing to the root
+    pub fn root () -> Path {
+        Path { segments: vec![] }
+    }
+
+    /// Parses a pointer string, see the [Path](struct.Path.html) docs for the
+    /// syntax. Every token becomes a `PathSegment::Key` with a string.
+    /// 
+    ///     use msgpack_simple::{MsgPack, Path, PathSegment};
+    /// 
+    ///     let path = Path::parse("/users/3/a~1b");
+    ///     assert_eq!(path.segments(), &[
+    ///         PathSegment::Key(MsgPack::String("users".to_string())),
+    ///         PathSegment::Key(MsgPack::String("3".to_string())),
+    ///         PathSegment::Key(MsgPack::String("a/b".to_string())),
+    ///     ]);
+    pub fn parse (pointer: &str) -> Path {
+        if pointer.is_empty() { return Path::root() }
+
+        let pointer = pointer.strip_prefix('/').unwrap_or(pointer);
+        let segments = pointer.split('/')
+            .map(|token| PathSegment::Key(MsgPack::String(token.replace("~1", "/").replace("~0", "~"))))
+            .collect();
+
+        Path { segments }
+    }
+
+    /// Extends the path with a map key
+    pub fn key <K: Into<MsgPack>> (mut self, key: K) -> Path {
+        self.segments.push(PathSegment::Key(key.into()));
+        self
+    }
+
+    /// Extends the path with an array index
+    pub fn index (mut self, index: usize) -> Path {
+        self.segments.push(PathSegment::Index(index));
+        self
+    }
+
+    /// Adds a segment to the end of the path
+    pub fn push (&mut self, segment: PathSegment) {
+        self.segments.push(segment);
+    }
+
+    // adds a segment to the start of the path, for errors found further down
+    pub(crate) fn prepend (&mut self, segment: PathSegment) {
+        self.segments.insert(0, segment);
+    }
+
+    /// Removes the last segment of the path and returns it
+    pub fn pop (&mut self) -> Option<PathSegment> {
+        self.segments.pop()
+    }
+
+    /// The segments of the path, from the root
+    pub fn segments (&self) -> &[PathSegment] {
+        &self.segments
+    }
+
+    /// Checks if the path points to the root
+    pub fn is_root (&self) -> bool {
+        self.segments.is_empty()
+    }
+}
+
+impl Display for Path {
+    fn fmt (&self, f: &mut Formatter) -> fmt::Result {
+        for segment in &self.segments {
+            match segment {
+                PathSegment::Key(MsgPack::String(key)) => write!(f, "/{}", key.replace('~', "~0").replace('/', "~1"))?,
+                PathSegment::Key(key) => write!(f, "/{}", key.to_string().replace('~', "~0").replace('/', "~1"))?,
+                PathSegment::Index(index) => write!(f, "/{}", index)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl From<&str> for Path {
+    fn from (pointer: &str) -> Path {
+        Path::parse(pointer)
+    }
+}
+
+impl From<&String> for Path {
+    fn from (pointer: &String) -> Path {
+        Path::parse(pointer)
+    }
+}
+
+impl From<&Path> for Path {
+    fn from (path: &Path) -> Path {
+        path.clone()
+    }
+}
+
+impl From<Vec<PathSegment>> for Path {
+    fn from (segments: Vec<PathSegment>) -> Path {
+        Path { segments }
+    }
+}
+
+pub(crate) fn array_index (segment: &PathSegment) -> Option<usize> {
+    match segment {
+        PathSegment::Index(index) => Some(*index),
+        PathSegment::Key(MsgPack::Int(index)) if *index >= 0 => Some(*index as usize),
+        PathSegment::Key(MsgPack::Uint(index)) => Some(*index as usize),
+        // like in JSON Pointer, leading zeros are not allowed
+        PathSegment::Key(MsgPack::String(token)) if token == "0" || !token.starts_with('0') => token.parse().ok(),
+        _ => None
+    }
+}
+
+pub(crate) fn map_position (map: &[MapElement], segment: &PathSegment) -> Option<usize> {
+    map.iter().position(|element| match segment {
+        PathSegment::Index(index) => index.matches_key(&element.key),
+        PathSegment::Key(MsgPack::Int(key)) => key.matches_key(&element.key),
+        PathSegment::Key(MsgPack::Uint(key)) => key.matches_key(&element.key),
+        PathSegment::Key(key) => key.matches_key(&element.key),
+    })
+}
+
+fn child <'a> (node: &'a MsgPack, segment: &PathSegment) -> Option<&'a MsgPack> {
+    match node {
+        MsgPack::Map(map) => map_position(map, segment).map(|position| &map[position].value),
+        MsgPack::Array(array) => array_index(segment).and_then(|index| array.get(index)),
+        _ => None
+    }
+}
+
+pub(crate) fn child_mut <'a> (node: &'a mut MsgPack, segment: &PathSegment) -> Option<&'a mut MsgPack> {
+    match node {
+        MsgPack::Map(map) => map_position(map, segment).map(move |position| &mut map[position].value),
+        MsgPack::Array(array) => array_index(segment).and_then(move |index| array.get_mut(index)),
+        _ => None
+    }
+}
+
+// the index of a segment in an array, where `-` is the index after the end
+fn append_index (array: &[MsgPack], segment: &PathSegment) -> Option<usize> {
+    match segment {
+        PathSegment::Key(MsgPack::String(token)) if token == "-" => Some(array.len()),
+        _ => array_index(segment)
+    }
+}
+
+// the value wrapped in a new map or array for each segment, innermost last
+fn nest (segments: &[PathSegment], value: MsgPack) -> MsgPack {
+    segments.iter().rev().fold(value, |inner, segment| match segment {
+        PathSegment::Index(_) => MsgPack::Array(vec![inner]),
+        PathSegment::Key(_) => MsgPack::Map(vec![MapElement { key: segment_key(segment), value: inner }]),
+    })
+}
+
+pub(crate) fn segment_key (segment: &PathSegment) -> MsgPack {
+    match segment {
+        PathSegment::Key(key) => key.clone(),
+        PathSegment::Index(index) => MsgPack::Uint(*index as u64),
+    }
+}
+
+impl MsgPack {
+    /// Looks up a value by its path
+    /// 
+    /// Accepts a pointer string like `"/users/3/name"` or a
+    /// [Path](struct.Path.html). Returns `None` if any part of the path is
+    /// missing.
+    /// 
+    ///     use msgpack_simple::msgpack;
+    /// 
+    ///     let message = msgpack!({ "users": [{ "name": "Ferris" }] });
+    /// 
+    ///     assert_eq!(message.pointer("/users/0/name"), Some(&msgpack!("Ferris")));
+    ///     assert_eq!(message.pointer("/users/1/name"), None);
+    ///     assert_eq!(message.pointer(""), Some(&message));
+    pub fn pointer <P: Into<Path>> (&self, path: P) -> Option<&MsgPack> {
+        let path = path.into();
+        let mut node = self;
+
+        for segment in path.segments() {
+            node = child(node, segment)?;
+        }
+
+        Some(node)
+    }
+
+    /// Looks up a value by its path, as a mutable reference
+    /// 
+    ///     use msgpack_simple::msgpack;
+    /// 
+    ///     let mut message = msgpack!({ "users": [{ "name": "Ferris" }] });
+    ///     *message.pointer_mut("/users/0/name").unwrap() = msgpack!("Corro");
+    /// 
+    ///     assert_eq!(message, msgpack!({ "users": [{ "name": "Corro" }] }));
+    pub fn pointer_mut <P: Into<Path>> (&mut self, path: P) -> Option<&mut MsgPack> {
+        let path = path.into();
+        let mut node = self;
+
+        for segment in path.segments() {
+            node = child_mut(node, segment)?;
+        }
+
+        Some(node)
+    }
+
+    /// Sets a value by its path, creating any missing maps and arrays along
+    /// the way, and returns the value it replaced, or `None` if there was none
+    /// 
+    /// Missing intermediate values, and Nils, are replaced with an array if
+    /// the next segment is a `PathSegment::Index`, and a map otherwise, so
+    /// pointer strings always create maps. Arrays can be extended by setting
+    /// the index right after the last element, or `-` in a pointer string.
+    /// 
+    /// The whole path is checked before anything is changed, so if an error is
+    /// returned, the value is left as it was.
+    /// 
+    ///     use msgpack_simple::{msgpack, MsgPack, Path};
+    /// 
+    ///     let mut config = MsgPack::Nil;
+    ///     config.set_path("/server/host", "localhost").unwrap();
+    ///     config.set_path(Path::root().key("server").key("ports").index(0), 8080).unwrap();
+    ///     config.set_path("/server/ports/-", 8081).unwrap();
+    /// 
+    ///     assert_eq!(config, msgpack!({ "server": { "host": "localhost", "ports": [8080, 8081] } }));
+    /// 
+    ///     let previous = config.set_path("/server/host", "example.com").unwrap();
+    ///     assert_eq!(previous, Some(msgpack!("localhost")));
+    /// 
+    ///     assert!(config.set_path("/server/host/name", "oops").is_err());
+    ///     assert!(config.set_path(Path::root().key("server").key("backup").index(3), "oops").is_err());
+    ///     assert_eq!(config.pointer("/server/backup"), None);
+    pub fn set_path <P: Into<Path>, V: Into<MsgPack>> (&mut self, path: P, value: V) -> Result<Option<MsgPack>, PathError> {
+        let path = path.into();
+        let segments = path.segments();
+
+        // follow the part of the path that exists, up to a missing value or a
+        // Nil, without changing anything
+        let mut node: &MsgPack = self;
+        let mut existing = 0;
+
+        while existing < segments.len() && !node.is_nil() {
+            let segment = &segments[existing];
+
+            node = match node {
+                MsgPack::Map(map) => match map_position(map, segment) {
+                    Some(position) => &map[position].value,
+                    None => break
+                },
+                MsgPack::Array(array) => match append_index(array, segment) {
+                    Some(index) if index < array.len() => &array[index],
+                    Some(index) if index == array.len() => break,
+                    Some(index) => {
+                        let reason = format!("index {} is out of bounds for an array of length {}", index, array.len());
+                        return Err(PathError { path: path.clone(), depth: existing, reason })
+                    },
+                    None => {
+                        return Err(PathError { path: path.clone(), depth: existing, reason: "array index expected".to_string() })
+                    }
+                },
+                other => {
+                    let reason = format!("cannot go into {}", other.type_name());
+                    return Err(PathError { path: path.clone(), depth: existing, reason })
+                }
+            };
+
+            existing += 1;
+        }
+
+        // the rest of the path goes through new maps and arrays, where only
+        // the first element of an array can be set
+        let rest = &segments[existing..];
+        let replaces_nil = node.is_nil();
+        let created = if replaces_nil { 0 } else { 1 };
+
+        for (offset, segment) in rest.iter().enumerate().skip(created) {
+            if let PathSegment::Index(index) = segment {
+                if *index != 0 {
+                    let reason = format!("index {} is out of bounds for an array of length 0", index);
+                    return Err(PathError { path: path.clone(), depth: existing + offset, reason })
+                }
+            }
+        }
+
+        let mut target = self;
+        for segment in &segments[..existing] {
+            // the lookups above found every one of these
+            target = child_mut(target, segment).unwrap();
+        }
+
+        let value = value.into();
+        if rest.is_empty() { return Ok(Some(std::mem::replace(target, value))) }
+
+        match target {
+            MsgPack::Map(map) if !replaces_nil => map.push(MapElement { key: segment_key(&rest[0]), value: nest(&rest[1..], value) }),
+            MsgPack::Array(array) if !replaces_nil => array.push(nest(&rest[1..], value)),
+            nil => *nil = nest(rest, value)
+        }
+
+        Ok(None)
+    }
+
+    /// Removes a value by its path and returns it
+    /// 
+    /// Map entries are removed entirely, and array elements after the removed
+    /// one are shifted down. Returns `None` if the value is not found, or the
+    /// path points to the root.
+    /// 
+    ///     use msgpack_simple::msgpack;
+    /// 
+    ///     let mut message = msgpack!({ "users": [{ "name": "Ferris", "age": 7 }] });
+    /// 
+    ///     assert_eq!(message.remove_path("/users/0/age"), Some(msgpack!(7)));
+    ///     assert_eq!(message.remove_path("/users/0/age"), None);
+    ///     assert_eq!(message, msgpack!({ "users": [{ "name": "Ferris" }] }));
+    pub fn remove_path <P: Into<Path>> (&mut self, path: P) -> Option<MsgPack> {
+        let mut path = path.into();
+        let last = path.pop()?;
+
+        match self.pointer_mut(path)? {
+            MsgPack::Map(map) => map_position(map, &last).map(|position| map.remove(position).value),
+            MsgPack::Array(array) => match array_index(&last) {
+                Some(index) if index < array.len() => Some(array.remove(index)),
+                _ => None
+            },
+            _ => None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_display () {
+        assert!(Path::parse("").is_root());
+        assert_eq!(Path::parse("/"), Path::root().key(""));
+        assert_eq!(Path::parse("a/b"), Path::parse("/a/b"));
+        assert_eq!(Path::parse("/~01/~10"), Path::root().key("~1").key("/0"));
+        assert_eq!(Path::parse("/~01/~10").to_string(), "/~01/~10");
+        assert_eq!(Path::root().key(-1).index(2).key(true).to_string(), "/-1/2/true");
+    }
+
+    #[test]
+    fn lookup () {
+        let message = MsgPack::Map(vec![
+            MapElement { key: MsgPack::Uint(1), value: MsgPack::Array(vec![MsgPack::Nil, MsgPack::Boolean(true)]) },
+            MapElement { key: MsgPack::String("1".to_string()), value: MsgPack::Int(2) },
+            MapElement { key: MsgPack::Binary(vec![0xff]), value: MsgPack::Int(3) },
+        ]);
+
+        assert_eq!(message.pointer("/1"), Some(&MsgPack::Int(2)));
+        assert_eq!(message.pointer(Path::root().index(1).key("1")), Some(&MsgPack::Boolean(true)));
+        assert_eq!(message.pointer(Path::root().key(1i8).index(1)), Some(&MsgPack::Boolean(true)));
+        assert_eq!(message.pointer(Path::root().key(MsgPack::Binary(vec![0xff]))), Some(&MsgPack::Int(3)));
+        assert_eq!(message.pointer(Path::root().index(1).key("01")), None);
+        assert_eq!(message.pointer(Path::root().index(1).key("-")), None);
+    }
+
+    #[test]
+    fn set_and_remove () {
+        let mut message = MsgPack::Nil;
+        assert_eq!(message.set_path(Path::root().key("a").index(0).key("b"), 1).unwrap(), None);
+        assert_eq!(message, MsgPack::Map(vec![MapElement {
+            key: MsgPack::String("a".to_string()),
+            value: MsgPack::Array(vec![MsgPack::Map(vec![MapElement { key: MsgPack::String("b".to_string()), value: MsgPack::Int(1) }])])
+        }]));
+
+        let error = message.set_path("/a/5", 1).unwrap_err();
+        assert_eq!(error.depth, 1);
+        assert_eq!(error.to_string(), "MsgPack path error at /a/5: index 5 is out of bounds for an array of length 1");
+
+        let error = message.set_path("/a/0/b/c", 1).unwrap_err();
+        assert_eq!(error.depth, 3);
+
+        // failures leave the value untouched
+        let mut map = MsgPack::Map(vec![]);
+        let error = map.set_path(Path::root().key("new").index(5), 1).unwrap_err();
+        assert_eq!(error.depth, 1);
+        assert_eq!(map, MsgPack::Map(vec![]));
+
+        let mut nil = MsgPack::Nil;
+        assert!(nil.set_path(Path::root().index(0).index(1), 1).is_err());
+        assert!(nil.is_nil());
+
+        // a Nil that gets replaced is returned as it was
+        let mut map = MsgPack::Map(vec![MapElement { key: MsgPack::String("a".to_string()), value: MsgPack::Nil }]);
+        assert_eq!(map.set_path("/a", 1).unwrap(), Some(MsgPack::Nil));
+        assert_eq!(map.set_path("/b", 2).unwrap(), None);
+
+        let before = message.clone();
+        assert_eq!(message.set_path("", 5).unwrap(), Some(before));
+        assert_eq!(message, MsgPack::Int(5));
+
+        let mut message = MsgPack::Array(vec![MsgPack::Int(1), MsgPack::Int(2), MsgPack::Int(3)]);
+        assert_eq!(message.remove_path("/1"), Some(MsgPack::Int(2)));
+        assert_eq!(message.remove_path("/2"), None);
+        assert_eq!(message.remove_path(""), None);
+        assert_eq!(message, MsgPack::Array(vec![MsgPack::Int(1), MsgPack::Int(3)]));
+    }
+}
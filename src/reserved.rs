@@ -0,0 +1,228 @@
+//! Handling of the extension type ids reserved by the MessagePack spec
+
+use super::{MsgPack, Extension};
+use super::error::{ParseError, ReservedExtension};
+use super::parser;
+use super::path::Path;
+use super::timestamp::Timestamp;
+
+// reserved type ids the spec has defined so far
+const DEFINED: [i8; 1] = [Timestamp::TYPE_ID];
+
+impl Extension {
+    /// Creates an extension with an application-defined type id, returning
+    /// `None` if the id is reserved for the spec
+    /// 
+    /// Ids 0 to 127 are free for applications, the negative ones are reserved,
+    /// so using them risks a collision with a type the spec defines later.
+    /// 
+    ///     use msgpack_simple::Extension;
+    /// 
+    ///     assert_eq!(Extension::application(2, vec![0x42]), Some(Extension { type_id: 2, value: vec![0x42] }));
+    ///     assert_eq!(Extension::application(-2, vec![0x42]), None);
+    pub fn application (type_id: i8, value: Vec<u8>) -> Option<Extension> {
+        if type_id < 0 { return None }
+        Some(Extension { type_id, value })
+    }
+
+    /// Checks if the type id is reserved for the spec
+    pub fn is_reserved (&self) -> bool {
+        self.type_id < 0
+    }
+
+    /// Checks if the type id is reserved, but not defined by the spec yet
+    pub fn is_unknown_reserved (&self) -> bool {
+        self.is_reserved() && !DEFINED.contains(&self.type_id)
+    }
+}
+
+/// What to do with extensions that use a reserved type id the spec doesn't
+/// define
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReservedPolicy {
+    /// Fail on them
+    Reject,
+    /// Accept them like any other extension
+    #[default]
+    PassThrough,
+    /// Accept them, but report them along with the result
+    Warn,
+}
+
+fn check (node: &MsgPack, path: &Path, found: &mut Vec<ReservedExtension>) {
+    if let MsgPack::Extension(extension) = node {
+        if extension.is_unknown_reserved() {
+            found.push(ReservedExtension { type_id: extension.type_id, path: path.clone() });
+        }
+    }
+}
+
+// every extension in the value with an unknown reserved type id, including
+// the ones in map keys, which are reported at the path of their entry
+fn find_reserved (value: &MsgPack) -> Vec<ReservedExtension> {
+    let mut found = vec![];
+
+    value.walk(&mut |path: &Path, _: usize, node: &MsgPack| {
+        check(node, path, &mut found);
+
+        if let MsgPack::Map(map) = node {
+            for element in map {
+                let entry = path.clone().key(element.key.clone());
+                element.key.walk(&mut |_: &Path, _: usize, node: &MsgPack| check(node, &entry, &mut found));
+            }
+        }
+    });
+
+    found
+}
+
+/// Settings for parsing, see [ReservedPolicy](enum.ReservedPolicy.html)
+/// 
+/// By default, extensions with reserved type ids are passed through, just
+/// like with [MsgPack::parse](enum.MsgPack.html#method.parse).
+/// 
+///     use msgpack_simple::{MsgPack, ParseOptions, ReservedPolicy};
+/// 
+///     // an array with an extension of type -2
+///     let data = [0x91, 0xd4, 0xfe, 0x00];
+/// 
+///     let rejecting = ParseOptions::new().reserved(ReservedPolicy::Reject);
+///     assert_eq!(rejecting.parse(&data).unwrap_err().byte, 1);
+/// 
+///     let warning = ParseOptions::new().reserved(ReservedPolicy::Warn);
+///     let (value, warnings) = warning.parse(&data).unwrap();
+///     assert_eq!(value, MsgPack::parse(&data).unwrap());
+///     assert_eq!(warnings[0].to_string(), "MsgPack reserved extension type -2 at /0");
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ParseOptions {
+    reserved: ReservedPolicy,
+}
+
+impl ParseOptions {
+    /// Creates the default settings
+    pub fn new () -> ParseOptions {
+        ParseOptions::default()
+    }
+
+    /// Sets what to do with unknown reserved extension types
+    pub fn reserved (mut self, policy: ReservedPolicy) -> ParseOptions {
+        self.reserved = policy;
+        self
+    }
+
+    /// Parses binary data as MsgPack, returning the result and the unknown
+    /// reserved extensions found if the policy is to warn
+    /// 
+    /// If the policy is to reject, the error points to the first unknown
+    /// reserved extension.
+    pub fn parse (&self, raw: &[u8]) -> Result<(MsgPack, Vec<ReservedExtension>), ParseError> {
+        match self.reserved {
+            ReservedPolicy::Reject => {
+                let (result, _) = parser::parse_with(raw, &mut |extension| !extension.is_unknown_reserved())?;
+                Ok((result, vec![]))
+            },
+            ReservedPolicy::PassThrough => Ok((MsgPack::parse(raw)?, vec![])),
+            ReservedPolicy::Warn => {
+                let result = MsgPack::parse(raw)?;
+                let warnings = find_reserved(&result);
+                Ok((result, warnings))
+            }
+        }
+    }
+}
+
+/// Settings for encoding, see [ReservedPolicy](enum.ReservedPolicy.html)
+/// 
+/// By default, extensions with reserved type ids are passed through, just
+/// like with [MsgPack::encode](enum.MsgPack.html#method.encode).
+/// 
+///     use msgpack_simple::{msgpack, EncodeOptions, ReservedPolicy};
+/// 
+///     let message = msgpack!({ "a": ext(-2, [0x00]) });
+/// 
+///     let error = EncodeOptions::new().reserved(ReservedPolicy::Reject).encode(&message).unwrap_err();
+///     assert_eq!(error.to_string(), "MsgPack reserved extension type -2 at /a");
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct EncodeOptions {
+    reserved: ReservedPolicy,
+}
+
+impl EncodeOptions {
+    /// Creates the default settings
+    pub fn new () -> EncodeOptions {
+        EncodeOptions::default()
+    }
+
+    /// Sets what to do with unknown reserved extension types
+    pub fn reserved (mut self, policy: ReservedPolicy) -> EncodeOptions {
+        self.reserved = policy;
+        self
+    }
+
+    /// Encodes a value, returning the data and the unknown reserved
+    /// extensions found if the policy is to warn
+    /// 
+    /// If the policy is to reject, the first unknown reserved extension is
+    /// returned as the error.
+    pub fn encode (&self, value: &MsgPack) -> Result<(Vec<u8>, Vec<ReservedExtension>), ReservedExtension> {
+        let warnings = match self.reserved {
+            ReservedPolicy::PassThrough => vec![],
+            ReservedPolicy::Warn => find_reserved(value),
+            ReservedPolicy::Reject => match find_reserved(value).into_iter().next() {
+                Some(error) => return Err(error),
+                None => vec![]
+            }
+        };
+
+        Ok((value.encode(), warnings))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::MapElement;
+
+    fn extension (type_id: i8) -> MsgPack {
+        MsgPack::Extension(Extension { type_id, value: vec![0] })
+    }
+
+    #[test]
+    fn parsing () {
+        let message = MsgPack::Array(vec![extension(5), extension(-1), MsgPack::Array(vec![extension(-128)])]);
+        let data = message.encode();
+
+        assert_eq!(ParseOptions::new().parse(&data).unwrap(), (message.clone(), vec![]));
+        assert_eq!(ParseOptions::new().reserved(ReservedPolicy::Reject).parse(&data).unwrap_err().byte, 8);
+        assert_eq!(ParseOptions::new().reserved(ReservedPolicy::Warn).parse(&data).unwrap(), (message, vec![
+            ReservedExtension { type_id: -128, path: Path::root().index(2).index(0) }
+        ]));
+
+        let timestamp = MsgPack::Extension(Extension { type_id: -1, value: vec![0; 4] });
+        assert!(ParseOptions::new().reserved(ReservedPolicy::Reject).parse(&timestamp.encode()).is_ok());
+    }
+
+    #[test]
+    fn encoding () {
+        let options = EncodeOptions::new();
+
+        assert_eq!(options.encode(&extension(-3)).unwrap(), (extension(-3).encode(), vec![]));
+        assert_eq!(options.reserved(ReservedPolicy::Warn).encode(&extension(-3)).unwrap().1.len(), 1);
+        assert!(options.reserved(ReservedPolicy::Reject).encode(&extension(-1)).is_ok());
+
+        let error = options.reserved(ReservedPolicy::Reject).encode(&extension(-3)).unwrap_err();
+        assert_eq!(error.to_string(), "MsgPack reserved extension type -3 at the root");
+
+        // extensions used as map keys count too, in every path
+        let keyed = MsgPack::Map(vec![MapElement { key: MsgPack::from("a"), value: MsgPack::Nil }, MapElement { key: extension(-5), value: MsgPack::Int(1) }]);
+        let found = vec![ReservedExtension { type_id: -5, path: Path::root().key(extension(-5)) }];
+        assert_eq!(options.reserved(ReservedPolicy::Warn).encode(&keyed).unwrap().1, found);
+        assert_eq!(options.reserved(ReservedPolicy::Reject).encode(&keyed).unwrap_err(), found[0]);
+        assert_eq!(ParseOptions::new().reserved(ReservedPolicy::Warn).parse(&keyed.encode()).unwrap().1, found);
+        assert_eq!(ParseOptions::new().reserved(ReservedPolicy::Reject).parse(&keyed.encode()).unwrap_err().byte, 4);
+
+        assert!(!Extension { type_id: 0, value: vec![] }.is_reserved());
+        assert!(Extension { type_id: -1, value: vec![] }.is_reserved());
+        assert!(!Extension { type_id: -1, value: vec![] }.is_unknown_reserved());
+    }
+}
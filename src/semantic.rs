@@ -0,0 +1,251 @@
+//! Equality that looks at what a value means instead of how it's stored
+
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use super::{MsgPack, MapElement};
+use super::cmp::{compare_floats, compare_int_float, float_bits, NIL, BOOLEAN, INTEGER, FLOAT, STRING, BINARY, ARRAY, MAP, EXTENSION};
+
+/// Settings for semantic equality, see
+/// [MsgPack::semantic_eq](enum.MsgPack.html#method.semantic_eq)
+/// 
+/// Ints and uints with the same value are always equal, and maps are always
+/// compared as unordered sets of entries. Whether floats with an integral
+/// value are equal to ints can be switched on with `integral_floats`.
+/// 
+///     use msgpack_simple::{msgpack, Semantic};
+/// 
+///     let strict = Semantic::new();
+///     let loose = Semantic::new().integral_floats(true);
+/// 
+///     assert!(!strict.equal(&msgpack!([1, 2]), &msgpack!([1.0, 2])));
+///     assert!(loose.equal(&msgpack!([1, 2]), &msgpack!([1.0, 2])));
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Semantic {
+    integral_floats: bool
+}
+
+impl Semantic {
+    /// Creates the default settings, where floats are never equal to ints
+    pub fn new () -> Semantic {
+        Semantic::default()
+    }
+
+    /// Sets whether floats with an integral value, like `5.0`, are equal to
+    /// ints with the same value
+    pub fn integral_floats (mut self, enabled: bool) -> Semantic {
+        self.integral_floats = enabled;
+        self
+    }
+
+    /// Checks if two values are semantically equal
+    pub fn equal (&self, a: &MsgPack, b: &MsgPack) -> bool {
+        match (a, b) {
+            (MsgPack::Nil, MsgPack::Nil) => true,
+            (MsgPack::Boolean(a), MsgPack::Boolean(b)) => a == b,
+            (MsgPack::Int(a), MsgPack::Int(b)) => a == b,
+            (MsgPack::Uint(a), MsgPack::Uint(b)) => a == b,
+            (MsgPack::Int(a), MsgPack::Uint(b)) | (MsgPack::Uint(b), MsgPack::Int(a)) => *a as i128 == *b as i128,
+            (MsgPack::Float(a), MsgPack::Float(b)) => compare_floats(*a, *b) == Ordering::Equal,
+            (MsgPack::Int(a), MsgPack::Float(b)) | (MsgPack::Float(b), MsgPack::Int(a)) => {
+                self.integral_floats && compare_int_float(*a as i128, *b) == Ordering::Equal
+            },
+            (MsgPack::Uint(a), MsgPack::Float(b)) | (MsgPack::Float(b), MsgPack::Uint(a)) => {
+                self.integral_floats && compare_int_float(*a as i128, *b) == Ordering::Equal
+            },
+            (MsgPack::String(a), MsgPack::String(b)) => a == b,
+            (MsgPack::Binary(a), MsgPack::Binary(b)) => a == b,
+            (MsgPack::Array(a), MsgPack::Array(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| self.equal(a, b))
+            },
+            (MsgPack::Map(a), MsgPack::Map(b)) => self.maps_equal(a, b),
+            (MsgPack::Extension(a), MsgPack::Extension(b)) => a == b,
+            _ => false
+        }
+    }
+
+    // every entry has to pair up with a distinct equal entry on the other
+    // side, duplicates included
+    fn maps_equal (&self, a: &[MapElement], b: &[MapElement]) -> bool {
+        if a.len() != b.len() { return false }
+
+        let mut used = vec![false; b.len()];
+
+        a.iter().all(|element| {
+            let found = b.iter().enumerate().position(|(index, other)| {
+                !used[index] && self.equal(&element.key, &other.key) && self.equal(&element.value, &other.value)
+            });
+
+            match found {
+                Some(index) => { used[index] = true; true },
+                None => false
+            }
+        })
+    }
+
+    /// Feeds a value into a hasher, consistently with `equal`, so values that
+    /// are semantically equal produce the same hash
+    pub fn hash <H: Hasher> (&self, value: &MsgPack, state: &mut H) {
+        match value {
+            MsgPack::Nil => state.write_u8(NIL),
+            MsgPack::Boolean(value) => { state.write_u8(BOOLEAN); value.hash(state) },
+            MsgPack::Int(value) => { state.write_u8(INTEGER); (*value as i128).hash(state) },
+            MsgPack::Uint(value) => { state.write_u8(INTEGER); (*value as i128).hash(state) },
+            MsgPack::Float(value) => {
+                if self.integral_floats && value.fract() == 0.0 && value.abs() < 2f64.powi(127) {
+                    state.write_u8(INTEGER);
+                    (*value as i128).hash(state)
+                } else {
+                    state.write_u8(FLOAT);
+                    float_bits(*value).hash(state)
+                }
+            },
+            MsgPack::String(value) => { state.write_u8(STRING); value.hash(state) },
+            MsgPack::Binary(value) => { state.write_u8(BINARY); value.hash(state) },
+            MsgPack::Array(value) => {
+                state.write_u8(ARRAY);
+                state.write_usize(value.len());
+                for item in value { self.hash(item, state) }
+            },
+            MsgPack::Map(value) => {
+                // entries are hashed on their own and summed, so the order
+                // doesn't matter
+                let sum = value.iter().fold(0u64, |sum, element| {
+                    let mut entry = DefaultHasher::new();
+                    self.hash(&element.key, &mut entry);
+                    self.hash(&element.value, &mut entry);
+                    sum.wrapping_add(entry.finish())
+                });
+
+                state.write_u8(MAP);
+                state.write_usize(value.len());
+                state.write_u64(sum);
+            },
+            MsgPack::Extension(value) => { state.write_u8(EXTENSION); value.hash(state) }
+        }
+    }
+}
+
+/// Wraps a MsgPack value to use semantic equality and hashing, for example as
+/// a cache key in a `HashMap`
+/// 
+/// Uses the default [Semantic](struct.Semantic.html) settings.
+/// 
+///     use std::collections::HashMap;
+///     use msgpack_simple::{msgpack, MsgPack, SemanticKey};
+/// 
+///     let mut cache = HashMap::new();
+///     cache.insert(SemanticKey(msgpack!({ "a": 1, "b": 2 })), "cached");
+/// 
+///     let key = SemanticKey(msgpack!({ "b": 2, "a": MsgPack::Uint(1) }));
+///     assert_eq!(cache.get(&key), Some(&"cached"));
+#[derive(Debug, Clone)]
+pub struct SemanticKey(pub MsgPack);
+
+impl PartialEq for SemanticKey {
+    fn eq (&self, other: &SemanticKey) -> bool {
+        Semantic::new().equal(&self.0, &other.0)
+    }
+}
+
+impl Eq for SemanticKey {}
+
+impl Hash for SemanticKey {
+    fn hash <H: Hasher> (&self, state: &mut H) {
+        Semantic::new().hash(&self.0, state)
+    }
+}
+
+impl MsgPack {
+    /// Checks if two values mean the same, even if they are stored
+    /// differently
+    /// 
+    /// Unlike `==`, ints and uints with the same value are equal, and maps are
+    /// equal if they have the same entries in any order. Floats are still
+    /// never equal to ints, use [Semantic](struct.Semantic.html) to change
+    /// that.
+    /// 
+    ///     use msgpack_simple::{msgpack, MsgPack};
+    /// 
+    ///     let parsed = MsgPack::parse(&[0x82, 0xa1, 0x61, 0xcc, 0x05, 0xa1, 0x62, 0xc0]).unwrap();
+    /// 
+    ///     assert_ne!(parsed, msgpack!({ "b": nil, "a": 5 }));
+    ///     assert!(parsed.semantic_eq(&msgpack!({ "b": nil, "a": 5 })));
+    pub fn semantic_eq (&self, other: &MsgPack) -> bool {
+        Semantic::new().equal(self, other)
+    }
+
+    /// Feeds the value into a hasher, consistently with
+    /// [semantic_eq](#method.semantic_eq)
+    pub fn semantic_hash <H: Hasher> (&self, state: &mut H) {
+        Semantic::new().hash(self, state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash (semantic: Semantic, value: &MsgPack) -> u64 {
+        let mut state = DefaultHasher::new();
+        semantic.hash(value, &mut state);
+        state.finish()
+    }
+
+    fn entry (key: MsgPack, value: MsgPack) -> MapElement {
+        MapElement { key, value }
+    }
+
+    #[test]
+    fn equality () {
+        assert!(MsgPack::Int(5).semantic_eq(&MsgPack::Uint(5)));
+        assert!(!MsgPack::Int(-1).semantic_eq(&MsgPack::Uint(u64::MAX)));
+        assert!(!MsgPack::Int(5).semantic_eq(&MsgPack::Float(5.0)));
+        assert!(Semantic::new().integral_floats(true).equal(&MsgPack::Uint(5), &MsgPack::Float(5.0)));
+        assert!(!Semantic::new().integral_floats(true).equal(&MsgPack::Uint(5), &MsgPack::Float(5.5)));
+        assert!(MsgPack::Float(f64::NAN).semantic_eq(&MsgPack::Float(f64::NAN)));
+
+        let a = MsgPack::Map(vec![
+            entry(MsgPack::Int(1), MsgPack::Nil),
+            entry(MsgPack::Int(1), MsgPack::Nil),
+            entry(MsgPack::Int(2), MsgPack::Nil),
+        ]);
+        let b = MsgPack::Map(vec![
+            entry(MsgPack::Uint(2), MsgPack::Nil),
+            entry(MsgPack::Uint(1), MsgPack::Nil),
+            entry(MsgPack::Int(1), MsgPack::Nil),
+        ]);
+        let c = MsgPack::Map(vec![
+            entry(MsgPack::Int(1), MsgPack::Nil),
+            entry(MsgPack::Int(2), MsgPack::Nil),
+            entry(MsgPack::Int(2), MsgPack::Nil),
+        ]);
+
+        assert!(a.semantic_eq(&b));
+        assert!(!a.semantic_eq(&c));
+        assert!(!MsgPack::Array(vec![MsgPack::Int(1), MsgPack::Int(2)]).semantic_eq(&MsgPack::Array(vec![MsgPack::Int(2), MsgPack::Int(1)])));
+    }
+
+    #[test]
+    fn hashing () {
+        let strict = Semantic::new();
+        let loose = Semantic::new().integral_floats(true);
+
+        assert_eq!(hash(strict, &MsgPack::Int(5)), hash(strict, &MsgPack::Uint(5)));
+        assert_ne!(hash(strict, &MsgPack::Int(5)), hash(strict, &MsgPack::Float(5.0)));
+        assert_eq!(hash(loose, &MsgPack::Int(5)), hash(loose, &MsgPack::Float(5.0)));
+        assert_eq!(hash(loose, &MsgPack::Int(0)), hash(loose, &MsgPack::Float(-0.0)));
+
+        let a = MsgPack::Map(vec![
+            entry(MsgPack::from("a"), MsgPack::Int(1)),
+            entry(MsgPack::from("b"), MsgPack::Array(vec![])),
+        ]);
+        let b = MsgPack::Map(vec![
+            entry(MsgPack::from("b"), MsgPack::Array(vec![])),
+            entry(MsgPack::from("a"), MsgPack::Uint(1)),
+        ]);
+
+        assert_eq!(hash(strict, &a), hash(strict, &b));
+    }
+}
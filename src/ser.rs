@@ -0,0 +1,456 @@
+//! A serde serializer that writes MessagePack directly, behind the `serde`
+//! feature
+
+use std::io::{self, Write};
+
+use serde::ser::{self, Serialize};
+
+use super::{MsgPack, Extension};
+use super::error::SerdeError;
+use super::path::Path;
+use super::marker;
+use super::parser;
+use super::serde_impls::{EXTENSION_TOKEN, extension_from_field};
+
+fn io_error (error: io::Error) -> SerdeError {
+    SerdeError { message: error.to_string(), byte: None, path: Path::root() }
+}
+
+/// Serializes a value to MessagePack
+/// 
+/// The value is written as it's serialized, without building a
+/// [MsgPack](enum.MsgPack.html) tree first, but in the same formats that
+/// `MsgPack::encode()` uses. Structs are written as maps keyed by their field
+/// names, and enums as their variant name, or a map from the name to the
+/// contents if the variant has any.
+/// 
+///     use msgpack_simple::{to_vec, MsgPack};
+/// 
+///     #[derive(serde::Serialize)]
+///     struct Point { x: i32, y: i32 }
+/// 
+///     let encoded = to_vec(&Point { x: 1, y: -2 }).unwrap();
+///     assert_eq!(encoded, vec![0x82, 0xa1, 0x78, 0x01, 0xa1, 0x79, 0xfe]);
+///     assert_eq!(MsgPack::parse(&encoded).unwrap()["y"], MsgPack::Int(-2));
+pub fn to_vec <T: Serialize + ?Sized> (value: &T) -> Result<Vec<u8>, SerdeError> {
+    let mut serializer = Serializer::new(vec![]);
+    value.serialize(&mut serializer)?;
+    Ok(serializer.into_inner())
+}
+
+/// Serializes a value to MessagePack into a writer, see [to_vec](fn.to_vec.html)
+/// 
+/// Values are written piece by piece, so a buffered writer is recommended.
+pub fn to_writer <W: Write, T: Serialize + ?Sized> (writer: W, value: &T) -> Result<(), SerdeError> {
+    value.serialize(&mut Serializer::new(writer))
+}
+
+/// The serializer behind [to_vec](fn.to_vec.html) and
+/// [to_writer](fn.to_writer.html)
+pub struct Serializer<W> {
+    writer: W,
+}
+
+impl <W: Write> Serializer<W> {
+    /// Creates a serializer that writes into the writer
+    pub fn new (writer: W) -> Serializer<W> {
+        Serializer { writer }
+    }
+
+    /// Returns the writer
+    pub fn into_inner (self) -> W {
+        self.writer
+    }
+
+    fn write_str (&mut self, value: &str) -> Result<(), SerdeError> {
+        marker::write_string_header(&mut self.writer, value.len()).map_err(io_error)?;
+        self.writer.write_all(value.as_bytes()).map_err(io_error)
+    }
+
+    // an enum variant with contents is a map with a single entry
+    fn write_variant (&mut self, variant: &str) -> Result<(), SerdeError> {
+        marker::write_map_header(&mut self.writer, 1).map_err(io_error)?;
+        self.write_str(variant)
+    }
+
+    fn compound (&mut self, length: Option<usize>, map: bool) -> Result<Compound<'_, W>, SerdeError> {
+        let state = match length {
+            Some(length) if map => marker::write_map_header(&mut self.writer, length).map(|_| State::Direct),
+            Some(length) => marker::write_array_header(&mut self.writer, length).map(|_| State::Direct),
+            None => Ok(State::Counted { buffer: Serializer::new(vec![]), count: 0, map })
+        };
+
+        Ok(Compound { serializer: self, state: state.map_err(io_error)? })
+    }
+}
+
+// how the items of a compound are written
+enum State {
+    // straight to the writer, after a header with the known length
+    Direct,
+    // into a buffer, as the length is only known at the end
+    Counted { buffer: Serializer<Vec<u8>>, count: usize, map: bool },
+    // into a buffer, to be written as a native extension at the end
+    Extension(Serializer<Vec<u8>>),
+}
+
+/// Serializes the items of arrays, maps, structs and enum variants
+pub struct Compound<'a, W> {
+    serializer: &'a mut Serializer<W>,
+    state: State,
+}
+
+impl <W: Write> Compound<'_, W> {
+    fn item <T: Serialize + ?Sized> (&mut self, value: &T, counted: bool) -> Result<(), SerdeError> {
+        match &mut self.state {
+            State::Direct => value.serialize(&mut *self.serializer),
+            State::Counted { buffer, count, .. } => {
+                if counted { *count += 1 }
+                value.serialize(buffer)
+            },
+            State::Extension(buffer) => value.serialize(buffer)
+        }
+    }
+
+    fn field <T: Serialize + ?Sized> (&mut self, key: &'static str, value: &T) -> Result<(), SerdeError> {
+        if let State::Direct = self.state {
+            self.serializer.write_str(key)?;
+        }
+
+        self.item(value, true)
+    }
+
+    fn finish (self) -> Result<(), SerdeError> {
+        let writer = &mut self.serializer.writer;
+
+        match self.state {
+            State::Direct => Ok(()),
+            State::Counted { buffer, count, map } => {
+                if map {
+                    marker::write_map_header(writer, count).map_err(io_error)?;
+                } else {
+                    marker::write_array_header(writer, count).map_err(io_error)?;
+                }
+
+                writer.write_all(&buffer.writer).map_err(io_error)
+            },
+            State::Extension(buffer) => {
+                let field = parser::parse(&buffer.writer).ok().map(|(value, _)| value);
+                let Extension { type_id, value } = extension_from_field(field)?;
+
+                marker::write_extension_header(writer, value.len(), type_id).map_err(io_error)?;
+                writer.write_all(&value).map_err(io_error)
+            }
+        }
+    }
+}
+
+impl <W: Write> ser::SerializeSeq for Compound<'_, W> {
+    type Ok = ();
+    type Error = SerdeError;
+
+    fn serialize_element <T: Serialize + ?Sized> (&mut self, value: &T) -> Result<(), SerdeError> {
+        self.item(value, true)
+    }
+
+    fn end (self) -> Result<(), SerdeError> {
+        self.finish()
+    }
+}
+
+impl <W: Write> ser::SerializeTuple for Compound<'_, W> {
+    type Ok = ();
+    type Error = SerdeError;
+
+    fn serialize_element <T: Serialize + ?Sized> (&mut self, value: &T) -> Result<(), SerdeError> {
+        self.item(value, true)
+    }
+
+    fn end (self) -> Result<(), SerdeError> {
+        self.finish()
+    }
+}
+
+impl <W: Write> ser::SerializeTupleStruct for Compound<'_, W> {
+    type Ok = ();
+    type Error = SerdeError;
+
+    fn serialize_field <T: Serialize + ?Sized> (&mut self, value: &T) -> Result<(), SerdeError> {
+        self.item(value, true)
+    }
+
+    fn end (self) -> Result<(), SerdeError> {
+        self.finish()
+    }
+}
+
+impl <W: Write> ser::SerializeTupleVariant for Compound<'_, W> {
+    type Ok = ();
+    type Error = SerdeError;
+
+    fn serialize_field <T: Serialize + ?Sized> (&mut self, value: &T) -> Result<(), SerdeError> {
+        self.item(value, true)
+    }
+
+    fn end (self) -> Result<(), SerdeError> {
+        self.finish()
+    }
+}
+
+impl <W: Write> ser::SerializeMap for Compound<'_, W> {
+    type Ok = ();
+    type Error = SerdeError;
+
+    fn serialize_key <T: Serialize + ?Sized> (&mut self, key: &T) -> Result<(), SerdeError> {
+        self.item(key, true)
+    }
+
+    fn serialize_value <T: Serialize + ?Sized> (&mut self, value: &T) -> Result<(), SerdeError> {
+        self.item(value, false)
+    }
+
+    fn end (self) -> Result<(), SerdeError> {
+        self.finish()
+    }
+}
+
+impl <W: Write> ser::SerializeStruct for Compound<'_, W> {
+    type Ok = ();
+    type Error = SerdeError;
+
+    fn serialize_field <T: Serialize + ?Sized> (&mut self, key: &'static str, value: &T) -> Result<(), SerdeError> {
+        self.field(key, value)
+    }
+
+    fn end (self) -> Result<(), SerdeError> {
+        self.finish()
+    }
+}
+
+impl <W: Write> ser::SerializeStructVariant for Compound<'_, W> {
+    type Ok = ();
+    type Error = SerdeError;
+
+    fn serialize_field <T: Serialize + ?Sized> (&mut self, key: &'static str, value: &T) -> Result<(), SerdeError> {
+        self.field(key, value)
+    }
+
+    fn end (self) -> Result<(), SerdeError> {
+        self.finish()
+    }
+}
+
+impl <'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
+    type Ok = ();
+    type Error = SerdeError;
+
+    type SerializeSeq = Compound<'a, W>;
+    type SerializeTuple = Compound<'a, W>;
+    type SerializeTupleStruct = Compound<'a, W>;
+    type SerializeTupleVariant = Compound<'a, W>;
+    type SerializeMap = Compound<'a, W>;
+    type SerializeStruct = Compound<'a, W>;
+    type SerializeStructVariant = Compound<'a, W>;
+
+    fn is_human_readable (&self) -> bool {
+        false
+    }
+
+    fn serialize_bool (self, value: bool) -> Result<(), SerdeError> {
+        self.writer.write_all(&[if value { 0xc3 } else { 0xc2 }]).map_err(io_error)
+    }
+
+    fn serialize_i8 (self, value: i8) -> Result<(), SerdeError> {
+        self.serialize_i64(value as i64)
+    }
+
+    fn serialize_i16 (self, value: i16) -> Result<(), SerdeError> {
+        self.serialize_i64(value as i64)
+    }
+
+    fn serialize_i32 (self, value: i32) -> Result<(), SerdeError> {
+        self.serialize_i64(value as i64)
+    }
+
+    fn serialize_i64 (self, value: i64) -> Result<(), SerdeError> {
+        marker::write_int(&mut self.writer, value).map_err(io_error)
+    }
+
+    fn serialize_i128 (self, value: i128) -> Result<(), SerdeError> {
+        self.writer.write_all(&MsgPack::from(value).encode()).map_err(io_error)
+    }
+
+    fn serialize_u8 (self, value: u8) -> Result<(), SerdeError> {
+        self.serialize_u64(value as u64)
+    }
+
+    fn serialize_u16 (self, value: u16) -> Result<(), SerdeError> {
+        self.serialize_u64(value as u64)
+    }
+
+    fn serialize_u32 (self, value: u32) -> Result<(), SerdeError> {
+        self.serialize_u64(value as u64)
+    }
+
+    fn serialize_u64 (self, value: u64) -> Result<(), SerdeError> {
+        marker::write_uint(&mut self.writer, value).map_err(io_error)
+    }
+
+    fn serialize_u128 (self, value: u128) -> Result<(), SerdeError> {
+        self.writer.write_all(&MsgPack::from(value).encode()).map_err(io_error)
+    }
+
+    fn serialize_f32 (self, value: f32) -> Result<(), SerdeError> {
+        self.serialize_f64(value as f64)
+    }
+
+    fn serialize_f64 (self, value: f64) -> Result<(), SerdeError> {
+        marker::write_float(&mut self.writer, value).map_err(io_error)
+    }
+
+    fn serialize_char (self, value: char) -> Result<(), SerdeError> {
+        self.write_str(value.encode_utf8(&mut [0; 4]))
+    }
+
+    fn serialize_str (self, value: &str) -> Result<(), SerdeError> {
+        self.write_str(value)
+    }
+
+    fn serialize_bytes (self, value: &[u8]) -> Result<(), SerdeError> {
+        marker::write_binary_header(&mut self.writer, value.len()).map_err(io_error)?;
+        self.writer.write_all(value).map_err(io_error)
+    }
+
+    fn serialize_none (self) -> Result<(), SerdeError> {
+        self.serialize_unit()
+    }
+
+    fn serialize_some <T: Serialize + ?Sized> (self, value: &T) -> Result<(), SerdeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit (self) -> Result<(), SerdeError> {
+        self.writer.write_all(&[0xc0]).map_err(io_error)
+    }
+
+    fn serialize_unit_struct (self, _name: &'static str) -> Result<(), SerdeError> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant (self, _name: &'static str, _index: u32, variant: &'static str) -> Result<(), SerdeError> {
+        self.write_str(variant)
+    }
+
+    fn serialize_newtype_struct <T: Serialize + ?Sized> (self, _name: &'static str, value: &T) -> Result<(), SerdeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant <T: Serialize + ?Sized> (self, _name: &'static str, _index: u32, variant: &'static str, value: &T) -> Result<(), SerdeError> {
+        self.write_variant(variant)?;
+        value.serialize(self)
+    }
+
+    fn serialize_seq (self, length: Option<usize>) -> Result<Compound<'a, W>, SerdeError> {
+        self.compound(length, false)
+    }
+
+    fn serialize_tuple (self, length: usize) -> Result<Compound<'a, W>, SerdeError> {
+        self.compound(Some(length), false)
+    }
+
+    fn serialize_tuple_struct (self, _name: &'static str, length: usize) -> Result<Compound<'a, W>, SerdeError> {
+        self.compound(Some(length), false)
+    }
+
+    fn serialize_tuple_variant (self, _name: &'static str, _index: u32, variant: &'static str, length: usize) -> Result<Compound<'a, W>, SerdeError> {
+        self.write_variant(variant)?;
+        self.compound(Some(length), false)
+    }
+
+    fn serialize_map (self, length: Option<usize>) -> Result<Compound<'a, W>, SerdeError> {
+        self.compound(length, true)
+    }
+
+    fn serialize_struct (self, name: &'static str, length: usize) -> Result<Compound<'a, W>, SerdeError> {
+        if name == EXTENSION_TOKEN {
+            return Ok(Compound { serializer: self, state: State::Extension(Serializer::new(vec![])) })
+        }
+
+        self.compound(Some(length), true)
+    }
+
+    fn serialize_struct_variant (self, _name: &'static str, _index: u32, variant: &'static str, length: usize) -> Result<Compound<'a, W>, SerdeError> {
+        self.write_variant(variant)?;
+        self.compound(Some(length), true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use serde::Serialize;
+    use super::super::{Extension, MapElement};
+    use super::super::serde_impls::fixtures::Shape;
+
+    struct Unsized(i32);
+
+    impl Serialize for Unsized {
+        fn serialize <S: ser::Serializer> (&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut seq = serializer.serialize_seq(None)?;
+            for value in 0..self.0 {
+                ser::SerializeSeq::serialize_element(&mut seq, &value)?;
+            }
+            ser::SerializeSeq::end(seq)
+        }
+    }
+
+    #[test]
+    fn matches_encode () {
+        let value = msgpack!({
+            "nil": nil,
+            "numbers": [-1, -300, (MsgPack::Uint(7)), 1.5],
+            "text": "Rust",
+            "raw": bin[1, 2, 3],
+            "ext": ext(-1, [0, 0, 0, 1]),
+            (MsgPack::Int(5)): { "nested": true }
+        });
+
+        assert_eq!(to_vec(&value).unwrap(), value.encode());
+        assert_eq!(to_vec(&Extension { type_id: 3, value: vec![0xaa; 20] }).unwrap(), MsgPack::Extension(Extension { type_id: 3, value: vec![0xaa; 20] }).encode());
+
+        let element = MapElement { key: MsgPack::Nil, value: MsgPack::Boolean(true) };
+        assert_eq!(to_vec(&element).unwrap(), msgpack!({ "key": nil, "value": true }).encode());
+    }
+
+    #[test]
+    fn types () {
+        let shapes = vec![Shape::Empty, Shape::Circle(0.5), Shape::Line(1, -1), Shape::Rect { width: 2, height: 3 }];
+        let expected = msgpack!([
+            "Empty",
+            { "Circle": 0.5 },
+            { "Line": [1, -1] },
+            { "Rect": { "width": (MsgPack::Uint(2)), "height": (MsgPack::Uint(3)) } }
+        ]);
+        assert_eq!(to_vec(&shapes).unwrap(), expected.encode());
+
+        let mut map = BTreeMap::new();
+        map.insert("a", vec![Some(1u8), None]);
+        let serialized = to_vec(&(map, (), 'x', u128::MAX)).unwrap();
+        assert_eq!(MsgPack::parse(&serialized).unwrap(), msgpack!([
+            { "a": [(MsgPack::Uint(1)), nil] },
+            nil,
+            "x",
+            (MsgPack::from(u128::MAX))
+        ]));
+
+        // a sequence with the length only known at the end
+        let serialized = to_vec(&Unsized(20)).unwrap();
+        assert_eq!(serialized[..2], [0xdc, 0x00]);
+        assert_eq!(MsgPack::parse(&serialized).unwrap()[19], MsgPack::Int(19));
+
+        let mut written = vec![];
+        to_writer(&mut written, &shapes).unwrap();
+        assert_eq!(written, expected.encode());
+    }
+}
@@ -0,0 +1,343 @@
+//! Serialize and Deserialize for the value types, behind the `serde` feature
+
+use std::convert::TryFrom;
+use std::fmt::{self, Formatter};
+
+use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{self, Serialize, SerializeMap, SerializeSeq, SerializeStruct, Serializer};
+
+use super::{MsgPack, Extension};
+
+/// The name and only field of the struct extensions are serialized as
+pub(crate) const EXTENSION_TOKEN: &str = "$msgpack_simple::Extension";
+
+// extension payloads, serialized with serialize_bytes
+struct Bytes<'a>(&'a [u8]);
+
+impl Serialize for Bytes<'_> {
+    fn serialize <S: Serializer> (&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+// extension payloads, accepted as bytes or, from formats without them, as a
+// sequence of bytes
+struct ByteBuf(Vec<u8>);
+
+impl <'de> Deserialize<'de> for ByteBuf {
+    fn deserialize <D: Deserializer<'de>> (deserializer: D) -> Result<ByteBuf, D::Error> {
+        struct ByteBufVisitor;
+
+        impl <'de> Visitor<'de> for ByteBufVisitor {
+            type Value = ByteBuf;
+
+            fn expecting (&self, f: &mut Formatter) -> fmt::Result {
+                write!(f, "a byte array")
+            }
+
+            fn visit_bytes <E: de::Error> (self, value: &[u8]) -> Result<ByteBuf, E> {
+                Ok(ByteBuf(value.to_vec()))
+            }
+
+            fn visit_byte_buf <E: de::Error> (self, value: Vec<u8>) -> Result<ByteBuf, E> {
+                Ok(ByteBuf(value))
+            }
+
+            fn visit_seq <A: SeqAccess<'de>> (self, mut seq: A) -> Result<ByteBuf, A::Error> {
+                let mut result = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+                while let Some(byte) = seq.next_element()? {
+                    result.push(byte);
+                }
+                Ok(ByteBuf(result))
+            }
+        }
+
+        deserializer.deserialize_byte_buf(ByteBufVisitor)
+    }
+}
+
+/// Serializes the value as the closest serde type
+/// 
+/// Binaries use `serialize_bytes`, maps keep their keys whatever type they
+/// are, and extensions are serialized like an
+/// [Extension](struct.Extension.html) on its own.
+impl Serialize for MsgPack {
+    fn serialize <S: Serializer> (&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            MsgPack::Nil => serializer.serialize_unit(),
+            MsgPack::Boolean(value) => serializer.serialize_bool(*value),
+            MsgPack::Int(value) => serializer.serialize_i64(*value),
+            MsgPack::Uint(value) => serializer.serialize_u64(*value),
+            MsgPack::Float(value) => serializer.serialize_f64(*value),
+            MsgPack::String(value) => serializer.serialize_str(value),
+            MsgPack::Binary(value) => serializer.serialize_bytes(value),
+            MsgPack::Array(value) => {
+                let mut seq = serializer.serialize_seq(Some(value.len()))?;
+                for item in value {
+                    seq.serialize_element(item)?;
+                }
+                seq.end()
+            },
+            MsgPack::Map(value) => {
+                let mut map = serializer.serialize_map(Some(value.len()))?;
+                for element in value {
+                    map.serialize_entry(&element.key, &element.value)?;
+                }
+                map.end()
+            },
+            MsgPack::Extension(value) => value.serialize(serializer)
+        }
+    }
+}
+
+struct MsgPackVisitor;
+
+impl <'de> Visitor<'de> for MsgPackVisitor {
+    type Value = MsgPack;
+
+    fn expecting (&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "any MsgPack value")
+    }
+
+    fn visit_unit <E: de::Error> (self) -> Result<MsgPack, E> {
+        Ok(MsgPack::Nil)
+    }
+
+    fn visit_none <E: de::Error> (self) -> Result<MsgPack, E> {
+        Ok(MsgPack::Nil)
+    }
+
+    fn visit_some <D: Deserializer<'de>> (self, deserializer: D) -> Result<MsgPack, D::Error> {
+        MsgPack::deserialize(deserializer)
+    }
+
+    fn visit_newtype_struct <D: Deserializer<'de>> (self, deserializer: D) -> Result<MsgPack, D::Error> {
+        MsgPack::deserialize(deserializer)
+    }
+
+    fn visit_bool <E: de::Error> (self, value: bool) -> Result<MsgPack, E> {
+        Ok(MsgPack::Boolean(value))
+    }
+
+    fn visit_i64 <E: de::Error> (self, value: i64) -> Result<MsgPack, E> {
+        Ok(MsgPack::Int(value))
+    }
+
+    fn visit_u64 <E: de::Error> (self, value: u64) -> Result<MsgPack, E> {
+        Ok(MsgPack::Uint(value))
+    }
+
+    fn visit_i128 <E: de::Error> (self, value: i128) -> Result<MsgPack, E> {
+        Ok(MsgPack::from(value))
+    }
+
+    fn visit_u128 <E: de::Error> (self, value: u128) -> Result<MsgPack, E> {
+        Ok(MsgPack::from(value))
+    }
+
+    fn visit_f64 <E: de::Error> (self, value: f64) -> Result<MsgPack, E> {
+        Ok(MsgPack::Float(value))
+    }
+
+    fn visit_str <E: de::Error> (self, value: &str) -> Result<MsgPack, E> {
+        Ok(MsgPack::String(value.to_string()))
+    }
+
+    fn visit_string <E: de::Error> (self, value: String) -> Result<MsgPack, E> {
+        Ok(MsgPack::String(value))
+    }
+
+    fn visit_bytes <E: de::Error> (self, value: &[u8]) -> Result<MsgPack, E> {
+        Ok(MsgPack::Binary(value.to_vec()))
+    }
+
+    fn visit_byte_buf <E: de::Error> (self, value: Vec<u8>) -> Result<MsgPack, E> {
+        Ok(MsgPack::Binary(value))
+    }
+
+    fn visit_seq <A: SeqAccess<'de>> (self, mut seq: A) -> Result<MsgPack, A::Error> {
+        let mut result = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(item) = seq.next_element()? {
+            result.push(item);
+        }
+        Ok(MsgPack::Array(result))
+    }
+
+    fn visit_map <A: MapAccess<'de>> (self, mut map: A) -> Result<MsgPack, A::Error> {
+        let mut result = Vec::with_capacity(map.size_hint().unwrap_or(0));
+
+        while let Some(key) = map.next_key::<MsgPack>()? {
+            // the token is only used by extensions, and only as the first key
+            let is_token = matches!(&key, MsgPack::String(key) if key == EXTENSION_TOKEN);
+            if result.is_empty() && is_token {
+                let (type_id, ByteBuf(value)) = map.next_value()?;
+                if map.next_key::<MsgPack>()?.is_some() {
+                    return Err(de::Error::custom("MsgPack extension with extra fields"))
+                }
+                return Ok(MsgPack::Extension(Extension { type_id, value }))
+            }
+
+            let value = map.next_value()?;
+            result.push(super::MapElement { key, value });
+        }
+
+        Ok(MsgPack::Map(result))
+    }
+}
+
+/// Deserializes any value from a self-describing format
+/// 
+/// Integers become ints or uints depending on what the format reports, so a
+/// positive `Int` may come back as a `Uint`. 128-bit integers that don't fit
+/// in 64 bits use the [Int128](struct.Int128.html) convention, and maps
+/// serialized from an extension turn back into one.
+impl <'de> Deserialize<'de> for MsgPack {
+    fn deserialize <D: Deserializer<'de>> (deserializer: D) -> Result<MsgPack, D::Error> {
+        deserializer.deserialize_any(MsgPackVisitor)
+    }
+}
+
+/// Serializes the extension as a struct with a single field, both named
+/// `$msgpack_simple::Extension`, holding a tuple of the type id and the data
+/// as bytes
+/// 
+/// In JSON, for example, this is `{"$msgpack_simple::Extension":[5,[1,2]]}`.
+/// The unusual name keeps it apart from ordinary maps, so deserializing a
+/// [MsgPack](enum.MsgPack.html) turns it back into an extension.
+impl Serialize for Extension {
+    fn serialize <S: Serializer> (&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct(EXTENSION_TOKEN, 1)?;
+        state.serialize_field(EXTENSION_TOKEN, &(self.type_id, Bytes(&self.value)))?;
+        state.end()
+    }
+}
+
+/// Reassembles an extension from the field it is serialized as, for
+/// serializers that catch the `EXTENSION_TOKEN` struct
+pub(crate) fn extension_from_field <E: ser::Error> (field: Option<MsgPack>) -> Result<Extension, E> {
+    // the field is a tuple of the type id and the data
+    match field {
+        Some(MsgPack::Array(mut items)) if items.len() == 2 => match (i8::try_from(&items[0]), items.pop()) {
+            (Ok(type_id), Some(MsgPack::Binary(value))) => Ok(Extension { type_id, value }),
+            _ => Err(E::custom("invalid MsgPack extension"))
+        },
+        _ => Err(E::custom("invalid MsgPack extension"))
+    }
+}
+
+struct ExtensionVisitor;
+
+impl <'de> Visitor<'de> for ExtensionVisitor {
+    type Value = Extension;
+
+    fn expecting (&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "a MsgPack extension")
+    }
+
+    fn visit_seq <A: SeqAccess<'de>> (self, mut seq: A) -> Result<Extension, A::Error> {
+        let (type_id, ByteBuf(value)) = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        Ok(Extension { type_id, value })
+    }
+
+    fn visit_map <A: MapAccess<'de>> (self, mut map: A) -> Result<Extension, A::Error> {
+        match map.next_key::<String>()? {
+            Some(ref key) if key == EXTENSION_TOKEN => {},
+            Some(key) => return Err(de::Error::unknown_field(&key, &[EXTENSION_TOKEN])),
+            None => return Err(de::Error::missing_field(EXTENSION_TOKEN))
+        }
+
+        let (type_id, ByteBuf(value)) = map.next_value()?;
+        Ok(Extension { type_id, value })
+    }
+}
+
+impl <'de> Deserialize<'de> for Extension {
+    fn deserialize <D: Deserializer<'de>> (deserializer: D) -> Result<Extension, D::Error> {
+        deserializer.deserialize_struct(EXTENSION_TOKEN, &[EXTENSION_TOKEN], ExtensionVisitor)
+    }
+}
+
+// types exercising every shape of serde data, shared by the tests of the
+// serializers and deserializers
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use serde::{Deserialize, Serialize};
+    use super::super::Extension;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    pub enum Shape {
+        Empty,
+        Circle(f64),
+        Line(i8, i8),
+        Rect { width: u8, height: u8 },
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    pub struct Drawing {
+        pub name: String,
+        pub shapes: Vec<Shape>,
+        pub scale: Option<f32>,
+        pub tag: Option<u128>,
+        pub raw: Extension,
+    }
+
+    pub fn drawing () -> Drawing {
+        Drawing {
+            name: "plan".to_string(),
+            shapes: vec![Shape::Empty, Shape::Circle(0.5), Shape::Line(1, -1), Shape::Rect { width: 2, height: 3 }],
+            scale: None,
+            tag: Some(u128::MAX),
+            raw: Extension { type_id: -3, value: vec![1, 2, 3] },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::MapElement;
+
+    #[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+    struct Envelope {
+        id: u32,
+        payload: MsgPack,
+    }
+
+    #[test]
+    fn json () {
+        let value = msgpack!({
+            "name": "Rust",
+            "tags": [-1, 2.5, nil, true],
+            "raw": bin[1, 2],
+            "ext": ext(5, [0xff])
+        });
+
+        let json = serde_json::to_string(&value).unwrap();
+        assert_eq!(json, r#"{"name":"Rust","tags":[-1,2.5,null,true],"raw":[1,2],"ext":{"$msgpack_simple::Extension":[5,[255]]}}"#);
+
+        let decoded: MsgPack = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded["ext"], value["ext"]);
+        assert_eq!(decoded["tags"], value["tags"]);
+
+        // JSON has no bytes, so binaries come back as arrays
+        assert_eq!(decoded["raw"], MsgPack::Array(vec![MsgPack::Uint(1), MsgPack::Uint(2)]));
+
+        let extension: Extension = serde_json::from_str(r#"{"$msgpack_simple::Extension":[-1,[0,0,0,1]]}"#).unwrap();
+        assert_eq!(extension, Extension { type_id: -1, value: vec![0, 0, 0, 1] });
+        assert!(serde_json::from_str::<MsgPack>(r#"{"$msgpack_simple::Extension":[1,[]],"a":1}"#).is_err());
+        assert!(serde_json::from_str::<Extension>(r#"{"type":1}"#).is_err());
+    }
+
+    #[test]
+    fn embedded () {
+        let envelope = Envelope { id: 7, payload: msgpack!({ "deep": [1, "two"] }) };
+        let json = serde_json::to_string(&envelope).unwrap();
+        assert_eq!(json, r#"{"id":7,"payload":{"deep":[1,"two"]}}"#);
+        assert_eq!(serde_json::from_str::<Envelope>(&json).unwrap().payload["deep"][1], MsgPack::String("two".to_string()));
+
+        let element = MapElement { key: MsgPack::String("a".to_string()), value: MsgPack::Boolean(false) };
+        let json = serde_json::to_string(&element).unwrap();
+        assert_eq!(json, r#"{"key":"a","value":false}"#);
+        assert_eq!(serde_json::from_str::<MapElement>(&json).unwrap(), element);
+    }
+}
@@ -0,0 +1,259 @@
+//! The timestamp extension type defined by the MessagePack spec
+
+use std::convert::TryFrom;
+use std::fmt::{self, Display, Formatter};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use byteorder::{BigEndian, ByteOrder};
+
+use super::{MsgPack, Extension};
+use super::error::ConversionError;
+
+const NANOS_PER_SECOND: u32 = 1_000_000_000;
+
+/// A point in time, stored as extension type -1
+/// 
+/// Counts seconds and nanoseconds since the Unix epoch, 1970-01-01 00:00:00
+/// UTC, ignoring leap seconds. The nanoseconds are always less than a second,
+/// and times before the epoch have negative seconds, so half a second before
+/// the epoch is -1 seconds and 500000000 nanoseconds.
+/// 
+/// Timestamps are encoded in the smallest of the three layouts the spec
+/// defines: 32 bits for whole seconds between 1970 and 2106, 64 bits for
+/// anything with nanoseconds until 2514, and 96 bits for the rest. All three
+/// are decoded.
+/// 
+///     use std::convert::TryFrom;
+///     use msgpack_simple::{MsgPack, Timestamp};
+/// 
+///     let timestamp = Timestamp::new(1_500_000_000, 250_000_000).unwrap();
+///     assert_eq!(timestamp.to_string(), "2017-07-14T02:40:00.250Z");
+/// 
+///     let encoded = MsgPack::from(timestamp).encode();
+///     assert_eq!(encoded.len(), 10);
+/// 
+///     let decoded = Timestamp::try_from(MsgPack::parse(&encoded).unwrap()).unwrap();
+///     assert_eq!(decoded, timestamp);
+/// 
+///     assert_eq!(Timestamp::new(0, 1_000_000_000), None);
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp {
+    seconds: i64,
+    nanos: u32,
+}
+
+impl Timestamp {
+    /// The extension type id of timestamps
+    pub const TYPE_ID: i8 = -1;
+
+    /// Creates a timestamp, returning `None` if the nanoseconds are a second
+    /// or more
+    pub fn new (seconds: i64, nanos: u32) -> Option<Timestamp> {
+        if nanos >= NANOS_PER_SECOND { return None }
+        Some(Timestamp { seconds, nanos })
+    }
+
+    /// Seconds since the Unix epoch
+    pub fn seconds (&self) -> i64 {
+        self.seconds
+    }
+
+    /// Nanoseconds on top of the seconds, always less than a second
+    pub fn nanos (&self) -> u32 {
+        self.nanos
+    }
+
+    /// Converts the timestamp to a `SystemTime`, returning `None` if it's out
+    /// of the range the platform supports
+    /// 
+    ///     use std::time::{Duration, UNIX_EPOCH};
+    ///     use msgpack_simple::Timestamp;
+    /// 
+    ///     let timestamp = Timestamp::new(-1, 500_000_000).unwrap();
+    ///     assert_eq!(timestamp.to_system_time(), UNIX_EPOCH.checked_sub(Duration::from_millis(500)));
+    pub fn to_system_time (&self) -> Option<SystemTime> {
+        let nanos = Duration::from_nanos(self.nanos as u64);
+
+        if self.seconds >= 0 {
+            UNIX_EPOCH.checked_add(Duration::from_secs(self.seconds as u64))?.checked_add(nanos)
+        } else {
+            UNIX_EPOCH.checked_sub(Duration::from_secs(self.seconds.unsigned_abs()))?.checked_add(nanos)
+        }
+    }
+}
+
+impl From<SystemTime> for Timestamp {
+    fn from (time: SystemTime) -> Timestamp {
+        match time.duration_since(UNIX_EPOCH) {
+            Ok(duration) => Timestamp { seconds: duration.as_secs() as i64, nanos: duration.subsec_nanos() },
+            Err(error) => {
+                // before the epoch, so the nanoseconds count back from the
+                // next whole second
+                let duration = error.duration();
+                let seconds = -(duration.as_secs() as i64);
+
+                match duration.subsec_nanos() {
+                    0 => Timestamp { seconds, nanos: 0 },
+                    nanos => Timestamp { seconds: seconds - 1, nanos: NANOS_PER_SECOND - nanos }
+                }
+            }
+        }
+    }
+}
+
+impl From<Timestamp> for Extension {
+    fn from (timestamp: Timestamp) -> Extension {
+        let Timestamp { seconds, nanos } = timestamp;
+
+        let value = if nanos == 0 && (0..=u32::MAX as i64).contains(&seconds) {
+            let mut value = vec![0; 4];
+            BigEndian::write_u32(&mut value, seconds as u32);
+            value
+        } else if (0..1 << 34).contains(&seconds) {
+            let mut value = vec![0; 8];
+            BigEndian::write_u64(&mut value, (nanos as u64) << 34 | seconds as u64);
+            value
+        } else {
+            let mut value = vec![0; 12];
+            BigEndian::write_u32(&mut value[..4], nanos);
+            BigEndian::write_i64(&mut value[4..], seconds);
+            value
+        };
+
+        Extension { type_id: Timestamp::TYPE_ID, value }
+    }
+}
+
+impl From<Timestamp> for MsgPack {
+    fn from (timestamp: Timestamp) -> MsgPack {
+        MsgPack::Extension(timestamp.into())
+    }
+}
+
+impl TryFrom<Extension> for Timestamp {
+    type Error = ConversionError;
+
+    fn try_from (extension: Extension) -> Result<Timestamp, ConversionError> {
+        let value = &extension.value;
+
+        let timestamp = match value.len() {
+            _ if extension.type_id != Timestamp::TYPE_ID => None,
+            4 => Timestamp::new(BigEndian::read_u32(value) as i64, 0),
+            8 => {
+                let data = BigEndian::read_u64(value);
+                Timestamp::new((data & 0x3_ffff_ffff) as i64, (data >> 34) as u32)
+            },
+            12 => Timestamp::new(BigEndian::read_i64(&value[4..]), BigEndian::read_u32(&value[..4])),
+            _ => None
+        };
+
+        timestamp.ok_or(ConversionError::type_error(MsgPack::Extension(extension), "timestamp"))
+    }
+}
+
+impl TryFrom<MsgPack> for Timestamp {
+    type Error = ConversionError;
+
+    fn try_from (value: MsgPack) -> Result<Timestamp, ConversionError> {
+        match value {
+            MsgPack::Extension(extension) => Timestamp::try_from(extension),
+            original => Err(ConversionError::type_error(original, "timestamp"))
+        }
+    }
+}
+
+// converts days since the epoch to a date in the proleptic Gregorian
+// calendar, from Howard Hinnant's civil_from_days
+fn civil_from_days (days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+/// Formats the timestamp as an RFC 3339 date and time in UTC
+/// 
+/// Fractions of a second are written with 3, 6 or 9 digits, whichever is
+/// enough, and left out for whole seconds. Years outside of 0000 to 9999 are
+/// written with as many digits as needed, which RFC 3339 itself doesn't
+/// allow.
+impl Display for Timestamp {
+    fn fmt (&self, f: &mut Formatter) -> fmt::Result {
+        let days = self.seconds.div_euclid(86_400);
+        let time = self.seconds.rem_euclid(86_400);
+        let (year, month, day) = civil_from_days(days);
+
+        write!(f, "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}", year, month, day, time / 3600, time / 60 % 60, time % 60)?;
+
+        match self.nanos {
+            0 => {},
+            nanos if nanos % 1_000_000 == 0 => write!(f, ".{:03}", nanos / 1_000_000)?,
+            nanos if nanos % 1_000 == 0 => write!(f, ".{:06}", nanos / 1_000)?,
+            nanos => write!(f, ".{:09}", nanos)?,
+        }
+
+        write!(f, "Z")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip (timestamp: Timestamp, length: usize) {
+        let extension = Extension::from(timestamp);
+        assert_eq!(extension.value.len(), length);
+        assert_eq!(Timestamp::try_from(extension).unwrap(), timestamp);
+    }
+
+    #[test]
+    fn layouts () {
+        round_trip(Timestamp::new(0, 0).unwrap(), 4);
+        round_trip(Timestamp::new(u32::MAX as i64, 0).unwrap(), 4);
+        round_trip(Timestamp::new(0, 1).unwrap(), 8);
+        round_trip(Timestamp::new(u32::MAX as i64 + 1, 0).unwrap(), 8);
+        round_trip(Timestamp::new((1 << 34) - 1, 999_999_999).unwrap(), 8);
+        round_trip(Timestamp::new(1 << 34, 0).unwrap(), 12);
+        round_trip(Timestamp::new(-1, 999_999_999).unwrap(), 12);
+        round_trip(Timestamp::new(i64::MIN, 0).unwrap(), 12);
+
+        assert_eq!(Extension::from(Timestamp::new(1, 0).unwrap()).value, vec![0, 0, 0, 1]);
+        assert_eq!(Extension::from(Timestamp::new(1, 1).unwrap()).value, vec![0, 0, 0, 0x04, 0, 0, 0, 1]);
+        assert_eq!(Extension::from(Timestamp::new(-1, 1).unwrap()).value, vec![0, 0, 0, 1, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+
+        // nanoseconds out of range, wrong length and wrong type
+        assert!(Timestamp::try_from(Extension { type_id: -1, value: vec![0xee, 0x6b, 0x28, 0x00, 0, 0, 0, 0] }).is_err());
+        assert!(Timestamp::try_from(Extension { type_id: -1, value: vec![0; 5] }).is_err());
+        assert!(Timestamp::try_from(Extension { type_id: 1, value: vec![0; 4] }).is_err());
+        assert!(Timestamp::try_from(MsgPack::Int(0)).is_err());
+    }
+
+    #[test]
+    fn system_time () {
+        let before = UNIX_EPOCH - Duration::new(2, 250_000_000);
+        let timestamp = Timestamp::from(before);
+
+        assert_eq!(timestamp, Timestamp::new(-3, 750_000_000).unwrap());
+        assert_eq!(timestamp.to_system_time(), Some(before));
+        assert_eq!(Timestamp::from(UNIX_EPOCH - Duration::from_secs(2)), Timestamp::new(-2, 0).unwrap());
+
+        let now = SystemTime::now();
+        assert_eq!(Timestamp::from(now).to_system_time(), Some(now));
+    }
+
+    #[test]
+    fn display () {
+        assert_eq!(Timestamp::new(0, 0).unwrap().to_string(), "1970-01-01T00:00:00Z");
+        assert_eq!(Timestamp::new(-1, 0).unwrap().to_string(), "1969-12-31T23:59:59Z");
+        assert_eq!(Timestamp::new(951_782_400, 1_000).unwrap().to_string(), "2000-02-29T00:00:00.000001Z");
+        assert_eq!(Timestamp::new(253_402_300_799, 1).unwrap().to_string(), "9999-12-31T23:59:59.000000001Z");
+        assert_eq!(Timestamp::new(-62_135_596_800, 0).unwrap().to_string(), "0001-01-01T00:00:00Z");
+    }
+}
@@ -0,0 +1,434 @@
+//! The ToMsgPack and FromMsgPack traits and their implementations for std
+//! types
+
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::hash::{BuildHasher, Hash};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use super::{MsgPack, MapElement};
+use super::error::{ConversionError, ConversionKind, FromMsgPackError};
+use super::path::PathSegment;
+use super::timestamp::Timestamp;
+
+/// Types that can be converted to MsgPack by reference
+/// 
+/// Unlike `Into<MsgPack>`, this doesn't consume the value, and it's
+/// implemented for references, smart pointers, and collections of any type
+/// that implements it. Structs are usually converted to maps keyed by their
+/// field names.
+/// 
+///     use msgpack_simple::{msgpack, MsgPack, ToMsgPack};
+/// 
+///     struct Point { x: i32, y: i32 }
+/// 
+///     impl ToMsgPack for Point {
+///         fn to_msgpack (&self) -> MsgPack {
+///             msgpack!({ "x": (self.x.to_msgpack()), "y": (self.y.to_msgpack()) })
+///         }
+///     }
+/// 
+///     let points = vec![Point { x: 1, y: 2 }];
+///     assert_eq!(points.to_msgpack(), msgpack!([{ "x": 1, "y": 2 }]));
+pub trait ToMsgPack {
+    /// Converts the value to MsgPack
+    fn to_msgpack (&self) -> MsgPack;
+}
+
+/// Types that can be converted from MsgPack, with errors that point to the
+/// part of the value that didn't fit
+/// 
+/// Implementations for containers convert each of their children, and use
+/// [FromMsgPackError.within()](struct.FromMsgPackError.html#method.within)
+/// to add the location of the child to its errors.
+/// 
+///     use msgpack_simple::{msgpack, MsgPack, FromMsgPack, FromMsgPackError, PathSegment};
+/// 
+///     #[derive(Debug, PartialEq)]
+///     struct Point { x: i32, y: i32 }
+/// 
+///     impl FromMsgPack for Point {
+///         fn from_msgpack (value: MsgPack) -> Result<Point, FromMsgPackError> {
+///             let field = |name: &str| {
+///                 let value = value.get(name).cloned().unwrap_or(MsgPack::Nil);
+///                 i32::from_msgpack(value).map_err(|error| error.within(PathSegment::Key(name.into())))
+///             };
+/// 
+///             Ok(Point { x: field("x")?, y: field("y")? })
+///         }
+///     }
+/// 
+///     assert_eq!(Point::from_msgpack(msgpack!({ "x": 1, "y": -2 })).unwrap(), Point { x: 1, y: -2 });
+/// 
+///     let error = Vec::<Point>::from_msgpack(msgpack!([{ "x": 1, "y": "2" }])).unwrap_err();
+///     assert_eq!(error.path.to_string(), "/0/y");
+pub trait FromMsgPack: Sized {
+    /// Converts the value from MsgPack
+    fn from_msgpack (value: MsgPack) -> Result<Self, FromMsgPackError>;
+}
+
+fn error (original: MsgPack, attempted: &'static str) -> FromMsgPackError {
+    ConversionError::type_error(original, attempted).into()
+}
+
+macro_rules! impl_primitive {
+    ($($target:ty),*) => {
+        $(
+            impl ToMsgPack for $target {
+                fn to_msgpack (&self) -> MsgPack {
+                    MsgPack::from(*self)
+                }
+            }
+
+            impl FromMsgPack for $target {
+                fn from_msgpack (value: MsgPack) -> Result<$target, FromMsgPackError> {
+                    Ok(<$target>::try_from(value)?)
+                }
+            }
+        )*
+    };
+}
+
+impl_primitive!(i8, i16, i32, i64, i128, u8, u16, u32, u64, u128, usize, f32, f64, bool);
+
+impl ToMsgPack for isize {
+    fn to_msgpack (&self) -> MsgPack {
+        MsgPack::from(*self)
+    }
+}
+
+impl FromMsgPack for isize {
+    fn from_msgpack (value: MsgPack) -> Result<isize, FromMsgPackError> {
+        // isize is at most 64 bits wide on every supported platform
+        let result = i64::try_from(&value).map_err(|error| error.kind)
+            .and_then(|inner| isize::try_from(inner).map_err(|_| ConversionKind::Range));
+        result.map_err(|kind| ConversionError { original: value, attempted: "isize", kind }.into())
+    }
+}
+
+impl ToMsgPack for char {
+    fn to_msgpack (&self) -> MsgPack {
+        MsgPack::String(self.to_string())
+    }
+}
+
+impl FromMsgPack for char {
+    fn from_msgpack (value: MsgPack) -> Result<char, FromMsgPackError> {
+        if let MsgPack::String(text) = &value {
+            let mut chars = text.chars();
+            if let (Some(result), None) = (chars.next(), chars.next()) { return Ok(result) }
+        }
+
+        Err(error(value, "char"))
+    }
+}
+
+impl ToMsgPack for str {
+    fn to_msgpack (&self) -> MsgPack {
+        MsgPack::String(self.to_string())
+    }
+}
+
+impl ToMsgPack for String {
+    fn to_msgpack (&self) -> MsgPack {
+        MsgPack::String(self.clone())
+    }
+}
+
+impl FromMsgPack for String {
+    fn from_msgpack (value: MsgPack) -> Result<String, FromMsgPackError> {
+        Ok(value.as_string()?)
+    }
+}
+
+/// The value itself, for dynamic parts of typed values
+impl ToMsgPack for MsgPack {
+    fn to_msgpack (&self) -> MsgPack {
+        self.clone()
+    }
+}
+
+impl FromMsgPack for MsgPack {
+    fn from_msgpack (value: MsgPack) -> Result<MsgPack, FromMsgPackError> {
+        Ok(value)
+    }
+}
+
+impl <T: ToMsgPack + ?Sized> ToMsgPack for &T {
+    fn to_msgpack (&self) -> MsgPack {
+        (**self).to_msgpack()
+    }
+}
+
+macro_rules! impl_pointer {
+    ($($pointer:ident),*) => {
+        $(
+            impl <T: ToMsgPack + ?Sized> ToMsgPack for $pointer<T> {
+                fn to_msgpack (&self) -> MsgPack {
+                    (**self).to_msgpack()
+                }
+            }
+
+            impl <T: FromMsgPack> FromMsgPack for $pointer<T> {
+                fn from_msgpack (value: MsgPack) -> Result<$pointer<T>, FromMsgPackError> {
+                    T::from_msgpack(value).map($pointer::new)
+                }
+            }
+        )*
+    };
+}
+
+impl_pointer!(Box, Rc, Arc);
+
+/// `None` is nil, `Some` is the value itself
+impl <T: ToMsgPack> ToMsgPack for Option<T> {
+    fn to_msgpack (&self) -> MsgPack {
+        match self {
+            Some(value) => value.to_msgpack(),
+            None => MsgPack::Nil
+        }
+    }
+}
+
+impl <T: FromMsgPack> FromMsgPack for Option<T> {
+    fn from_msgpack (value: MsgPack) -> Result<Option<T>, FromMsgPackError> {
+        match value {
+            MsgPack::Nil => Ok(None),
+            value => T::from_msgpack(value).map(Some)
+        }
+    }
+}
+
+// converts the items of an array, adding the index of the item to errors
+fn from_items <T: FromMsgPack> (items: Vec<MsgPack>) -> Result<Vec<T>, FromMsgPackError> {
+    items.into_iter()
+        .enumerate()
+        .map(|(index, item)| T::from_msgpack(item).map_err(|error| error.within(PathSegment::Index(index))))
+        .collect()
+}
+
+impl <T: ToMsgPack> ToMsgPack for [T] {
+    fn to_msgpack (&self) -> MsgPack {
+        MsgPack::Array(self.iter().map(ToMsgPack::to_msgpack).collect())
+    }
+}
+
+impl <T: ToMsgPack> ToMsgPack for Vec<T> {
+    fn to_msgpack (&self) -> MsgPack {
+        self.as_slice().to_msgpack()
+    }
+}
+
+impl <T: FromMsgPack> FromMsgPack for Vec<T> {
+    fn from_msgpack (value: MsgPack) -> Result<Vec<T>, FromMsgPackError> {
+        from_items(value.as_array()?)
+    }
+}
+
+impl <T: ToMsgPack, const N: usize> ToMsgPack for [T; N] {
+    fn to_msgpack (&self) -> MsgPack {
+        self[..].to_msgpack()
+    }
+}
+
+/// Arrays have to have exactly `N` items
+impl <T: FromMsgPack, const N: usize> FromMsgPack for [T; N] {
+    fn from_msgpack (value: MsgPack) -> Result<[T; N], FromMsgPackError> {
+        match value {
+            MsgPack::Array(items) if items.len() == N => {
+                let items = from_items(items)?;
+                Ok(<[T; N]>::try_from(items).unwrap_or_else(|_| unreachable!()))
+            },
+            original => Err(error(original, "fixed size array"))
+        }
+    }
+}
+
+macro_rules! impl_tuple {
+    ($($length:expr => ($($name:ident $index:tt),+))*) => {
+        $(
+            impl <$($name: ToMsgPack),+> ToMsgPack for ($($name,)+) {
+                fn to_msgpack (&self) -> MsgPack {
+                    MsgPack::Array(vec![$(self.$index.to_msgpack()),+])
+                }
+            }
+
+            /// Tuples are arrays with exactly as many items
+            impl <$($name: FromMsgPack),+> FromMsgPack for ($($name,)+) {
+                #[allow(non_snake_case)]
+                fn from_msgpack (value: MsgPack) -> Result<($($name,)+), FromMsgPackError> {
+                    let items = match value {
+                        MsgPack::Array(items) if items.len() == $length => items,
+                        original => return Err(error(original, "tuple"))
+                    };
+
+                    let mut items = items.into_iter();
+                    $(
+                        let $name = $name::from_msgpack(items.next().unwrap_or(MsgPack::Nil))
+                            .map_err(|error| error.within(PathSegment::Index($index)))?;
+                    )+
+
+                    Ok(($($name,)+))
+                }
+            }
+        )*
+    };
+}
+
+impl_tuple! {
+    1 => (A 0)
+    2 => (A 0, B 1)
+    3 => (A 0, B 1, C 2)
+    4 => (A 0, B 1, C 2, D 3)
+    5 => (A 0, B 1, C 2, D 3, E 4)
+    6 => (A 0, B 1, C 2, D 3, E 4, F 5)
+    7 => (A 0, B 1, C 2, D 3, E 4, F 5, G 6)
+    8 => (A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7)
+}
+
+// converts the entries of a map, adding the key of the entry to errors
+fn from_entries <K: FromMsgPack, V: FromMsgPack> (value: MsgPack) -> impl Iterator<Item = Result<(K, V), FromMsgPackError>> {
+    let elements = value.as_map().map_err(FromMsgPackError::from);
+
+    let (elements, failed) = match elements {
+        Ok(elements) => (elements, None),
+        Err(error) => (vec![], Some(Err(error)))
+    };
+
+    failed.into_iter().chain(elements.into_iter().map(|MapElement { key, value }| {
+        let within = |error: FromMsgPackError| error.within(PathSegment::Key(key.clone()));
+        let converted_key = K::from_msgpack(key.clone()).map_err(within)?;
+        let converted_value = V::from_msgpack(value).map_err(within)?;
+
+        Ok((converted_key, converted_value))
+    }))
+}
+
+impl <K: ToMsgPack, V: ToMsgPack, S> ToMsgPack for HashMap<K, V, S> {
+    fn to_msgpack (&self) -> MsgPack {
+        MsgPack::Map(self.iter().map(|(key, value)| MapElement { key: key.to_msgpack(), value: value.to_msgpack() }).collect())
+    }
+}
+
+impl <K: FromMsgPack + Eq + Hash, V: FromMsgPack, S: BuildHasher + Default> FromMsgPack for HashMap<K, V, S> {
+    fn from_msgpack (value: MsgPack) -> Result<HashMap<K, V, S>, FromMsgPackError> {
+        from_entries(value).collect()
+    }
+}
+
+impl <K: ToMsgPack, V: ToMsgPack> ToMsgPack for BTreeMap<K, V> {
+    fn to_msgpack (&self) -> MsgPack {
+        MsgPack::Map(self.iter().map(|(key, value)| MapElement { key: key.to_msgpack(), value: value.to_msgpack() }).collect())
+    }
+}
+
+impl <K: FromMsgPack + Ord, V: FromMsgPack> FromMsgPack for BTreeMap<K, V> {
+    fn from_msgpack (value: MsgPack) -> Result<BTreeMap<K, V>, FromMsgPackError> {
+        from_entries(value).collect()
+    }
+}
+
+/// A map with the whole seconds and the nanoseconds, `{"secs": 1, "nanos": 0}`,
+/// as serde writes durations
+impl ToMsgPack for Duration {
+    fn to_msgpack (&self) -> MsgPack {
+        MsgPack::Map(vec![
+            MapElement { key: MsgPack::from("secs"), value: MsgPack::from(self.as_secs()) },
+            MapElement { key: MsgPack::from("nanos"), value: MsgPack::from(self.subsec_nanos()) },
+        ])
+    }
+}
+
+/// Also accepts an array of the seconds and the nanoseconds
+impl FromMsgPack for Duration {
+    fn from_msgpack (value: MsgPack) -> Result<Duration, FromMsgPackError> {
+        let parts = match &value {
+            MsgPack::Map(_) => value.get("secs").zip(value.get("nanos")),
+            MsgPack::Array(items) if items.len() == 2 => Some((&items[0], &items[1])),
+            _ => None
+        };
+
+        let parts = parts.and_then(|(secs, nanos)| Some((u64::try_from(secs).ok()?, u32::try_from(nanos).ok()?)));
+        match parts {
+            Some((secs, nanos)) if nanos < 1_000_000_000 => Ok(Duration::new(secs, nanos)),
+            _ => Err(error(value, "duration"))
+        }
+    }
+}
+
+/// A [Timestamp](struct.Timestamp.html) extension
+impl ToMsgPack for SystemTime {
+    fn to_msgpack (&self) -> MsgPack {
+        MsgPack::from(Timestamp::from(*self))
+    }
+}
+
+impl FromMsgPack for SystemTime {
+    fn from_msgpack (value: MsgPack) -> Result<SystemTime, FromMsgPackError> {
+        let timestamp = Timestamp::try_from(value)?;
+        timestamp.to_system_time().ok_or_else(|| error(timestamp.into(), "system time"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+    use super::super::Path;
+
+    fn round_trip <T: ToMsgPack + FromMsgPack + PartialEq + std::fmt::Debug> (value: T) {
+        assert_eq!(T::from_msgpack(value.to_msgpack()).unwrap(), value);
+    }
+
+    #[test]
+    fn std_types () {
+        round_trip(-5i8);
+        round_trip(u128::MAX);
+        round_trip(isize::MIN);
+        round_trip('λ');
+        round_trip("text".to_string());
+        round_trip(vec![Some(1.5f64), None]);
+        round_trip([[1u8, 2], [3, 4]]);
+        round_trip((1u8, "a".to_string(), Box::new(false), Rc::new(-1i32), Arc::new(0.5f32)));
+        round_trip(Duration::new(5, 250));
+        round_trip(UNIX_EPOCH + Duration::new(1_500_000_000, 1));
+
+        let mut map = HashMap::new();
+        map.insert(1u8, vec!["one".to_string()]);
+        round_trip(map);
+
+        let mut map = BTreeMap::new();
+        map.insert("b".to_string(), msgpack!({ "nested": [nil] }));
+        map.insert("a".to_string(), MsgPack::Nil);
+        assert_eq!(map.to_msgpack(), msgpack!({ "a": nil, "b": { "nested": [nil] } }));
+        round_trip(map);
+
+        assert_eq!(5u16.to_msgpack(), MsgPack::Uint(5));
+        assert_eq!("x".to_msgpack(), MsgPack::String("x".to_string()));
+        assert_eq!(Duration::from_millis(1500).to_msgpack(), msgpack!({ "secs": (MsgPack::Uint(1)), "nanos": (MsgPack::Uint(500_000_000)) }));
+        assert_eq!(Duration::from_msgpack(msgpack!([2, 0])).unwrap(), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn errors () {
+        let error = <[u8; 2]>::from_msgpack(msgpack!([1, 2, 3])).unwrap_err();
+        assert_eq!(error.error.attempted, "fixed size array");
+        assert!(error.path.is_root());
+
+        let error = BTreeMap::<String, (bool, u8)>::from_msgpack(msgpack!({ "a": [true, 1], "b": [false, 256] })).unwrap_err();
+        assert_eq!(error.path, Path::root().key("b").index(1));
+        assert!(error.error.is_range_error());
+        assert_eq!(error.error.recover(), MsgPack::Int(256));
+
+        let error = HashMap::<u8, u8>::from_msgpack(msgpack!({ "a": 1 })).unwrap_err();
+        assert_eq!(error.to_string(), "MsgPack conversion error at /a: cannot use string as u8");
+
+        assert!(isize::from_msgpack(MsgPack::Uint(u64::MAX)).unwrap_err().error.is_range_error());
+        assert!(char::from_msgpack(msgpack!("ab")).is_err());
+        assert!(Duration::from_msgpack(msgpack!({ "secs": 1, "nanos": 1_000_000_000 })).is_err());
+        assert!(SystemTime::from_msgpack(MsgPack::Int(0)).is_err());
+        assert!(Option::<u8>::from_msgpack(MsgPack::Nil).unwrap().is_none());
+    }
+}
@@ -0,0 +1,171 @@
+//! UUIDs, stored as a 16 byte extension
+
+use std::convert::TryFrom;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use super::{MsgPack, Extension};
+use super::error::{ConversionError, TextError};
+
+/// A UUID, stored as an extension holding its 16 bytes
+/// 
+/// The bytes are in the usual RFC 4122 order, the same as in the text form,
+/// and the extension is always encoded as a fixext 16 (`0xd8`). The
+/// conversions use type id 2, the one Tarantool uses, and
+/// [UuidCodec](struct.UuidCodec.html) can handle any other.
+/// 
+/// UUIDs are written in the hyphenated lowercase form, and read from it or
+/// from 32 hex digits without hyphens, in either case.
+/// 
+///     use std::convert::TryFrom;
+///     use msgpack_simple::{MsgPack, Uuid};
+/// 
+///     let uuid: Uuid = "64d22e4d-ac92-4a23-899a-e59f34af5479".parse().unwrap();
+///     let encoded = MsgPack::from(uuid).encode();
+/// 
+///     assert_eq!(encoded[..4], [0xd8, 0x02, 0x64, 0xd2]);
+///     assert_eq!(encoded.len(), 18);
+/// 
+///     let decoded = Uuid::try_from(MsgPack::parse(&encoded).unwrap()).unwrap();
+///     assert_eq!(decoded.to_string(), "64d22e4d-ac92-4a23-899a-e59f34af5479");
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Uuid {
+    bytes: [u8; 16],
+}
+
+impl Uuid {
+    /// The extension type id used by the conversions
+    pub const TYPE_ID: i8 = 2;
+
+    /// Creates a UUID from its bytes
+    pub fn from_bytes (bytes: [u8; 16]) -> Uuid {
+        Uuid { bytes }
+    }
+
+    /// The bytes of the UUID
+    pub fn as_bytes (&self) -> &[u8; 16] {
+        &self.bytes
+    }
+
+    /// Reads a UUID from the bytes of an extension, returning `None` if there
+    /// aren't exactly 16 of them
+    pub fn from_slice (bytes: &[u8]) -> Option<Uuid> {
+        let mut result = [0; 16];
+        if bytes.len() != result.len() { return None }
+
+        result.copy_from_slice(bytes);
+        Some(Uuid { bytes: result })
+    }
+}
+
+impl From<Uuid> for Extension {
+    fn from (uuid: Uuid) -> Extension {
+        Extension { type_id: Uuid::TYPE_ID, value: uuid.bytes.to_vec() }
+    }
+}
+
+impl From<Uuid> for MsgPack {
+    fn from (uuid: Uuid) -> MsgPack {
+        MsgPack::Extension(uuid.into())
+    }
+}
+
+impl TryFrom<Extension> for Uuid {
+    type Error = ConversionError;
+
+    fn try_from (extension: Extension) -> Result<Uuid, ConversionError> {
+        let uuid = match extension.type_id {
+            Uuid::TYPE_ID => Uuid::from_slice(&extension.value),
+            _ => None
+        };
+
+        uuid.ok_or(ConversionError::type_error(MsgPack::Extension(extension), "uuid"))
+    }
+}
+
+impl TryFrom<MsgPack> for Uuid {
+    type Error = ConversionError;
+
+    fn try_from (value: MsgPack) -> Result<Uuid, ConversionError> {
+        match value {
+            MsgPack::Extension(extension) => Uuid::try_from(extension),
+            original => Err(ConversionError::type_error(original, "uuid"))
+        }
+    }
+}
+
+impl Display for Uuid {
+    fn fmt (&self, f: &mut Formatter) -> fmt::Result {
+        let bytes = &self.bytes;
+
+        write!(f, "{}-{}-{}-{}-{}",
+            hex::encode(&bytes[..4]),
+            hex::encode(&bytes[4..6]),
+            hex::encode(&bytes[6..8]),
+            hex::encode(&bytes[8..10]),
+            hex::encode(&bytes[10..])
+        )
+    }
+}
+
+impl FromStr for Uuid {
+    type Err = TextError;
+
+    fn from_str (text: &str) -> Result<Uuid, TextError> {
+        let error = || TextError { text: text.to_string(), attempted: "uuid" };
+
+        let digits = match text.len() {
+            32 => text.to_string(),
+            36 => {
+                let hyphens = [8, 13, 18, 23];
+                let valid = text.char_indices().all(|(index, c)| (c == '-') == hyphens.contains(&index));
+                if !valid { return Err(error()) }
+
+                text.replace('-', "")
+            },
+            _ => return Err(error())
+        };
+
+        let bytes = hex::decode(digits).map_err(|_| error())?;
+        Uuid::from_slice(&bytes).ok_or_else(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // in the MP_UUID layout of Tarantool
+    const TEXT: &str = "64d22e4d-ac92-4a23-899a-e59f34af5479";
+    const ENCODED: [u8; 18] = [
+        0xd8, 0x02,
+        0x64, 0xd2, 0x2e, 0x4d, 0xac, 0x92, 0x4a, 0x23,
+        0x89, 0x9a, 0xe5, 0x9f, 0x34, 0xaf, 0x54, 0x79
+    ];
+
+    #[test]
+    fn encoding () {
+        let uuid: Uuid = TEXT.parse().unwrap();
+        assert_eq!(MsgPack::from(uuid).encode(), ENCODED.to_vec());
+        assert_eq!(Uuid::try_from(MsgPack::parse(&ENCODED).unwrap()).unwrap(), uuid);
+
+        assert!(Uuid::try_from(Extension { type_id: 2, value: vec![0; 15] }).is_err());
+        assert!(Uuid::try_from(Extension { type_id: 3, value: vec![0; 16] }).is_err());
+        assert!(Uuid::try_from(MsgPack::String(TEXT.to_string())).is_err());
+    }
+
+    #[test]
+    fn text () {
+        let uuid: Uuid = TEXT.parse().unwrap();
+        assert_eq!(uuid.to_string(), TEXT);
+        assert_eq!("64D22E4DAC924A23899AE59F34AF5479".parse::<Uuid>().unwrap(), uuid);
+        assert_eq!(Uuid::from_bytes([0; 16]).to_string(), "00000000-0000-0000-0000-000000000000");
+
+        assert!("64d22e4d-ac92-4a23-899a-e59f34af547".parse::<Uuid>().is_err());
+        assert!("64d22e4dac92-4a23-899a-e59f-34af5479".parse::<Uuid>().is_err());
+        assert!("64d22e4d-ac92-4a23-899a-e59f34af547g".parse::<Uuid>().is_err());
+
+        let error = "nope".parse::<Uuid>().unwrap_err();
+        assert_eq!(error.to_string(), "MsgPack text error: cannot read \"nope\" as uuid");
+    }
+}
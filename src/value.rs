@@ -0,0 +1,621 @@
+//! Converting between serde types and MsgPack trees, behind the `serde`
+//! feature
+
+use std::convert::TryFrom;
+
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, Visitor};
+use serde::forward_to_deserialize_any;
+use serde::ser::{self, Serialize};
+
+use super::{MsgPack, MapElement, Extension};
+use super::error::SerdeError;
+use super::path::PathSegment;
+use super::serde_impls::{EXTENSION_TOKEN, extension_from_field};
+
+/// Serializes a value into a [MsgPack](enum.MsgPack.html) tree
+/// 
+/// The tree has the same shape as the data [to_vec](fn.to_vec.html) writes:
+/// structs become maps keyed by their field names, and enums their variant
+/// name, or a map from the name to the contents if the variant has any.
+/// Errors carry the path of the value that failed.
+/// 
+///     use msgpack_simple::{to_value, MsgPack};
+/// 
+///     #[derive(serde::Serialize)]
+///     struct Point { x: i32, y: u8 }
+/// 
+///     let value = to_value(&Point { x: -1, y: 2 }).unwrap();
+///     assert_eq!(value["x"], MsgPack::Int(-1));
+///     assert_eq!(value["y"], MsgPack::Uint(2));
+pub fn to_value <T: Serialize + ?Sized> (value: &T) -> Result<MsgPack, SerdeError> {
+    value.serialize(ValueSerializer)
+}
+
+/// Deserializes a value from a [MsgPack](enum.MsgPack.html) tree
+/// 
+/// This is useful for values that are only partly known up front, where one
+/// part of a message decides how to read the rest. Errors carry the path of
+/// the value that failed.
+/// 
+///     use msgpack_simple::{from_value, msgpack};
+/// 
+///     #[derive(serde::Deserialize, Debug)]
+///     struct Login { user: String, attempts: u8 }
+/// 
+///     let message = msgpack!({ "kind": "login", "body": { "user": "ferris", "attempts": 3 } });
+///     if message["kind"] == msgpack!("login") {
+///         let login: Login = from_value(message["body"].clone()).unwrap();
+///         assert_eq!(login.user, "ferris");
+///     }
+/// 
+///     let error = from_value::<Vec<Login>>(msgpack!([{ "user": "ferris", "attempts": 300 }])).unwrap_err();
+///     assert_eq!(error.path.to_string(), "/0/attempts");
+pub fn from_value <T: DeserializeOwned> (value: MsgPack) -> Result<T, SerdeError> {
+    T::deserialize(ValueDeserializer(value))
+}
+
+// an enum variant with contents, as a map with a single entry
+fn variant (name: &str, value: MsgPack) -> MsgPack {
+    MsgPack::Map(vec![MapElement { key: MsgPack::String(name.to_string()), value }])
+}
+
+fn variant_error (name: &str) -> impl Fn(SerdeError) -> SerdeError + '_ {
+    move |error| error.within(PathSegment::Key(MsgPack::String(name.to_string())))
+}
+
+struct ValueSerializer;
+
+impl ser::Serializer for ValueSerializer {
+    type Ok = MsgPack;
+    type Error = SerdeError;
+
+    type SerializeSeq = SeqCollector;
+    type SerializeTuple = SeqCollector;
+    type SerializeTupleStruct = SeqCollector;
+    type SerializeTupleVariant = SeqCollector;
+    type SerializeMap = MapCollector;
+    type SerializeStruct = MapCollector;
+    type SerializeStructVariant = MapCollector;
+
+    fn is_human_readable (&self) -> bool {
+        false
+    }
+
+    fn serialize_bool (self, value: bool) -> Result<MsgPack, SerdeError> {
+        Ok(MsgPack::Boolean(value))
+    }
+
+    fn serialize_i8 (self, value: i8) -> Result<MsgPack, SerdeError> {
+        Ok(MsgPack::from(value))
+    }
+
+    fn serialize_i16 (self, value: i16) -> Result<MsgPack, SerdeError> {
+        Ok(MsgPack::from(value))
+    }
+
+    fn serialize_i32 (self, value: i32) -> Result<MsgPack, SerdeError> {
+        Ok(MsgPack::from(value))
+    }
+
+    fn serialize_i64 (self, value: i64) -> Result<MsgPack, SerdeError> {
+        Ok(MsgPack::from(value))
+    }
+
+    fn serialize_i128 (self, value: i128) -> Result<MsgPack, SerdeError> {
+        Ok(MsgPack::from(value))
+    }
+
+    fn serialize_u8 (self, value: u8) -> Result<MsgPack, SerdeError> {
+        Ok(MsgPack::from(value))
+    }
+
+    fn serialize_u16 (self, value: u16) -> Result<MsgPack, SerdeError> {
+        Ok(MsgPack::from(value))
+    }
+
+    fn serialize_u32 (self, value: u32) -> Result<MsgPack, SerdeError> {
+        Ok(MsgPack::from(value))
+    }
+
+    fn serialize_u64 (self, value: u64) -> Result<MsgPack, SerdeError> {
+        Ok(MsgPack::from(value))
+    }
+
+    fn serialize_u128 (self, value: u128) -> Result<MsgPack, SerdeError> {
+        Ok(MsgPack::from(value))
+    }
+
+    fn serialize_f32 (self, value: f32) -> Result<MsgPack, SerdeError> {
+        Ok(MsgPack::from(value))
+    }
+
+    fn serialize_f64 (self, value: f64) -> Result<MsgPack, SerdeError> {
+        Ok(MsgPack::from(value))
+    }
+
+    fn serialize_char (self, value: char) -> Result<MsgPack, SerdeError> {
+        Ok(MsgPack::String(value.to_string()))
+    }
+
+    fn serialize_str (self, value: &str) -> Result<MsgPack, SerdeError> {
+        Ok(MsgPack::String(value.to_string()))
+    }
+
+    fn serialize_bytes (self, value: &[u8]) -> Result<MsgPack, SerdeError> {
+        Ok(MsgPack::Binary(value.to_vec()))
+    }
+
+    fn serialize_none (self) -> Result<MsgPack, SerdeError> {
+        Ok(MsgPack::Nil)
+    }
+
+    fn serialize_some <T: Serialize + ?Sized> (self, value: &T) -> Result<MsgPack, SerdeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit (self) -> Result<MsgPack, SerdeError> {
+        Ok(MsgPack::Nil)
+    }
+
+    fn serialize_unit_struct (self, _name: &'static str) -> Result<MsgPack, SerdeError> {
+        Ok(MsgPack::Nil)
+    }
+
+    fn serialize_unit_variant (self, _name: &'static str, _index: u32, variant: &'static str) -> Result<MsgPack, SerdeError> {
+        Ok(MsgPack::String(variant.to_string()))
+    }
+
+    fn serialize_newtype_struct <T: Serialize + ?Sized> (self, _name: &'static str, value: &T) -> Result<MsgPack, SerdeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant <T: Serialize + ?Sized> (self, _name: &'static str, _index: u32, name: &'static str, value: &T) -> Result<MsgPack, SerdeError> {
+        let value = to_value(value).map_err(variant_error(name))?;
+        Ok(variant(name, value))
+    }
+
+    fn serialize_seq (self, length: Option<usize>) -> Result<SeqCollector, SerdeError> {
+        Ok(SeqCollector { items: Vec::with_capacity(length.unwrap_or(0)), variant: None })
+    }
+
+    fn serialize_tuple (self, length: usize) -> Result<SeqCollector, SerdeError> {
+        self.serialize_seq(Some(length))
+    }
+
+    fn serialize_tuple_struct (self, _name: &'static str, length: usize) -> Result<SeqCollector, SerdeError> {
+        self.serialize_seq(Some(length))
+    }
+
+    fn serialize_tuple_variant (self, _name: &'static str, _index: u32, name: &'static str, length: usize) -> Result<SeqCollector, SerdeError> {
+        Ok(SeqCollector { items: Vec::with_capacity(length), variant: Some(name) })
+    }
+
+    fn serialize_map (self, length: Option<usize>) -> Result<MapCollector, SerdeError> {
+        Ok(MapCollector { elements: Vec::with_capacity(length.unwrap_or(0)), key: None, variant: None, extension: false })
+    }
+
+    fn serialize_struct (self, name: &'static str, length: usize) -> Result<MapCollector, SerdeError> {
+        let extension = name == EXTENSION_TOKEN;
+        Ok(MapCollector { elements: Vec::with_capacity(length), key: None, variant: None, extension })
+    }
+
+    fn serialize_struct_variant (self, _name: &'static str, _index: u32, name: &'static str, length: usize) -> Result<MapCollector, SerdeError> {
+        Ok(MapCollector { elements: Vec::with_capacity(length), key: None, variant: Some(name), extension: false })
+    }
+}
+
+struct SeqCollector {
+    items: Vec<MsgPack>,
+    variant: Option<&'static str>,
+}
+
+impl SeqCollector {
+    fn push <T: Serialize + ?Sized> (&mut self, value: &T) -> Result<(), SerdeError> {
+        let index = self.items.len();
+        let value = to_value(value).map_err(|error| error.within(PathSegment::Index(index)))?;
+
+        self.items.push(value);
+        Ok(())
+    }
+
+    fn finish (self) -> Result<MsgPack, SerdeError> {
+        match self.variant {
+            Some(name) => Ok(variant(name, MsgPack::Array(self.items))),
+            None => Ok(MsgPack::Array(self.items))
+        }
+    }
+}
+
+impl ser::SerializeSeq for SeqCollector {
+    type Ok = MsgPack;
+    type Error = SerdeError;
+
+    fn serialize_element <T: Serialize + ?Sized> (&mut self, value: &T) -> Result<(), SerdeError> {
+        self.push(value)
+    }
+
+    fn end (self) -> Result<MsgPack, SerdeError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for SeqCollector {
+    type Ok = MsgPack;
+    type Error = SerdeError;
+
+    fn serialize_element <T: Serialize + ?Sized> (&mut self, value: &T) -> Result<(), SerdeError> {
+        self.push(value)
+    }
+
+    fn end (self) -> Result<MsgPack, SerdeError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqCollector {
+    type Ok = MsgPack;
+    type Error = SerdeError;
+
+    fn serialize_field <T: Serialize + ?Sized> (&mut self, value: &T) -> Result<(), SerdeError> {
+        self.push(value)
+    }
+
+    fn end (self) -> Result<MsgPack, SerdeError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for SeqCollector {
+    type Ok = MsgPack;
+    type Error = SerdeError;
+
+    fn serialize_field <T: Serialize + ?Sized> (&mut self, value: &T) -> Result<(), SerdeError> {
+        let name = self.variant.unwrap_or_default();
+        self.push(value).map_err(variant_error(name))
+    }
+
+    fn end (self) -> Result<MsgPack, SerdeError> {
+        self.finish()
+    }
+}
+
+struct MapCollector {
+    elements: Vec<MapElement>,
+    key: Option<MsgPack>,
+    variant: Option<&'static str>,
+    // a struct named after the token, to be turned into an extension
+    extension: bool,
+}
+
+impl MapCollector {
+    fn insert <T: Serialize + ?Sized> (&mut self, key: MsgPack, value: &T) -> Result<(), SerdeError> {
+        let value = to_value(value).map_err(|error| error.within(PathSegment::Key(key.clone())))?;
+
+        self.elements.push(MapElement { key, value });
+        Ok(())
+    }
+
+    fn field <T: Serialize + ?Sized> (&mut self, key: &'static str, value: &T) -> Result<(), SerdeError> {
+        let result = self.insert(MsgPack::String(key.to_string()), value);
+
+        match self.variant {
+            Some(name) => result.map_err(variant_error(name)),
+            None => result
+        }
+    }
+
+    fn finish (mut self) -> Result<MsgPack, SerdeError> {
+        if self.extension {
+            let field = self.elements.pop().map(|element| element.value);
+            return extension_from_field(field).map(MsgPack::Extension)
+        }
+
+        match self.variant {
+            Some(name) => Ok(variant(name, MsgPack::Map(self.elements))),
+            None => Ok(MsgPack::Map(self.elements))
+        }
+    }
+}
+
+impl ser::SerializeMap for MapCollector {
+    type Ok = MsgPack;
+    type Error = SerdeError;
+
+    fn serialize_key <T: Serialize + ?Sized> (&mut self, key: &T) -> Result<(), SerdeError> {
+        self.key = Some(to_value(key)?);
+        Ok(())
+    }
+
+    fn serialize_value <T: Serialize + ?Sized> (&mut self, value: &T) -> Result<(), SerdeError> {
+        let key = self.key.take().ok_or_else(|| ser::Error::custom("map value without a key"))?;
+        self.insert(key, value)
+    }
+
+    fn end (self) -> Result<MsgPack, SerdeError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStruct for MapCollector {
+    type Ok = MsgPack;
+    type Error = SerdeError;
+
+    fn serialize_field <T: Serialize + ?Sized> (&mut self, key: &'static str, value: &T) -> Result<(), SerdeError> {
+        self.field(key, value)
+    }
+
+    fn end (self) -> Result<MsgPack, SerdeError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for MapCollector {
+    type Ok = MsgPack;
+    type Error = SerdeError;
+
+    fn serialize_field <T: Serialize + ?Sized> (&mut self, key: &'static str, value: &T) -> Result<(), SerdeError> {
+        self.field(key, value)
+    }
+
+    fn end (self) -> Result<MsgPack, SerdeError> {
+        self.finish()
+    }
+}
+
+struct ValueDeserializer(MsgPack);
+
+impl <'de> de::Deserializer<'de> for ValueDeserializer {
+    type Error = SerdeError;
+
+    fn is_human_readable (&self) -> bool {
+        false
+    }
+
+    fn deserialize_any <V: Visitor<'de>> (self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.0 {
+            MsgPack::Nil => visitor.visit_unit(),
+            MsgPack::Boolean(value) => visitor.visit_bool(value),
+            MsgPack::Int(value) => visitor.visit_i64(value),
+            MsgPack::Uint(value) => visitor.visit_u64(value),
+            MsgPack::Float(value) => visitor.visit_f64(value),
+            MsgPack::String(value) => visitor.visit_string(value),
+            MsgPack::Binary(value) => visitor.visit_byte_buf(value),
+            MsgPack::Array(value) => {
+                let mut items = Items { items: value.into_iter(), index: 0 };
+                let result = visitor.visit_seq(&mut items)?;
+                items.end()?;
+                Ok(result)
+            },
+            MsgPack::Map(value) => {
+                let mut elements = Elements { elements: value.into_iter(), value: None };
+                let result = visitor.visit_map(&mut elements)?;
+                elements.end()?;
+                Ok(result)
+            },
+            MsgPack::Extension(Extension { type_id, value }) => {
+                // given to visitors as it's serialized, a map from the token
+                // to a tuple of the type id and the data
+                let parts = MsgPack::Array(vec![MsgPack::from(type_id), MsgPack::Binary(value)]);
+                ValueDeserializer(variant(EXTENSION_TOKEN, parts)).deserialize_any(visitor)
+            }
+        }
+    }
+
+    fn deserialize_i128 <V: Visitor<'de>> (self, visitor: V) -> Result<V::Value, SerdeError> {
+        let value = i128::try_from(self.0).map_err(de::Error::custom)?;
+        visitor.visit_i128(value)
+    }
+
+    fn deserialize_u128 <V: Visitor<'de>> (self, visitor: V) -> Result<V::Value, SerdeError> {
+        let value = u128::try_from(self.0).map_err(de::Error::custom)?;
+        visitor.visit_u128(value)
+    }
+
+    fn deserialize_option <V: Visitor<'de>> (self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.0 {
+            MsgPack::Nil => visitor.visit_none(),
+            value => visitor.visit_some(ValueDeserializer(value))
+        }
+    }
+
+    fn deserialize_newtype_struct <V: Visitor<'de>> (self, _name: &'static str, visitor: V) -> Result<V::Value, SerdeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum <V: Visitor<'de>> (self, _name: &'static str, _variants: &'static [&'static str], visitor: V) -> Result<V::Value, SerdeError> {
+        match self.0 {
+            // a unit variant is just the name
+            MsgPack::String(name) => visitor.visit_enum(name.into_deserializer()),
+            // any other is a map from the name to the contents
+            MsgPack::Map(mut map) if map.len() == 1 => {
+                let MapElement { key, value } = map.remove(0);
+                visitor.visit_enum(Variant { key, value })
+            },
+            _ => Err(de::Error::custom("expected an enum variant name or a map with a single entry"))
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes
+        byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+struct Items {
+    items: std::vec::IntoIter<MsgPack>,
+    index: usize,
+}
+
+impl Items {
+    // the visitor has to read every item
+    fn end (&self) -> Result<(), SerdeError> {
+        match self.items.len() {
+            0 => Ok(()),
+            _ => Err(de::Error::custom("more items than expected"))
+        }
+    }
+}
+
+impl <'de> de::SeqAccess<'de> for Items {
+    type Error = SerdeError;
+
+    fn next_element_seed <T: DeserializeSeed<'de>> (&mut self, seed: T) -> Result<Option<T::Value>, SerdeError> {
+        let item = match self.items.next() {
+            Some(item) => item,
+            None => return Ok(None)
+        };
+
+        let index = self.index;
+        self.index += 1;
+
+        seed.deserialize(ValueDeserializer(item)).map(Some).map_err(|error| error.within(PathSegment::Index(index)))
+    }
+
+    fn size_hint (&self) -> Option<usize> {
+        Some(self.items.len())
+    }
+}
+
+struct Elements {
+    elements: std::vec::IntoIter<MapElement>,
+    // the entry whose key was read last
+    value: Option<MapElement>,
+}
+
+impl Elements {
+    // the visitor has to read every entry
+    fn end (&self) -> Result<(), SerdeError> {
+        match self.elements.len() {
+            0 => Ok(()),
+            _ => Err(de::Error::custom("more entries than expected"))
+        }
+    }
+}
+
+impl <'de> de::MapAccess<'de> for Elements {
+    type Error = SerdeError;
+
+    fn next_key_seed <K: DeserializeSeed<'de>> (&mut self, seed: K) -> Result<Option<K::Value>, SerdeError> {
+        let element = match self.elements.next() {
+            Some(element) => element,
+            None => return Ok(None)
+        };
+
+        let key = seed.deserialize(ValueDeserializer(element.key.clone()))
+            .map_err(|error| error.within(PathSegment::Key(element.key.clone())))?;
+
+        self.value = Some(element);
+        Ok(Some(key))
+    }
+
+    fn next_value_seed <V: DeserializeSeed<'de>> (&mut self, seed: V) -> Result<V::Value, SerdeError> {
+        let MapElement { key, value } = self.value.take().ok_or_else(|| de::Error::custom("map value without a key"))?;
+        seed.deserialize(ValueDeserializer(value)).map_err(|error| error.within(PathSegment::Key(key)))
+    }
+
+    fn size_hint (&self) -> Option<usize> {
+        Some(self.elements.len())
+    }
+}
+
+// an enum variant with contents, from a map with a single entry
+struct Variant {
+    key: MsgPack,
+    value: MsgPack,
+}
+
+impl <'de> de::EnumAccess<'de> for Variant {
+    type Error = SerdeError;
+    type Variant = Self;
+
+    fn variant_seed <V: DeserializeSeed<'de>> (self, seed: V) -> Result<(V::Value, Self), SerdeError> {
+        let variant = seed.deserialize(ValueDeserializer(self.key.clone()))?;
+        Ok((variant, self))
+    }
+}
+
+impl Variant {
+    fn within (&self) -> impl Fn(SerdeError) -> SerdeError {
+        let key = self.key.clone();
+        move |error| error.within(PathSegment::Key(key.clone()))
+    }
+}
+
+impl <'de> de::VariantAccess<'de> for Variant {
+    type Error = SerdeError;
+
+    fn unit_variant (self) -> Result<(), SerdeError> {
+        let within = self.within();
+        de::Deserialize::deserialize(ValueDeserializer(self.value)).map_err(within)
+    }
+
+    fn newtype_variant_seed <T: DeserializeSeed<'de>> (self, seed: T) -> Result<T::Value, SerdeError> {
+        let within = self.within();
+        seed.deserialize(ValueDeserializer(self.value)).map_err(within)
+    }
+
+    fn tuple_variant <V: Visitor<'de>> (self, _length: usize, visitor: V) -> Result<V::Value, SerdeError> {
+        let within = self.within();
+        de::Deserializer::deserialize_any(ValueDeserializer(self.value), visitor).map_err(within)
+    }
+
+    fn struct_variant <V: Visitor<'de>> (self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value, SerdeError> {
+        let within = self.within();
+        de::Deserializer::deserialize_any(ValueDeserializer(self.value), visitor).map_err(within)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Serialize;
+    use super::super::{Path, to_vec};
+    use super::super::serde_impls::fixtures::{Shape, Drawing, drawing};
+
+    #[test]
+    fn round_trip () {
+        let value = to_value(&drawing()).unwrap();
+        assert_eq!(value.encode(), to_vec(&drawing()).unwrap());
+        assert_eq!(value["shapes"][3], msgpack!({ "Rect": { "width": (MsgPack::Uint(2)), "height": (MsgPack::Uint(3)) } }));
+        assert_eq!(from_value::<Drawing>(value).unwrap(), drawing());
+
+        let value = msgpack!({
+            "numbers": [-1, (MsgPack::Uint(7)), 1.5, nil, false],
+            "raw": bin[1, 2, 3],
+            "ext": ext(-1, [0, 0, 0, 1]),
+            5: { "nested": "text" }
+        });
+        assert_eq!(to_value(&value).unwrap(), value);
+        assert_eq!(from_value::<MsgPack>(value.clone()).unwrap(), value);
+    }
+
+    #[test]
+    fn error_paths () {
+        let mut value = to_value(&drawing()).unwrap();
+        value["shapes"][2] = msgpack!({ "Line": [1, 300] });
+
+        let error = from_value::<Drawing>(value).unwrap_err();
+        assert_eq!(error.path, Path::root().key("shapes").index(2).key("Line").index(1));
+        assert_eq!(error.byte, None);
+        assert!(error.to_string().starts_with("MsgPack serde error at /shapes/2/Line/1: invalid value"));
+
+        let error = from_value::<Shape>(msgpack!({ "Rect": { "width": 1 } })).unwrap_err();
+        assert_eq!(error.path, Path::root().key("Rect"));
+        assert!(error.message.contains("height"));
+
+        let error = from_value::<(u8, u8)>(msgpack!([1, 2, 3])).unwrap_err();
+        assert!(error.path.is_root());
+
+        struct Failing;
+        impl Serialize for Failing {
+            fn serialize <S: ser::Serializer> (&self, _serializer: S) -> Result<S::Ok, S::Error> {
+                Err(ser::Error::custom("cannot serialize"))
+            }
+        }
+
+        let mut map = std::collections::BTreeMap::new();
+        map.insert("inner", vec![None, Some(Failing)]);
+        assert_eq!(to_value(&map).unwrap_err().path, Path::root().key("inner").index(1));
+    }
+}
@@ -0,0 +1,246 @@
+//! Walking and rewriting MsgPack trees
+
+use super::{MsgPack, MapElement};
+use super::path::{Path, PathSegment};
+
+/// Receives every node of a MsgPack tree from
+/// [MsgPack::walk](enum.MsgPack.html#method.walk)
+/// 
+/// Nodes are visited parent first, with the path leading to them from the
+/// root, and their depth, which is 0 for the root. Map keys are part of the
+/// path, but are not visited as nodes themselves.
+/// 
+/// Closures taking the same arguments implement this trait, so simple passes
+/// don't need a type of their own.
+/// 
+///     use msgpack_simple::{msgpack, MsgPack, Path, Visitor};
+/// 
+///     struct Strings(Vec<String>);
+/// 
+///     impl Visitor for Strings {
+///         fn visit (&mut self, path: &Path, _depth: usize, node: &MsgPack) {
+///             if node.is_string() { self.0.push(path.to_string()) }
+///         }
+///     }
+/// 
+///     let message = msgpack!({ "name": "Ferris", "tags": ["crab", 42] });
+///     let mut strings = Strings(vec![]);
+///     message.walk(&mut strings);
+/// 
+///     assert_eq!(strings.0, vec!["/name", "/tags/0"]);
+pub trait Visitor {
+    /// Called once for every node
+    fn visit (&mut self, path: &Path, depth: usize, node: &MsgPack);
+}
+
+impl <F: FnMut(&Path, usize, &MsgPack)> Visitor for F {
+    fn visit (&mut self, path: &Path, depth: usize, node: &MsgPack) {
+        self(path, depth, node)
+    }
+}
+
+/// Receives every node of a MsgPack tree for writing from
+/// [MsgPack::walk_mut](enum.MsgPack.html#method.walk_mut)
+/// 
+/// Nodes are visited parent first, so if a node is replaced, the children of
+/// the new value are visited next.
+pub trait VisitorMut {
+    /// Called once for every node
+    fn visit_mut (&mut self, path: &Path, depth: usize, node: &mut MsgPack);
+}
+
+impl <F: FnMut(&Path, usize, &mut MsgPack)> VisitorMut for F {
+    fn visit_mut (&mut self, path: &Path, depth: usize, node: &mut MsgPack) {
+        self(path, depth, node)
+    }
+}
+
+fn walk_node <V: Visitor + ?Sized> (node: &MsgPack, path: &mut Path, visitor: &mut V) {
+    let depth = path.segments().len();
+    visitor.visit(path, depth, node);
+
+    match node {
+        MsgPack::Array(array) => {
+            for (index, item) in array.iter().enumerate() {
+                path.push(PathSegment::Index(index));
+                walk_node(item, path, visitor);
+                path.pop();
+            }
+        },
+        MsgPack::Map(map) => {
+            for element in map {
+                path.push(PathSegment::Key(element.key.clone()));
+                walk_node(&element.value, path, visitor);
+                path.pop();
+            }
+        },
+        _ => {}
+    }
+}
+
+fn walk_node_mut <V: VisitorMut + ?Sized> (node: &mut MsgPack, path: &mut Path, visitor: &mut V) {
+    let depth = path.segments().len();
+    visitor.visit_mut(path, depth, node);
+
+    match node {
+        MsgPack::Array(array) => {
+            for (index, item) in array.iter_mut().enumerate() {
+                path.push(PathSegment::Index(index));
+                walk_node_mut(item, path, visitor);
+                path.pop();
+            }
+        },
+        MsgPack::Map(map) => {
+            for element in map {
+                path.push(PathSegment::Key(element.key.clone()));
+                walk_node_mut(&mut element.value, path, visitor);
+                path.pop();
+            }
+        },
+        _ => {}
+    }
+}
+
+fn transform_node <F> (node: MsgPack, path: &mut Path, transform: &mut F) -> Option<MsgPack>
+where F: FnMut(&Path, MsgPack) -> Option<MsgPack> {
+    let node = match node {
+        MsgPack::Array(array) => {
+            let mut result = Vec::with_capacity(array.len());
+
+            for (index, item) in array.into_iter().enumerate() {
+                path.push(PathSegment::Index(index));
+                if let Some(item) = transform_node(item, path, transform) { result.push(item) }
+                path.pop();
+            }
+
+            MsgPack::Array(result)
+        },
+        MsgPack::Map(map) => {
+            let mut result = Vec::with_capacity(map.len());
+
+            for MapElement { key, value } in map {
+                path.push(PathSegment::Key(key));
+                let value = transform_node(value, path, transform);
+                let key = match path.pop() {
+                    Some(PathSegment::Key(key)) => key,
+                    _ => unreachable!()
+                };
+
+                if let Some(value) = value { result.push(MapElement { key, value }) }
+            }
+
+            MsgPack::Map(result)
+        },
+        other => other
+    };
+
+    transform(path, node)
+}
+
+impl MsgPack {
+    /// Visits every node of the tree, parents before their children, see
+    /// [Visitor](trait.Visitor.html)
+    /// 
+    ///     use msgpack_simple::{msgpack, MsgPack, Path};
+    /// 
+    ///     let message = msgpack!({ "a": [1, [2]] });
+    ///     let mut deepest = 0;
+    ///     message.walk(&mut |_: &Path, depth: usize, _: &MsgPack| deepest = deepest.max(depth));
+    /// 
+    ///     assert_eq!(deepest, 3);
+    pub fn walk <V: Visitor + ?Sized> (&self, visitor: &mut V) {
+        walk_node(self, &mut Path::root(), visitor)
+    }
+
+    /// Visits every node of the tree for writing, parents before their
+    /// children, see [VisitorMut](trait.VisitorMut.html)
+    /// 
+    ///     use msgpack_simple::{msgpack, MsgPack, Path};
+    /// 
+    ///     let mut message = msgpack!({ "a": [1, "two"], "b": 3 });
+    ///     message.walk_mut(&mut |_: &Path, _: usize, node: &mut MsgPack| {
+    ///         if let MsgPack::Int(value) = node { *value *= 10 }
+    ///     });
+    /// 
+    ///     assert_eq!(message, msgpack!({ "a": [10, "two"], "b": 30 }));
+    pub fn walk_mut <V: VisitorMut + ?Sized> (&mut self, visitor: &mut V) {
+        walk_node_mut(self, &mut Path::root(), visitor)
+    }
+
+    /// Rewrites the tree bottom-up, calling the function with every node after
+    /// its children have been rewritten
+    /// 
+    /// The function returns the new value of the node, or `None` to remove it
+    /// from its parent array or map. Paths refer to the original positions, so
+    /// array indexes don't shift when earlier elements are removed. If the
+    /// root itself is removed, the result is Nil.
+    /// 
+    ///     use msgpack_simple::{msgpack, MsgPack};
+    /// 
+    ///     let message = msgpack!({ "name": "Ferris", "email": nil, "key": bin[0xca, 0xfe] });
+    ///     let cleaned = message.transform(|_, node| match node {
+    ///         MsgPack::Nil => None,
+    ///         MsgPack::Binary(bytes) => Some(MsgPack::String(hex::encode(bytes))),
+    ///         other => Some(other)
+    ///     });
+    /// 
+    ///     assert_eq!(cleaned, msgpack!({ "name": "Ferris", "key": "cafe" }));
+    pub fn transform <F> (self, mut transform: F) -> MsgPack
+    where F: FnMut(&Path, MsgPack) -> Option<MsgPack> {
+        transform_node(self, &mut Path::root(), &mut transform).unwrap_or(MsgPack::Nil)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample () -> MsgPack {
+        MsgPack::Map(vec![
+            MapElement { key: MsgPack::from("a"), value: MsgPack::Array(vec![MsgPack::Nil, MsgPack::Int(1)]) },
+            MapElement { key: MsgPack::Int(7), value: MsgPack::Boolean(true) },
+        ])
+    }
+
+    #[test]
+    fn walking () {
+        let mut visited = vec![];
+        sample().walk(&mut |path: &Path, depth: usize, node: &MsgPack| visited.push((path.to_string(), depth, node.clone())));
+
+        assert_eq!(visited, vec![
+            ("".to_string(), 0, sample()),
+            ("/a".to_string(), 1, MsgPack::Array(vec![MsgPack::Nil, MsgPack::Int(1)])),
+            ("/a/0".to_string(), 2, MsgPack::Nil),
+            ("/a/1".to_string(), 2, MsgPack::Int(1)),
+            ("/7".to_string(), 1, MsgPack::Boolean(true)),
+        ]);
+
+        let mut message = sample();
+        message.walk_mut(&mut |_: &Path, depth: usize, node: &mut MsgPack| {
+            if node.is_nil() { *node = MsgPack::Array(vec![MsgPack::Uint(depth as u64)]) }
+        });
+
+        assert_eq!(message.pointer("/a/0/0"), Some(&MsgPack::Uint(2)));
+    }
+
+    #[test]
+    fn transforming () {
+        let mut order = vec![];
+        let result = sample().transform(|path, node| {
+            order.push(path.to_string());
+            match node {
+                MsgPack::Nil => None,
+                MsgPack::Int(value) => Some(MsgPack::Int(value + 1)),
+                other => Some(other)
+            }
+        });
+
+        assert_eq!(order, vec!["/a/0", "/a/1", "/a", "/7", ""]);
+        assert_eq!(result, MsgPack::Map(vec![
+            MapElement { key: MsgPack::from("a"), value: MsgPack::Array(vec![MsgPack::Int(2)]) },
+            MapElement { key: MsgPack::Int(7), value: MsgPack::Boolean(true) },
+        ]));
+
+        assert_eq!(sample().transform(|_, _| None), MsgPack::Nil);
+    }
+}